@@ -1,84 +1,1733 @@
-use std::sync::Arc;
-use crate::{HitRecord, Ray, colors::Color, vec_math::{Vec3, dot, reflect, refract, unit_vector}};
+use std::{f64::consts::PI, ops, sync::Arc};
+use crate::{HitRecord, Ray, colors::Color, measured::MeasuredBrdf, ray_math::RayDifferentials, sampling, spectral::{self, Dispersion}, textures::Texture, microfacet::{self, Charlie, ComplexIor, Gtr1, RoughConductor, RoughDielectric, ThinFilm, TrowbridgeReitz, fresnel_dielectric, schlick_weight}, vec_math::{Onb, Vec3, cross, dot, lerp, reflect, refract, unit_vector}};
 
 /// Represents the effects of a reflections: A reflected ray and some amount of light attenuation.
+///
+/// The attenuation is not limited to 1, importance sampled materials weigh rarely sampled directions more.
 pub struct Reflection {
     pub reflected: Ray,
-    pub attenuation: Color,
+    pub attenuation: Vec3,
 }
 
 /// Type of a shader or as the technical term goes, a BRDF.
-pub type BRDF = Arc<dyn Fn(Ray, &HitRecord) -> Option<Reflection> + Send + Sync>; 
+pub type BRDF = Arc<dyn Material>;
+
+/// Properties of a material that tell how it scatters light, flags can be combined with `|`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct MaterialFlags(u8);
+
+impl MaterialFlags {
+    /// The material does not scatter light, it only absorbs or emits it.
+    pub const NONE: Self = Self(0);
+    /// Scatters light over a wide range of directions, like matte surfaces and volumes.
+    pub const DIFFUSE: Self = Self(1);
+    /// Scatters light in a lobe around the mirror direction, like rough metals.
+    pub const GLOSSY: Self = Self(1 << 1);
+    /// Scatters light in a single direction, such materials can not be evaluated and always return zero from `eval` and `pdf`.
+    pub const SPECULAR: Self = Self(1 << 2);
+    /// Lets light pass through to the other side of the surface.
+    pub const TRANSMISSIVE: Self = Self(1 << 3);
+
+    /// Returns true if all flags in `other` are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for MaterialFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Describes how light scatters at a hit.
+///
+/// All directions point away from the hit point, `incoming` is the ray that hit the surface.
+/// Light flows from the scattered direction towards the origin of `incoming`.
+pub trait Material: Send + Sync {
+    /// Samples a direction to continue the path in. The attenuation is the BRDF times the cosine of the
+    /// scattered direction, divided by the probability density of sampling it.
+    /// Returns `None` if the light is absorbed.
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection>;
+
+    /// Evaluates the BRDF times the cosine between `direction` and the normal.
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3;
+
+    /// Probability density per solid angle of `sample` returning `direction`.
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64;
+
+    /// Light emitted by the material towards the origin of `incoming`, not limited to 1.
+    fn emitted(&self, _incoming: Ray, _hit: &HitRecord) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Fraction of the surface that is there in [0, 1], shapes randomly skip hits on partially transparent parts
+    /// so the ray passes straight through. Defaults to fully opaque.
+    fn opacity(&self, _incoming: Ray, _hit: &HitRecord) -> f64 {
+        1.0
+    }
+
+    /// How the material scatters light.
+    fn flags(&self) -> MaterialFlags;
+}
 
 /// For creating materials with the lambertian diffuse lighting model, for use with perfectly diffuse objects.
 #[must_use]
-pub fn make_lambertian_diffuse_brdf(albedo: Color) -> BRDF {
-    let brdf = move |_incoming: Ray, hit: &HitRecord| {
+pub fn make_lambertian_diffuse_brdf<T: Texture + 'static>(albedo: T) -> BRDF {
+    Arc::new(Lambertian { albedo })
+}
+
+struct Lambertian<T: Texture> {
+    albedo: T,
+}
+
+impl<T: Texture> Material for Lambertian<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let albedo = texture_at(&self.albedo, incoming, hit);
         // Dont send out a ray if the ray is fully absorbed.
         if albedo == Color::new(0.0, 0.0, 0.0) {
             return None;
         }
 
-        let mut scatter_direction = hit.normal + Vec3::random_unit_vector();
-        // Handle edge case where scatter direction is almost zero which may cause floating point precision issues.
-        if scatter_direction.near_zero() {
-            scatter_direction = hit.normal;
-        }
-
-        let reflected = Ray::new(hit.point, scatter_direction);
+        let scatter_direction = sampling::cosine_hemisphere((rand::random(), rand::random()));
+        let reflected = hit.spawn_ray(Onb::from_normal(hit.normal).to_world(scatter_direction));
         
-        let attenuation = albedo;
+        let attenuation = albedo.into();
         Some(
             Reflection {
                 reflected,
                 attenuation,
             }
         )
-    };
-    Arc::new(brdf)
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let albedo: Vec3 = texture_at(&self.albedo, incoming, hit).into();
+        albedo * self.pdf(incoming, hit, direction)
+    }
+
+    fn pdf(&self, _incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        sampling::cosine_hemisphere_pdf(dot(unit_vector(direction), hit.normal))
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::DIFFUSE
+    }
+}
+
+/// For creating materials that emit light, light is only emitted from the front of the surface.
+///
+/// The emitted light is the color `emission` times `strength`, so lights can be brighter than white.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color};
+/// let lamp = brdfs::make_diffuse_light_brdf(Color::new(1.0, 0.9, 0.7), 4.0);
+/// ```
+#[must_use]
+pub fn make_diffuse_light_brdf<T: Texture + 'static>(emission: T, strength: f64) -> BRDF {
+    Arc::new(DiffuseLight { emission, strength })
+}
+
+struct DiffuseLight<T: Texture> {
+    emission: T,
+    strength: f64,
+}
+
+impl<T: Texture> Material for DiffuseLight<T> {
+    fn sample(&self, _incoming: Ray, _hit: &HitRecord) -> Option<Reflection> {
+        None
+    }
+
+    fn eval(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn pdf(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, incoming: Ray, hit: &HitRecord) -> Vec3 {
+        if hit.front_face {self.strength * Vec3::from(texture_at(&self.emission, incoming, hit))} else {Vec3::default()}
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::NONE
+    }
 }
 
 /// For creating materials with the reflection characteristics of a metal.
 #[must_use]
-pub fn make_metal_brdf(albedo: Color) -> BRDF {
-    let brdf = move |incoming: Ray, hit: &HitRecord| {
+pub fn make_metal_brdf<T: Texture + 'static>(albedo: T) -> BRDF {
+    Arc::new(Metal { albedo })
+}
+
+struct Metal<T: Texture> {
+    albedo: T,
+}
+
+impl<T: Texture> Material for Metal<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let albedo = texture_at(&self.albedo, incoming, hit);
         if albedo == Color::new(0.0, 0.0, 0.0) {
             return None;
         }
         
         let reflection = reflect(incoming.direction(), hit.normal);
-        let attenuation = albedo;
-        let reflected = Ray::new(hit.point, reflection);
+        let attenuation = albedo.into();
+        let reflected = hit.spawn_specular_ray(incoming, reflection, |direction| reflect(direction, hit.normal));
         Some(
             Reflection { reflected, attenuation }
         )
-    };
-    Arc::new(brdf)
+    }
+
+    fn eval(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn pdf(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::SPECULAR
+    }
+}
+
+/// For creating rough metals with GGX microfacets.
+///
+/// `roughness` in [0, 1] goes from a mirror to a matte surface and may be a texture, `anisotropy` in [-1, 1]
+/// stretches the highlights along the tangent of the surface for positive values and across it for negative values,
+/// like on brushed metal.
+/// # Example
+/// ```
+/// use renders::{brdfs, microfacet::ComplexIor, noise::NoiseTexture};
+/// let brushed_aluminium = brdfs::make_rough_metal_brdf(ComplexIor::ALUMINIUM, 0.3, 0.8);
+/// let worn_copper = brdfs::make_rough_metal_brdf(ComplexIor::COPPER, NoiseTexture::new(1, 8.0), 0.0);
+/// ```
+#[must_use]
+pub fn make_rough_metal_brdf<R: Texture + 'static>(ior: ComplexIor, roughness: R, anisotropy: f64) -> BRDF {
+    Arc::new(RoughMetal { ior, roughness, anisotropy, film: None })
+}
+
+/// For creating rough metals covered by a thin film, such as oil on steel or the heat tint of titanium.
+///
+/// The reflections of the film interfere and color the metal depending on the viewing angle, the other parameters are
+/// the same as for [`make_rough_metal_brdf`].
+/// # Example
+/// ```
+/// use renders::{brdfs, microfacet::{ComplexIor, ThinFilm}};
+/// let oily_aluminium = brdfs::make_thin_film_metal_brdf(ComplexIor::ALUMINIUM, 0.2, 0.0, ThinFilm::new(350.0, 1.45));
+/// ```
+#[must_use]
+pub fn make_thin_film_metal_brdf<R: Texture + 'static>(ior: ComplexIor, roughness: R, anisotropy: f64, film: ThinFilm) -> BRDF {
+    Arc::new(RoughMetal { ior, roughness, anisotropy, film: Some(film) })
+}
+
+struct RoughMetal<R: Texture> {
+    ior: ComplexIor,
+    roughness: R,
+    anisotropy: f64,
+    film: Option<ThinFilm>,
+}
+
+impl<R: Texture> RoughMetal<R> {
+    fn conductor(&self, hit: &HitRecord) -> RoughConductor {
+        let roughness = self.roughness.scalar_value(hit.u, hit.v, hit.point);
+        RoughConductor {
+            distribution: TrowbridgeReitz::from_roughness(roughness, self.anisotropy),
+            ior: self.ior,
+            film: self.film,
+        }
+    }
+}
+
+impl<R: Texture> Material for RoughMetal<R> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let conductor = self.conductor(hit);
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = conductor.sample(wo, (rand::random::<f64>(), rand::random::<f64>()))?;
+
+        let pdf = conductor.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(
+            Reflection {
+                reflected: hit.spawn_ray(frame.to_world(wi)),
+                attenuation: conductor.eval(wo, wi) / pdf,
+            }
+        )
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let frame = shading_frame(hit);
+        self.conductor(hit).eval(frame.to_local(-unit_vector(incoming.direction())), frame.to_local(unit_vector(direction)))
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        let frame = shading_frame(hit);
+        self.conductor(hit).pdf(frame.to_local(-unit_vector(incoming.direction())), frame.to_local(unit_vector(direction)))
+    }
+
+    /// Even at zero roughness the microfacet distribution keeps a tiny width, so it can always be evaluated.
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::GLOSSY
+    }
+}
+
+/// For creating cloth such as velvet and satin, a diffuse base under a velvety sheen that lights up at grazing angles.
+///
+/// The sheen uses the "Charlie" distribution with color `sheen` and `roughness` in [0, 1], all three may be textures.
+/// The base only receives the light the sheen does not reflect, so the cloth does not reflect more than it receives.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color};
+/// let velvet = brdfs::make_cloth_brdf(Color::new(0.3, 0.02, 0.05), Color::new(0.9, 0.4, 0.5), 0.6);
+/// ```
+#[must_use]
+pub fn make_cloth_brdf<A: Texture + 'static, S: Texture + 'static, R: Texture + 'static>(albedo: A, sheen: S, roughness: R) -> BRDF {
+    Arc::new(Cloth { albedo, sheen, roughness })
+}
+
+struct Cloth<A: Texture, S: Texture, R: Texture> {
+    albedo: A,
+    sheen: S,
+    roughness: R,
+}
+
+impl<A: Texture, S: Texture, R: Texture> Cloth<A, S, R> {
+    /// The BRDF in the local shading frame.
+    fn f(&self, incoming: Ray, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+        let albedo: Vec3 = texture_at(&self.albedo, incoming, hit).into();
+        let sheen: Vec3 = texture_at(&self.sheen, incoming, hit).into();
+        let distribution = Charlie::from_roughness(self.roughness.scalar_value(hit.u, hit.v, hit.point));
+
+        let unreflected = Vec3::new(1.0, 1.0, 1.0) - distribution.albedo(wo) * sheen;
+        let diffuse = unreflected * albedo / PI;
+        distribution.f(wo, wi) * sheen + diffuse
+    }
+}
+
+impl<A: Texture, S: Texture, R: Texture> Material for Cloth<A, S, R> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = sampling::cosine_hemisphere((rand::random::<f64>(), rand::random::<f64>()));
+        let value = self.f(incoming, hit, wo, wi);
+        if value.near_zero() {
+            return None;
+        }
+        Some(
+            Reflection {
+                reflected: hit.spawn_ray(frame.to_world(wi)),
+                attenuation: PI * value,
+            }
+        )
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let frame = shading_frame(hit);
+        let wi = frame.to_local(unit_vector(direction));
+        self.f(incoming, hit, frame.to_local(-unit_vector(incoming.direction())), wi) * wi.z()
+    }
+
+    fn pdf(&self, _incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        sampling::cosine_hemisphere_pdf(dot(unit_vector(direction), hit.normal))
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::DIFFUSE | MaterialFlags::GLOSSY
+    }
+}
+
+/// For creating materials that reflect light as measured from a real material, see [`MeasuredBrdf`].
+///
+/// Directions are sampled proportional to the cosine, so narrow highlights are noisy.
+#[must_use]
+pub fn make_measured_brdf(measurement: MeasuredBrdf) -> BRDF {
+    Arc::new(Measured { measurement })
+}
+
+struct Measured {
+    measurement: MeasuredBrdf,
+}
+
+impl Measured {
+    /// Samples like [`Material::sample`], with `u` choosing the direction.
+    fn sample_with(&self, incoming: Ray, hit: &HitRecord, u: (f64, f64)) -> Option<Reflection> {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = sampling::cosine_hemisphere(u);
+        let value = self.measurement.value(wi, wo);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 || value.near_zero() {
+            return None;
+        }
+        Some(
+            Reflection {
+                reflected: hit.spawn_ray(frame.to_world(wi)),
+                attenuation: PI * value,
+            }
+        )
+    }
+}
+
+impl Material for Measured {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        self.sample_with(incoming, hit, (rand::random::<f64>(), rand::random::<f64>()))
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let frame = shading_frame(hit);
+        let wi = frame.to_local(unit_vector(direction));
+        self.measurement.value(wi, frame.to_local(-unit_vector(incoming.direction()))) * wi.z()
+    }
+
+    fn pdf(&self, _incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        sampling::cosine_hemisphere_pdf(dot(unit_vector(direction), hit.normal))
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::GLOSSY
+    }
 }
 
 /// For creating glass like materials
 #[must_use]
-pub fn make_glass_brdf(ior: f64, albedo: Color) -> BRDF {
-    let brdf = move |incoming: Ray, hit: &HitRecord| {
+pub fn make_glass_brdf<T: Texture + 'static>(ior: f64, albedo: T) -> BRDF {
+    Arc::new(Glass { ior, albedo, film: None, dispersion: None })
+}
+
+/// For creating glass like materials whose index of refraction depends on the wavelength, such as prisms and diamonds.
+///
+/// The colors only split up when rendering spectrally, see [`crate::camera::CameraBuilder::set_spectral`], otherwise
+/// the glass behaves as plain glass with the index of refraction at the d line.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, spectral::Dispersion};
+/// let diamond = brdfs::make_dispersive_glass_brdf(Dispersion::DIAMOND, Color::new(1.0, 1.0, 1.0));
+/// let flint = brdfs::make_dispersive_glass_brdf(Dispersion::from_abbe(1.62, 36.0), Color::new(1.0, 1.0, 1.0));
+/// ```
+#[must_use]
+pub fn make_dispersive_glass_brdf<T: Texture + 'static>(dispersion: Dispersion, albedo: T) -> BRDF {
+    let ior = dispersion.ior(spectral::D_LINE_WAVELENGTH);
+    Arc::new(Glass { ior, albedo, film: None, dispersion: Some(dispersion) })
+}
+
+/// For creating glass like materials covered by a thin film, whose reflections interfere and show rainbow colors.
+///
+/// A soap bubble is a film with air on both sides, which is glass with an index of refraction of 1.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, microfacet::ThinFilm};
+/// let soap_bubble = brdfs::make_thin_film_glass_brdf(1.0, Color::new(1.0, 1.0, 1.0), ThinFilm::new(400.0, 1.33));
+/// let coated_lens = brdfs::make_thin_film_glass_brdf(1.5, Color::new(1.0, 1.0, 1.0), ThinFilm::new(100.0, 1.38));
+/// ```
+#[must_use]
+pub fn make_thin_film_glass_brdf<T: Texture + 'static>(ior: f64, albedo: T, film: ThinFilm) -> BRDF {
+    Arc::new(Glass { ior, albedo, film: Some(film), dispersion: None })
+}
+
+struct Glass<T: Texture> {
+    ior: f64,
+    albedo: T,
+    film: Option<ThinFilm>,
+    dispersion: Option<Dispersion>,
+}
+
+impl<T: Texture> Glass<T> {
+    /// The index of refraction for the light carried by `incoming`.
+    fn ior_for(&self, incoming: Ray) -> f64 {
+        match (self.dispersion, incoming.wavelength()) {
+            (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength),
+            _ => self.ior,
+        }
+    }
+
+    /// Decides whether light arriving at an angle with cosine `cos_theta` is reflected, using the uniform random number
+    /// `u` in [0, 1), returns that together with the weight of the choice per channel.
+    fn choose_reflection(&self, cos_theta: f64, refraction_constant: f64, front_face: bool, u: f64) -> (bool, Vec3) {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).sqrt();
+        if refraction_constant * sin_theta > 1.0 {
+            return (true, white);
+        }
+        let Some(film) = self.film else {
+            return (reflectance(cos_theta, refraction_constant) > u, white);
+        };
+
+        let reflectance = film.reflectance_dielectric(if front_face {cos_theta} else {-cos_theta}, self.ior);
+        // Choosing by the strongest channel keeps the weights of the colored reflections at most 1, the transmitted
+        // light is nearly white so its weights stay close to 1.
+        let probability = reflectance.x().max(reflectance.y()).max(reflectance.z());
+        if probability >= 1.0 || (probability > 0.0 && probability > u) {
+            (true, reflectance / probability)
+        } else {
+            (false, (white - reflectance) / (1.0 - probability))
+        }
+    }
+}
+
+impl<T: Texture> Material for Glass<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let ior = self.ior_for(incoming);
         let refraction_constant = if hit.front_face {1.0/ior} else {ior};
         
         let unit_direction = unit_vector(incoming.direction());
         let cos_theta = dot(-unit_direction, hit.normal).min(1.0);
-        let sin_theta = f64::sqrt(1.0 - cos_theta*cos_theta);
-
-        let cannot_refract = refraction_constant * sin_theta > 1.0;
-        let direction = if cannot_refract || reflectance(cos_theta, refraction_constant) > rand::random::<f64>() {
-            reflect(unit_direction, hit.normal)
+        let (reflects, weight) = self.choose_reflection(cos_theta, refraction_constant, hit.front_face, rand::random());
+        let bend = |direction: Vec3| if reflects {
+            reflect(direction, hit.normal)
         } else {
-            refract(unit_direction, hit.normal, refraction_constant)
+            refract(direction, hit.normal, refraction_constant)
         };
         
-        let scattered = Ray::new(hit.point, direction);
+        let scattered = hit.spawn_specular_ray(incoming, bend(unit_direction), bend);
+        Some(
+            Reflection { reflected: scattered, attenuation: Vec3::from(texture_at(&self.albedo, incoming, hit)) * weight }
+        )
+    }
+
+    fn eval(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn pdf(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::SPECULAR | MaterialFlags::TRANSMISSIVE
+    }
+}
+
+/// For creating rough glass like materials such as frosted glass, with GGX microfacets for reflection and refraction.
+///
+/// `roughness` in [0, 1] may be a texture. Light travelling through the inside of the glass is absorbed according to the Beer-Lambert law with
+/// absorption coefficient `absorption` per unit of distance, so thicker glass is more strongly colored.
+/// Use [`glass_absorption_from_color`] to pick the coefficient by color.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color};
+/// let absorption = brdfs::glass_absorption_from_color(Color::new(0.4, 0.8, 0.5), 1.0);
+/// let frosted_green_glass = brdfs::make_rough_glass_brdf(1.5, 0.3, absorption);
+/// ```
+#[must_use]
+pub fn make_rough_glass_brdf<R: Texture + 'static>(ior: f64, roughness: R, absorption: Vec3) -> BRDF {
+    Arc::new(RoughGlass { ior, roughness, absorption })
+}
+
+struct RoughGlass<R: Texture> {
+    ior: f64,
+    roughness: R,
+    absorption: Vec3,
+}
+
+impl<R: Texture> RoughGlass<R> {
+    fn dielectric(&self, hit: &HitRecord) -> RoughDielectric {
+        let roughness = self.roughness.scalar_value(hit.u, hit.v, hit.point);
+        RoughDielectric {
+            distribution: TrowbridgeReitz::from_roughness(roughness, 0.0),
+            ior: if hit.front_face {self.ior} else {1.0 / self.ior},
+        }
+    }
+
+    /// Fraction of light that is not absorbed on the way from the origin of `incoming` to the hit.
+    fn transmittance(&self, incoming: Ray, hit: &HitRecord) -> Vec3 {
+        // Hitting the inside of the surface means the ray travelled through the glass to get here.
+        if hit.front_face {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        let distance = (hit.point - incoming.origin()).length();
+        Vec3::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
+    }
+}
+
+impl<R: Texture> Material for RoughGlass<R> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let dielectric = self.dielectric(hit);
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = dielectric.sample(wo, (rand::random::<f64>(), rand::random::<f64>()), rand::random::<f64>())?;
+
+        let pdf = dielectric.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
         Some(
-            Reflection { reflected: scattered, attenuation: albedo }
+            Reflection {
+                reflected: hit.spawn_ray(frame.to_world(wi)),
+                attenuation: dielectric.eval(wo, wi) / pdf * self.transmittance(incoming, hit),
+            }
         )
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = frame.to_local(unit_vector(direction));
+        self.dielectric(hit).eval(wo, wi) * self.transmittance(incoming, hit)
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        let frame = shading_frame(hit);
+        self.dielectric(hit).pdf(frame.to_local(-unit_vector(incoming.direction())), frame.to_local(unit_vector(direction)))
+    }
+
+    /// Even at zero roughness the microfacet distribution keeps a tiny width, so it can always be evaluated.
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::GLOSSY | MaterialFlags::TRANSMISSIVE
+    }
+}
+
+/// Absorption coefficient of glass that gives light the color `color` after travelling `distance` through it.
+/// # Panics
+/// Panics if `distance` is not positive.
+#[must_use]
+pub fn glass_absorption_from_color(color: Color, distance: f64) -> Vec3 {
+    assert!(distance > 0.0);
+    let color: Vec3 = color.into();
+    let channel = |transmittance: f64| -transmittance.max(1e-4).ln() / distance;
+    Vec3::new(channel(color.x()), channel(color.y()), channel(color.z()))
+}
+
+/// For creating the isotropic phase function of a volume, which scatters light equally in all directions.
+#[must_use]
+pub fn make_isotropic_phase_function<T: Texture + 'static>(albedo: T) -> BRDF {
+    Arc::new(HenyeyGreenstein { albedo, g: 0.0 })
+}
+
+/// For creating the Henyey-Greenstein phase function of a volume.
+/// The asymmetry `g` lies in the range (-1, 1): positive values scatter light forwards, negative values backwards
+/// and 0 is isotropic.
+/// # Panics
+/// Panics if `g` is not in the range (-1, 1).
+#[must_use]
+pub fn make_henyey_greenstein_phase_function<T: Texture + 'static>(albedo: T, g: f64) -> BRDF {
+    assert!(g > -1.0 && g < 1.0);
+    Arc::new(HenyeyGreenstein { albedo, g })
+}
+
+struct HenyeyGreenstein<T: Texture> {
+    albedo: T,
+    g: f64,
+}
+
+impl<T: Texture> HenyeyGreenstein<T> {
+    /// Density of scattering into a direction at an angle with cosine `cos_theta` from the direction of travel.
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = (2.0 * g).mul_add(-cos_theta, g.mul_add(g, 1.0));
+        g.mul_add(-g, 1.0) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl<T: Texture> Material for HenyeyGreenstein<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let direction = sample_henyey_greenstein(unit_vector(incoming.direction()), self.g, (rand::random(), rand::random()));
+        Some(
+            Reflection {
+                reflected: Ray::new(hit.point, direction),
+                attenuation: self.albedo.value(hit.u, hit.v, hit.point).into(),
+            }
+        )
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let albedo: Vec3 = self.albedo.value(hit.u, hit.v, hit.point).into();
+        albedo * self.pdf(incoming, hit, direction)
+    }
+
+    fn pdf(&self, incoming: Ray, _hit: &HitRecord, direction: Vec3) -> f64 {
+        self.phase(dot(unit_vector(incoming.direction()), unit_vector(direction)))
+    }
+
+    /// Phase functions scatter light over the whole sphere of directions.
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::DIFFUSE | MaterialFlags::TRANSMISSIVE
+    }
+}
+
+/// For creating hair and fur, using the hair scattering model of d'Eon et al. and Chiang et al. as described in pbrt.
+///
+/// Should be used on curves, as it relies on the normal being bent across the width of the hair.
+///
+/// - `absorption` is the absorption coefficient of the hair fibre per unit of its diameter, see
+///   [`hair_absorption_from_melanin`] and [`hair_absorption_from_color`] for easy ways to get it.
+/// - `longitudinal_roughness` in the range (0, 1] controls how wide the highlights are along the hair.
+/// - `azimuthal_roughness` in the range (0, 1] controls how far light spreads around the hair.
+/// - `scale_angle` is the tilt of the scales on the hair surface in degrees, around 2 for human hair.
+#[must_use]
+pub fn make_hair_brdf(absorption: Vec3, longitudinal_roughness: f64, azimuthal_roughness: f64, scale_angle: f64) -> BRDF {
+    Arc::new(Hair::new(absorption, longitudinal_roughness, azimuthal_roughness, scale_angle))
+}
+
+/// Absorption coefficient of hair with the given concentrations of the eumelanin and pheomelanin pigments.
+///
+/// Eumelanin concentrations of around 0.3 give blonde, 1.3 brown and 8 black hair. Pheomelanin makes hair red.
+#[must_use]
+pub fn hair_absorption_from_melanin(eumelanin: f64, pheomelanin: f64) -> Vec3 {
+    eumelanin * Vec3::new(0.419, 0.697, 1.37) + pheomelanin * Vec3::new(0.187, 0.4, 1.05)
+}
+
+/// Absorption coefficient of hair that appears roughly as `color` after many bounces,
+/// for a hair with the given azimuthal roughness.
+#[must_use]
+pub fn hair_absorption_from_color(color: Color, azimuthal_roughness: f64) -> Vec3 {
+    let b = azimuthal_roughness;
+    let denominator = 0.245f64.mul_add(b, 5.574).mul_add(b, -10.73).mul_add(b, 2.532).mul_add(b, -0.215).mul_add(b, 5.969);
+    let channel = |reflectance: f64| {
+        let absorption = reflectance.max(1e-4).ln() / denominator;
+        absorption * absorption
     };
-    Arc::new(brdf)
+    let color: Vec3 = color.into();
+    Vec3::new(channel(color.x()), channel(color.y()), channel(color.z()))
+}
+
+/// Parameters of the principled BRDF, they follow the Principled BSDF of Blender so materials translate one-to-one.
+/// All parameters except `ior` are in the range [0, 1].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PrincipledParameters {
+    /// Diffuse color, or the color of the reflections of metals.
+    pub base_color: Color,
+    /// Blends between a dielectric at 0 and a metal at 1.
+    pub metallic: f64,
+    /// Roughness of the specular reflections and transmission.
+    pub roughness: f64,
+    /// Amount of specular reflection of dielectrics, 0.5 corresponds to an index of refraction of 1.5.
+    pub specular: f64,
+    /// Tints the specular reflection of dielectrics towards the base color.
+    pub specular_tint: f64,
+    /// Soft velvet like reflection at grazing angles, for cloth.
+    pub sheen: f64,
+    /// Tints the sheen towards the base color.
+    pub sheen_tint: f64,
+    /// Strength of a second, white specular layer on top of everything else.
+    pub clearcoat: f64,
+    /// Glossiness of the clear coat, 1 is the glossiest.
+    pub clearcoat_gloss: f64,
+    /// Blends the diffuse reflection towards a flattened approximation of subsurface scattering.
+    pub subsurface: f64,
+    /// Stretches specular highlights along the tangent of the surface.
+    pub anisotropic: f64,
+    /// Blends between an opaque surface at 0 and a fully transmissive glass like surface at 1.
+    pub transmission: f64,
+    /// Index of refraction used for transmission.
+    pub ior: f64,
+}
+
+impl Default for PrincipledParameters {
+    /// The defaults of the Principled BSDF in Blender.
+    fn default() -> Self {
+        Self {
+            base_color: Color::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            subsurface: 0.0,
+            anisotropic: 0.0,
+            transmission: 0.0,
+            ior: 1.45,
+        }
+    }
+}
+
+/// For creating materials with the Disney principled BRDF.
+///
+/// A single material that covers most real world surfaces from a small set of intuitive parameters.
+/// All lobes are importance sampled and combined with multiple importance sampling.
+/// # Example
+/// ```
+/// use renders::{brdfs::{self, PrincipledParameters}, colors::Color};
+/// let car_paint = brdfs::make_principled_brdf(PrincipledParameters {
+///     base_color: Color::new(0.6, 0.05, 0.05),
+///     roughness: 0.4,
+///     clearcoat: 1.0,
+///     ..PrincipledParameters::default()
+/// });
+/// ```
+#[must_use]
+pub fn make_principled_brdf(parameters: PrincipledParameters) -> BRDF {
+    Arc::new(Principled::new(parameters))
+}
+
+/// For creating materials with the Disney principled BRDF where the base color comes from a texture.
+///
+/// The `base_color` of `parameters` is ignored.
+#[must_use]
+pub fn make_textured_principled_brdf<T: Texture + 'static>(parameters: PrincipledParameters, base_color: T) -> BRDF {
+    Arc::new(TexturedPrincipled { parameters, base_color })
+}
+
+struct TexturedPrincipled<T: Texture> {
+    parameters: PrincipledParameters,
+    base_color: T,
+}
+
+impl<T: Texture> TexturedPrincipled<T> {
+    fn at(&self, incoming: Ray, hit: &HitRecord) -> Principled {
+        Principled::new(PrincipledParameters {
+            base_color: texture_at(&self.base_color, incoming, hit),
+            ..self.parameters
+        })
+    }
+}
+
+impl<T: Texture> Material for TexturedPrincipled<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        self.at(incoming, hit).sample(incoming, hit)
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        self.at(incoming, hit).eval(incoming, hit, direction)
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        self.at(incoming, hit).pdf(incoming, hit, direction)
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        Principled::new(self.parameters).flags()
+    }
+}
+
+struct Principled {
+    parameters: PrincipledParameters,
+    base_color: Vec3,
+    tint: Vec3,
+    specular_color: Vec3,
+    specular: TrowbridgeReitz,
+    clearcoat: Gtr1,
+    transmission: TrowbridgeReitz,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    lobe_probabilities: [f64; 4],
+}
+
+impl Principled {
+    const DIFFUSE: usize = 0;
+    const SPECULAR: usize = 1;
+    const CLEARCOAT: usize = 2;
+    const TRANSMISSION: usize = 3;
+
+    fn new(parameters: PrincipledParameters) -> Self {
+        let base_color: Vec3 = parameters.base_color.into();
+        let base_luminance = luminance(base_color);
+        let tint = if base_luminance > 0.0 {base_color / base_luminance} else {Vec3::new(1.0, 1.0, 1.0)};
+
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let dielectric_specular = parameters.specular * 0.08 * lerp_vec(white, tint, parameters.specular_tint);
+        let specular_color = lerp_vec(dielectric_specular, base_color, parameters.metallic);
+
+        let diffuse_weight = (1.0 - parameters.metallic) * (1.0 - parameters.transmission);
+        let transmission_weight = (1.0 - parameters.metallic) * parameters.transmission;
+        let clearcoat_weight = 0.25 * parameters.clearcoat;
+
+        let mut lobe_probabilities = [0.0; 4];
+        lobe_probabilities[Self::DIFFUSE] = diffuse_weight * base_luminance.max(0.05);
+        lobe_probabilities[Self::SPECULAR] = (1.0 - transmission_weight) * luminance(specular_color).max(0.1);
+        lobe_probabilities[Self::CLEARCOAT] = clearcoat_weight;
+        lobe_probabilities[Self::TRANSMISSION] = transmission_weight;
+        let total: f64 = lobe_probabilities.iter().sum();
+        let lobe_probabilities = lobe_probabilities.map(|probability| probability / total);
+
+        Self {
+            parameters,
+            base_color,
+            tint,
+            specular_color,
+            specular: TrowbridgeReitz::from_roughness(parameters.roughness, parameters.anisotropic),
+            clearcoat: Gtr1::new(lerp(0.1, 0.001, parameters.clearcoat_gloss)),
+            transmission: TrowbridgeReitz::from_roughness(parameters.roughness, 0.0),
+            diffuse_weight,
+            transmission_weight,
+            lobe_probabilities,
+        }
+    }
+
+    fn transmission_lobe(&self, front_face: bool) -> RoughDielectric {
+        RoughDielectric {
+            distribution: self.transmission,
+            ior: if front_face {self.parameters.ior} else {1.0 / self.parameters.ior},
+        }
+    }
+
+    /// Evaluates the BRDF times the cosine of `wi`, in the local shading frame.
+    fn eval_local(&self, wo: Vec3, wi: Vec3, front_face: bool) -> Vec3 {
+        let parameters = &self.parameters;
+        let mut result = Vec3::default();
+
+        if self.transmission_weight > 0.0 {
+            let transmitted = self.transmission_lobe(front_face).eval(wo, wi);
+            // Refracted light is tinted by the base color, reflected light stays white.
+            let tint = if wi.z() < 0.0 {sqrt_vec(self.base_color)} else {Vec3::new(1.0, 1.0, 1.0)};
+            result += self.transmission_weight * transmitted * tint;
+        }
+
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return result;
+        }
+
+        let wm = (wo + wi).normalized();
+        let cos_theta_d = dot(wi, wm);
+        let fresnel_in = schlick_weight(wi.z());
+        let fresnel_out = schlick_weight(wo.z());
+        let fresnel_half = schlick_weight(cos_theta_d);
+
+        if self.diffuse_weight > 0.0 {
+            // Burley diffuse with retro reflection at grazing angles.
+            let retro = (2.0 * cos_theta_d * cos_theta_d).mul_add(parameters.roughness, 0.5);
+            let diffuse = (retro - 1.0).mul_add(fresnel_in, 1.0) * (retro - 1.0).mul_add(fresnel_out, 1.0);
+
+            // Hanrahan-Krueger inspired approximation of subsurface scattering.
+            let flattened = cos_theta_d * cos_theta_d * parameters.roughness;
+            let subsurface_fresnel = (flattened - 1.0).mul_add(fresnel_in, 1.0) * (flattened - 1.0).mul_add(fresnel_out, 1.0);
+            let subsurface = 1.25 * subsurface_fresnel.mul_add(1.0 / (wi.z() + wo.z()) - 0.5, 0.5);
+
+            let sheen_color = lerp_vec(Vec3::new(1.0, 1.0, 1.0), self.tint, parameters.sheen_tint);
+            let sheen = fresnel_half * parameters.sheen * sheen_color;
+
+            let lambert = lerp(diffuse, subsurface, parameters.subsurface) / PI * self.base_color;
+            result += self.diffuse_weight * wi.z() * (lambert + sheen);
+        }
+
+        // Specular reflection, for transmissive materials the reflection is part of the transmission lobe.
+        let specular_fresnel = lerp_vec(self.specular_color, Vec3::new(1.0, 1.0, 1.0), fresnel_half);
+        let specular = self.specular.d(wm) * self.specular.g(wo, wi) / (4.0 * wo.z());
+        result += (1.0 - self.transmission_weight) * specular * specular_fresnel;
+
+        if parameters.clearcoat > 0.0 {
+            let clearcoat_roughness = TrowbridgeReitz::new(0.25, 0.25);
+            let clearcoat_fresnel = lerp(0.04, 1.0, fresnel_half);
+            let clearcoat = self.clearcoat.d(wm) * clearcoat_roughness.g(wo, wi) * clearcoat_fresnel / (4.0 * wo.z());
+            let clearcoat = 0.25 * parameters.clearcoat * clearcoat;
+            result += Vec3::new(clearcoat, clearcoat, clearcoat);
+        }
+
+        result
+    }
+
+    /// Density of sampling `wi` given `wo`, in the local shading frame.
+    fn pdf_local(&self, wo: Vec3, wi: Vec3, front_face: bool) -> f64 {
+        let mut pdf = self.lobe_probabilities[Self::TRANSMISSION] * self.transmission_lobe(front_face).pdf(wo, wi);
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return pdf;
+        }
+
+        let wm = (wo + wi).normalized();
+        pdf += self.lobe_probabilities[Self::DIFFUSE] * sampling::cosine_hemisphere_pdf(wi.z());
+        pdf += self.lobe_probabilities[Self::SPECULAR] * self.specular.pdf(wo, wm) / (4.0 * dot(wo, wm));
+        pdf += self.lobe_probabilities[Self::CLEARCOAT] * self.clearcoat.pdf(wm) / (4.0 * dot(wo, wm));
+        pdf
+    }
+}
+
+impl Material for Principled {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let mut choice = rand::random::<f64>();
+        let lobe = self.lobe_probabilities.iter().position(|probability| {
+            let chosen = choice < *probability;
+            choice -= probability;
+            chosen
+        }).unwrap_or(Self::SPECULAR);
+
+        let u = (rand::random::<f64>(), rand::random::<f64>());
+        let wi = match lobe {
+            Self::DIFFUSE => sampling::cosine_hemisphere(u),
+            Self::CLEARCOAT => microfacet::reflect(wo, self.clearcoat.sample_normal(u)),
+            Self::TRANSMISSION => self.transmission_lobe(hit.front_face).sample(wo, u, rand::random())?,
+            _ => microfacet::reflect(wo, self.specular.sample_visible_normal(wo, u)),
+        };
+
+        let pdf = self.pdf_local(wo, wi, hit.front_face);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let attenuation = self.eval_local(wo, wi, hit.front_face) / pdf;
+
+        Some(
+            Reflection {
+                reflected: hit.spawn_ray(frame.to_world(wi)),
+                attenuation,
+            }
+        )
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let frame = shading_frame(hit);
+        self.eval_local(frame.to_local(-unit_vector(incoming.direction())), frame.to_local(unit_vector(direction)), hit.front_face)
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        let frame = shading_frame(hit);
+        self.pdf_local(frame.to_local(-unit_vector(incoming.direction())), frame.to_local(unit_vector(direction)), hit.front_face)
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        let transmissive = if self.transmission_weight > 0.0 {MaterialFlags::TRANSMISSIVE} else {MaterialFlags::NONE};
+        MaterialFlags::DIFFUSE | MaterialFlags::GLOSSY | transmissive
+    }
+}
+
+/// Parameters of the dielectric coating of a layered material, see [`make_layered_brdf`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CoatParameters {
+    /// Index of refraction of the coating.
+    pub ior: f64,
+    /// Roughness of the coating in [0, 1], 0 gives a mirror like lacquer.
+    pub roughness: f64,
+    /// Thickness of the coating, light travelling through it is absorbed along the way.
+    pub thickness: f64,
+    /// Absorption coefficient per unit of distance inside the coating, see [`glass_absorption_from_color`].
+    pub absorption: Vec3,
+}
+
+impl Default for CoatParameters {
+    /// A smooth and clear lacquer.
+    fn default() -> Self {
+        Self {
+            ior: 1.5,
+            roughness: 0.0,
+            thickness: 0.0,
+            absorption: Vec3::default(),
+        }
+    }
+}
+
+/// Most bounces between the coating and the base of a layered material that are followed.
+const MAX_LAYER_BOUNCES: usize = 16;
+
+/// For creating materials with a dielectric coating on top of any other material, such as car paint or varnished wood.
+///
+/// Light refracts into the coating and bounces between the base and the underside of the coating until it refracts back
+/// out, so the layers neither lose nor create energy. Sampling follows this random walk, while `eval` and `pdf` are
+/// unbiased stochastic estimates of it as in the layered BSDF of pbrt, so they vary a little between calls.
+/// The base is treated as opaque, light it transmits is lost.
+/// # Example
+/// ```
+/// use renders::{brdfs::{self, CoatParameters}, colors::Color};
+/// let varnish = CoatParameters {
+///     roughness: 0.1,
+///     thickness: 0.01,
+///     absorption: brdfs::glass_absorption_from_color(Color::new(0.9, 0.7, 0.4), 0.01),
+///     ..CoatParameters::default()
+/// };
+/// let wood = brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.3, 0.1));
+/// let varnished_wood = brdfs::make_layered_brdf(wood, varnish);
+/// ```
+#[must_use]
+pub fn make_layered_brdf(base: BRDF, coat: CoatParameters) -> BRDF {
+    let distribution = TrowbridgeReitz::from_roughness(coat.roughness, 0.0);
+    Arc::new(Layered {
+        base,
+        outside: RoughDielectric { distribution, ior: coat.ior },
+        inside: RoughDielectric { distribution, ior: 1.0 / coat.ior },
+        thickness: coat.thickness,
+        absorption: coat.absorption,
+    })
+}
+
+struct Layered {
+    base: BRDF,
+    /// Top of the coating seen from above.
+    outside: RoughDielectric,
+    /// Top of the coating seen from below, with directions mirrored to lie above the surface.
+    inside: RoughDielectric,
+    thickness: f64,
+    absorption: Vec3,
+}
+
+/// A direction sampled at the top of a coating, with the scattering function times the cosine and the density.
+#[derive(Clone, Copy)]
+struct CoatSample {
+    direction: Vec3,
+    value: f64,
+    pdf: f64,
+}
+
+impl Layered {
+    /// The scattering function, without the cosine, of the top of the coating for light arriving from either side.
+    fn coat_f(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wi.z() == 0.0 {
+            return 0.0;
+        }
+        let value = if wo.z() > 0.0 {self.outside.eval(wo, wi)} else {self.inside.eval(mirror(wo), mirror(wi))};
+        value / wi.z().abs()
+    }
+
+    fn coat_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() > 0.0 {self.outside.pdf(wo, wi)} else {self.inside.pdf(mirror(wo), mirror(wi))}
+    }
+
+    fn coat_sample(&self, wo: Vec3) -> Option<CoatSample> {
+        let (dielectric, above) = if wo.z() > 0.0 {(&self.outside, wo)} else {(&self.inside, mirror(wo))};
+        let wi = dielectric.sample(above, (rand::random::<f64>(), rand::random::<f64>()), rand::random::<f64>())?;
+        let pdf = dielectric.pdf(above, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let direction = if wo.z() > 0.0 {wi} else {mirror(wi)};
+        Some(CoatSample { direction, value: dielectric.eval(above, wi), pdf })
+    }
+
+    /// Fraction of light that is not absorbed when crossing the coating in direction `w`.
+    fn transmittance(&self, w: Vec3) -> Vec3 {
+        if self.thickness <= 0.0 {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        let distance = self.thickness / w.z().abs().max(1e-6);
+        Vec3::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
+    }
+
+    /// The ray arriving at the base from inside the coating, leaving towards local direction `wo`.
+    ///
+    /// The coating is thin, so the base sees the footprint of `incoming` on the surface, carried by parallel differentials.
+    fn base_incoming(frame: &Onb, incoming: Ray, hit: &HitRecord, wo: Vec3) -> Ray {
+        let wo = frame.to_world(wo);
+        let ray = Ray::new(hit.point + wo, -wo);
+        let Some((x_offset, y_offset)) = incoming.differentials().and_then(|differentials| hit.offsets(differentials)) else {
+            return ray;
+        };
+        ray.set_differentials(RayDifferentials {
+            x_origin: ray.origin() + x_offset,
+            x_direction: -wo,
+            y_origin: ray.origin() + y_offset,
+            y_direction: -wo,
+        })
+    }
+
+    /// Evaluates the base times the cosine for local directions inside the coating.
+    fn base_eval(&self, frame: &Onb, incoming: Ray, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+        self.base.eval(Self::base_incoming(frame, incoming, hit, wo), hit, frame.to_world(wi))
+    }
+
+    fn base_pdf(&self, frame: &Onb, incoming: Ray, hit: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        self.base.pdf(Self::base_incoming(frame, incoming, hit, wo), hit, frame.to_world(wi))
+    }
+
+    /// Samples the base for local directions inside the coating, returns the direction and its attenuation.
+    fn base_sample(&self, frame: &Onb, incoming: Ray, hit: &HitRecord, wo: Vec3) -> Option<(Vec3, Vec3)> {
+        let reflection = self.base.sample(Self::base_incoming(frame, incoming, hit, wo), hit)?;
+        let wi = frame.to_local(unit_vector(reflection.reflected.direction()));
+        (wi.z() > 0.0).then_some((wi, reflection.attenuation))
+    }
+}
+
+impl Material for Layered {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let entry = self.coat_sample(wo)?;
+        let mut weight = Vec3::new(1.0, 1.0, 1.0) * (entry.value / entry.pdf);
+        let mut w = entry.direction;
+
+        for _ in 0..MAX_LAYER_BOUNCES {
+            if w.z() > 0.0 {
+                return Some(
+                    Reflection {
+                        reflected: hit.spawn_ray(frame.to_world(w)),
+                        attenuation: weight,
+                    }
+                );
+            }
+            let (up, attenuation) = self.base_sample(&frame, incoming, hit, -w)?;
+            weight = weight * self.transmittance(w) * attenuation * self.transmittance(up);
+
+            let top = self.coat_sample(-up)?;
+            weight = weight * (top.value / top.pdf);
+            w = top.direction;
+        }
+        None
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = frame.to_local(unit_vector(direction));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+
+        let mut f = Vec3::new(1.0, 1.0, 1.0) * self.coat_f(wo, wi);
+        let Some(entry) = self.coat_sample(wo).filter(|entry| entry.direction.z() < 0.0) else {
+            return f * wi.z();
+        };
+        // Light refracted in from `wi`, connected to every bounce off the base with multiple importance sampling.
+        let light = self.coat_sample(wi).filter(|light| light.direction.z() < 0.0);
+        let base_specular = self.base.flags().contains(MaterialFlags::SPECULAR);
+
+        let mut beta = Vec3::new(1.0, 1.0, 1.0) * (entry.value / entry.pdf);
+        let mut w = entry.direction;
+        for _ in 0..MAX_LAYER_BOUNCES {
+            beta = beta * self.transmittance(w);
+
+            if let Some(light) = light.filter(|_| !base_specular) {
+                let base_pdf = self.base_pdf(&frame, incoming, hit, -w, -light.direction);
+                let weight = power_heuristic(light.pdf, base_pdf);
+                let base = self.base_eval(&frame, incoming, hit, -w, -light.direction);
+                let top = self.coat_f(light.direction, wi);
+                f += beta * base * self.transmittance(light.direction) * (top * weight / light.pdf);
+            }
+
+            let Some((up, attenuation)) = self.base_sample(&frame, incoming, hit, -w) else {
+                break;
+            };
+            beta = beta * attenuation * self.transmittance(up);
+
+            let exit = self.coat_f(-up, wi);
+            if exit > 0.0 {
+                let weight = if base_specular {
+                    1.0
+                } else {
+                    power_heuristic(self.base_pdf(&frame, incoming, hit, -w, up), self.coat_pdf(wi, -up))
+                };
+                f += beta * (exit * weight);
+            }
+
+            let Some(top) = self.coat_sample(-up).filter(|top| top.direction.z() < 0.0) else {
+                break;
+            };
+            beta = beta * (top.value / top.pdf);
+            w = top.direction;
+        }
+        f * wi.z()
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        let frame = shading_frame(hit);
+        let wo = frame.to_local(-unit_vector(incoming.direction()));
+        let wi = frame.to_local(unit_vector(direction));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        // Follows the walk of `sample`, adding the density of leaving towards `wi` after every bounce off the base.
+        let mut pdf = self.coat_pdf(wo, wi);
+        let Some(entry) = self.coat_sample(wo).filter(|entry| entry.direction.z() < 0.0) else {
+            return pdf;
+        };
+        let mut w = entry.direction;
+        for _ in 0..MAX_LAYER_BOUNCES {
+            let Some((up, _)) = self.base_sample(&frame, incoming, hit, -w) else {
+                break;
+            };
+            pdf += self.coat_pdf(-up, wi);
+
+            let Some(top) = self.coat_sample(-up).filter(|top| top.direction.z() < 0.0) else {
+                break;
+            };
+            w = top.direction;
+        }
+        pdf
+    }
+
+    /// Even at zero roughness the coating keeps a tiny width, so it can always be evaluated.
+    fn flags(&self) -> MaterialFlags {
+        let base = self.base.flags();
+        [MaterialFlags::DIFFUSE, MaterialFlags::SPECULAR]
+            .into_iter()
+            .filter(|&lobe| base.contains(lobe))
+            .fold(MaterialFlags::GLOSSY, |flags, lobe| flags | lobe)
+    }
+}
+
+/// For blending two materials by a weight in [0, 1] that may be a texture mask, such as rust over paint.
+///
+/// At a weight of 0 the surface is made of `first` and at 1 of `second`. Each hit picks one of the two at random
+/// according to the weight, so any two materials can be combined.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, noise::NoiseTexture};
+/// let paint = brdfs::make_lambertian_diffuse_brdf(Color::new(0.1, 0.3, 0.6));
+/// let rust = brdfs::make_lambertian_diffuse_brdf(Color::new(0.4, 0.15, 0.05));
+/// let rusty_paint = brdfs::make_mix_brdf(paint, rust, NoiseTexture::new(3, 8.0));
+/// ```
+#[must_use]
+pub fn make_mix_brdf<T: Texture + 'static>(first: BRDF, second: BRDF, weight: T) -> BRDF {
+    Arc::new(Mix { first, second, weight })
+}
+
+struct Mix<T: Texture> {
+    first: BRDF,
+    second: BRDF,
+    weight: T,
+}
+
+impl<T: Texture> Mix<T> {
+    fn weight(&self, hit: &HitRecord) -> f64 {
+        self.weight.scalar_value(hit.u, hit.v, hit.point).clamp(0.0, 1.0)
+    }
+}
+
+impl<T: Texture> Material for Mix<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        if rand::random::<f64>() < self.weight(hit) {
+            self.second.sample(incoming, hit)
+        } else {
+            self.first.sample(incoming, hit)
+        }
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        lerp_vec(self.first.eval(incoming, hit, direction), self.second.eval(incoming, hit, direction), self.weight(hit))
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        lerp(self.first.pdf(incoming, hit, direction), self.second.pdf(incoming, hit, direction), self.weight(hit))
+    }
+
+    fn emitted(&self, incoming: Ray, hit: &HitRecord) -> Vec3 {
+        lerp_vec(self.first.emitted(incoming, hit), self.second.emitted(incoming, hit), self.weight(hit))
+    }
+
+    fn opacity(&self, incoming: Ray, hit: &HitRecord) -> f64 {
+        lerp(self.first.opacity(incoming, hit), self.second.opacity(incoming, hit), self.weight(hit))
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        self.first.flags() | self.second.flags()
+    }
+}
+
+/// For cutting shapes out of surfaces with an opacity mask in [0, 1], such as leaves and fences on quads.
+///
+/// Where the mask is 0 the surface is not there at all and rays pass through, where it is 1 the surface is made of
+/// `material`. Values in between let a random part of the rays through, which also makes the filtered edges of
+/// masks seen from far away look smooth. Use [`crate::textures::ImageTexture::alpha_channel`] to mask by the alpha
+/// of an image.
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, textures::UvChecker};
+/// let wood = brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.35, 0.2));
+/// let fence = brdfs::make_cutout_brdf(wood, UvChecker::new(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 8.0));
+/// ```
+#[must_use]
+pub fn make_cutout_brdf<T: Texture + 'static>(material: BRDF, opacity: T) -> BRDF {
+    Arc::new(Cutout { material, opacity })
+}
+
+struct Cutout<T: Texture> {
+    material: BRDF,
+    opacity: T,
+}
+
+impl<T: Texture> Material for Cutout<T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        self.material.sample(incoming, hit)
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        self.material.eval(incoming, hit, direction)
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        self.material.pdf(incoming, hit, direction)
+    }
+
+    fn emitted(&self, incoming: Ray, hit: &HitRecord) -> Vec3 {
+        self.material.emitted(incoming, hit)
+    }
+
+    fn opacity(&self, incoming: Ray, hit: &HitRecord) -> f64 {
+        let opacity: Vec3 = texture_at(&self.opacity, incoming, hit).into();
+        let opacity = (opacity.x() + opacity.y() + opacity.z()) / 3.0;
+        opacity * self.material.opacity(incoming, hit)
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        self.material.flags()
+    }
+}
+
+/// Looks up a texture at a hit, filtered over the footprint of a pixel if the incoming ray has differentials.
+fn texture_at<T: Texture>(texture: &T, incoming: Ray, hit: &HitRecord) -> Color {
+    texture.filtered_value(hit.u, hit.v, hit.point, hit.footprint(incoming))
+}
+
+/// Local coordinate system at a hit, with the shading normal as z axis and the tangent as x axis.
+fn shading_frame(hit: &HitRecord) -> Onb {
+    Onb::from_normal_and_tangent(hit.normal, hit.tangent)
+}
+
+/// Mirrors a local direction to the other side of the surface.
+const fn mirror(w: Vec3) -> Vec3 {
+    Vec3::new(w.x(), w.y(), -w.z())
+}
+
+/// Weight of the first of two sampling strategies by the power heuristic of Veach, given the densities of both.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (pdf, other_pdf) = (pdf * pdf, other_pdf * other_pdf);
+    if pdf + other_pdf <= 0.0 {0.0} else {pdf / (pdf + other_pdf)}
+}
+
+fn luminance(color: Vec3) -> f64 {
+    0.0722f64.mul_add(color.z(), 0.2126f64.mul_add(color.x(), 0.7152 * color.y()))
+}
+
+fn lerp_vec(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+fn sqrt_vec(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().max(0.0).sqrt(), v.y().max(0.0).sqrt(), v.z().max(0.0).sqrt())
+}
+
+/// Amount of hair scattering lobes that are modelled explicitly,
+/// lobe `p` has light travel `p` times through the inside of the hair before leaving.
+const HAIR_LOBES: usize = 3;
+
+/// Index of refraction of the keratin of a hair.
+const HAIR_IOR: f64 = 1.55;
+
+struct Hair {
+    absorption: Vec3,
+    longitudinal_variance: [f64; HAIR_LOBES + 1],
+    logistic_scale: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl Hair {
+    fn new(absorption: Vec3, longitudinal_roughness: f64, azimuthal_roughness: f64, scale_angle: f64) -> Self {
+        let beta_m = longitudinal_roughness.clamp(1e-3, 1.0);
+        let beta_n = azimuthal_roughness.clamp(1e-3, 1.0);
+
+        let v = 3.7f64.mul_add(beta_m.powi(20), 0.812f64.mul_add(beta_m * beta_m, 0.726 * beta_m));
+        let v = v * v;
+        let longitudinal_variance = [v, 0.25 * v, 4.0 * v, 4.0 * v];
+
+        let logistic_scale = (PI / 8.0).sqrt()
+            * 5.372f64.mul_add(beta_n.powi(22), 1.194f64.mul_add(beta_n * beta_n, 0.265 * beta_n));
+
+        let mut sin_2k_alpha = [scale_angle.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [sin_2k_alpha[0].mul_add(-sin_2k_alpha[0], 1.0).max(0.0).sqrt(), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].mul_add(cos_2k_alpha[i - 1], -(sin_2k_alpha[i - 1] * sin_2k_alpha[i - 1]));
+        }
+
+        Self { absorption, longitudinal_variance, logistic_scale, sin_2k_alpha, cos_2k_alpha }
+    }
+
+    /// Attenuation of each lobe, for light arriving at offset `h` across the hair.
+    fn lobe_attenuation(&self, cos_theta_o: f64, h: f64) -> [Vec3; HAIR_LOBES + 1] {
+        let sin_theta_o = cos_theta_o.mul_add(-cos_theta_o, 1.0).max(0.0).sqrt();
+        let sin_theta_t = sin_theta_o / HAIR_IOR;
+        let cos_theta_t = sin_theta_t.mul_add(-sin_theta_t, 1.0).max(0.0).sqrt();
+        let modified_ior = HAIR_IOR.mul_add(HAIR_IOR, -(sin_theta_o * sin_theta_o)).sqrt() / cos_theta_o;
+        let sin_gamma_t = h / modified_ior;
+        let cos_gamma_t = sin_gamma_t.mul_add(-sin_gamma_t, 1.0).max(0.0).sqrt();
+
+        // Transmittance of a single path through the inside of the hair.
+        let path_length = 2.0 * cos_gamma_t / cos_theta_t;
+        let transmittance = Vec3::new(
+            (-self.absorption.x() * path_length).exp(),
+            (-self.absorption.y() * path_length).exp(),
+            (-self.absorption.z() * path_length).exp(),
+        );
+
+        let cos_gamma_o = h.mul_add(-h, 1.0).max(0.0).sqrt();
+        let fresnel = fresnel_dielectric(cos_theta_o * cos_gamma_o, HAIR_IOR);
+
+        let mut attenuation = [Vec3::default(); HAIR_LOBES + 1];
+        attenuation[0] = Vec3::new(fresnel, fresnel, fresnel);
+        attenuation[1] = (1.0 - fresnel) * (1.0 - fresnel) * transmittance;
+        for p in 2..HAIR_LOBES {
+            attenuation[p] = attenuation[p - 1] * transmittance * fresnel;
+        }
+        // All remaining bounces are summed into the last lobe as a geometric series.
+        let remaining = |a: f64, t: f64| a * t * fresnel / t.mul_add(-fresnel, 1.0);
+        let last = attenuation[HAIR_LOBES - 1];
+        attenuation[HAIR_LOBES] = Vec3::new(
+            remaining(last.x(), transmittance.x()),
+            remaining(last.y(), transmittance.y()),
+            remaining(last.z(), transmittance.z()),
+        );
+        attenuation
+    }
+
+    /// Direction of the scattered ray relative to the hair, and the offset across the hair where it was hit.
+    fn geometry(incoming: Ray, hit: &HitRecord) -> Option<HairGeometry> {
+        // Local frame: x along the hair, z facing the viewer and y across the width of the hair.
+        let along = hit.tangent;
+        let outgoing = -unit_vector(incoming.direction());
+        let facing = outgoing - dot(outgoing, along) * along;
+        if facing.near_zero() {
+            return None;
+        }
+        let facing = facing.normalized();
+        let across = unit_vector(cross(facing, along));
+        let h = dot(hit.normal, across).clamp(-1.0, 1.0);
+
+        let sin_theta_o = dot(outgoing, along).clamp(-1.0, 1.0);
+        let cos_theta_o = sin_theta_o.mul_add(-sin_theta_o, 1.0).max(0.0).sqrt();
+        let modified_ior = HAIR_IOR.mul_add(HAIR_IOR, -(sin_theta_o * sin_theta_o)).sqrt() / cos_theta_o;
+
+        Some(HairGeometry {
+            along,
+            facing,
+            across,
+            h,
+            gamma_o: h.asin(),
+            gamma_t: (h / modified_ior).clamp(-1.0, 1.0).asin(),
+            sin_theta_o,
+            cos_theta_o,
+            phi_o: dot(outgoing, across).atan2(dot(outgoing, facing)),
+        })
+    }
+
+    /// Sine and cosine of the outgoing angle of lobe `p`, tilted to account for the scales on the hair surface.
+    fn tilted_outgoing(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin_theta_op, cos_theta_op) = match p {
+            0 => (
+                sin_theta_o.mul_add(self.cos_2k_alpha[1], -(cos_theta_o * self.sin_2k_alpha[1])),
+                cos_theta_o.mul_add(self.cos_2k_alpha[1], sin_theta_o * self.sin_2k_alpha[1]),
+            ),
+            1 => (
+                sin_theta_o.mul_add(self.cos_2k_alpha[0], cos_theta_o * self.sin_2k_alpha[0]),
+                cos_theta_o.mul_add(self.cos_2k_alpha[0], -(sin_theta_o * self.sin_2k_alpha[0])),
+            ),
+            2 => (
+                sin_theta_o.mul_add(self.cos_2k_alpha[2], cos_theta_o * self.sin_2k_alpha[2]),
+                cos_theta_o.mul_add(self.cos_2k_alpha[2], -(sin_theta_o * self.sin_2k_alpha[2])),
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_op, cos_theta_op.abs())
+    }
+
+    /// Calls `lobe` with the attenuation, the probability of sampling and the value of the longitudinal and
+    /// azimuthal scattering functions of each lobe, for light scattered into `direction`.
+    fn for_each_lobe<F: FnMut(Vec3, f64, f64)>(&self, incoming: Ray, hit: &HitRecord, direction: Vec3, mut lobe: F) {
+        let Some(geometry) = Self::geometry(incoming, hit) else {
+            return;
+        };
+        let direction = unit_vector(direction);
+        let sin_theta_i = dot(direction, geometry.along).clamp(-1.0, 1.0);
+        let cos_theta_i = sin_theta_i.mul_add(-sin_theta_i, 1.0).max(0.0).sqrt();
+        let phi = dot(direction, geometry.across).atan2(dot(direction, geometry.facing)) - geometry.phi_o;
+
+        let attenuation = self.lobe_attenuation(geometry.cos_theta_o, geometry.h);
+        let (weights, total_weight) = lobe_weights(&attenuation);
+        if total_weight <= 0.0 {
+            return;
+        }
+
+        for (p, lobe_attenuation) in attenuation.iter().enumerate() {
+            let (sin_theta_op, cos_theta_op) = self.tilted_outgoing(p, geometry.sin_theta_o, geometry.cos_theta_o);
+            let longitudinal = hair_longitudinal_scattering(
+                cos_theta_i, cos_theta_op, sin_theta_i, sin_theta_op, self.longitudinal_variance[p],
+            );
+            let azimuthal = if p < HAIR_LOBES {
+                let delta_phi = phi - hair_azimuthal_offset(p, geometry.gamma_o, geometry.gamma_t);
+                trimmed_logistic(wrap_angle(delta_phi), self.logistic_scale, -PI, PI)
+            } else {
+                1.0 / (2.0 * PI)
+            };
+            lobe(*lobe_attenuation, weights[p] / total_weight, longitudinal * azimuthal);
+        }
+    }
+}
+
+/// Geometry of a hair hit in the local frame of the hair.
+struct HairGeometry {
+    along: Vec3,
+    facing: Vec3,
+    across: Vec3,
+    h: f64,
+    gamma_o: f64,
+    gamma_t: f64,
+    sin_theta_o: f64,
+    cos_theta_o: f64,
+    phi_o: f64,
+}
+
+impl Material for Hair {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        let geometry = Self::geometry(incoming, hit)?;
+
+        // Pick a lobe proportional to how much light it carries.
+        let attenuation = self.lobe_attenuation(geometry.cos_theta_o, geometry.h);
+        let (weights, total_weight) = lobe_weights(&attenuation);
+        if total_weight <= 0.0 {
+            return None;
+        }
+        let mut choice = rand::random::<f64>() * total_weight;
+        let mut p = HAIR_LOBES;
+        for (lobe, weight) in weights.iter().enumerate() {
+            if choice < *weight {
+                p = lobe;
+                break;
+            }
+            choice -= weight;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilted_outgoing(p, geometry.sin_theta_o, geometry.cos_theta_o);
+
+        // Sample the longitudinal scattering function.
+        let variance = self.longitudinal_variance[p];
+        let u = rand::random::<f64>().max(1e-5);
+        let cos_theta = variance.mul_add((1.0 - u).mul_add((-2.0 / variance).exp(), u).ln(), 1.0);
+        let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).max(0.0).sqrt();
+        let cos_phi = (2.0 * PI * rand::random::<f64>()).cos();
+        let sin_theta_i = (sin_theta * cos_phi).mul_add(cos_theta_op, -(cos_theta * sin_theta_op)).clamp(-1.0, 1.0);
+        let cos_theta_i = sin_theta_i.mul_add(-sin_theta_i, 1.0).max(0.0).sqrt();
+
+        // Sample the azimuthal scattering function.
+        let delta_phi = if p < HAIR_LOBES {
+            hair_azimuthal_offset(p, geometry.gamma_o, geometry.gamma_t)
+                + sample_trimmed_logistic(rand::random(), self.logistic_scale, -PI, PI)
+        } else {
+            2.0 * PI * rand::random::<f64>()
+        };
+        let phi_i = geometry.phi_o + delta_phi;
+
+        let direction = sin_theta_i * geometry.along
+            + cos_theta_i * phi_i.cos() * geometry.facing
+            + cos_theta_i * phi_i.sin() * geometry.across;
+
+        // Both scattering functions are sampled exactly, so only the lobe choice has to be corrected for.
+        Some(
+            Reflection {
+                reflected: hit.spawn_ray(direction),
+                attenuation: attenuation[p] * (total_weight / weights[p]),
+            }
+        )
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        let mut result = Vec3::default();
+        self.for_each_lobe(incoming, hit, direction, |attenuation, _, scattering| result += scattering * attenuation);
+        result
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        let mut pdf = 0.0;
+        self.for_each_lobe(incoming, hit, direction, |_, probability, scattering| pdf += probability * scattering);
+        pdf
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::GLOSSY | MaterialFlags::TRANSMISSIVE
+    }
+}
+
+/// Average attenuation of each hair lobe and their sum, used as weights to choose between the lobes.
+fn lobe_weights(attenuation: &[Vec3; HAIR_LOBES + 1]) -> ([f64; HAIR_LOBES + 1], f64) {
+    let weights = attenuation.map(|a| (a.x() + a.y() + a.z()) / 3.0);
+    (weights, weights.iter().sum())
+}
+
+/// Change in azimuth of light leaving a hair after travelling `p` times through its inside.
+fn hair_azimuthal_offset(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let lobe = p as f64;
+    lobe.mul_add(PI, (2.0 * lobe).mul_add(gamma_t, -2.0 * gamma_o))
+}
+
+/// Longitudinal scattering function of a hair lobe with variance `variance`.
+fn hair_longitudinal_scattering(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, variance: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / variance;
+    let b = sin_theta_i * sin_theta_o / variance;
+    if variance <= 0.1 {
+        // Evaluated in log space to prevent overflow of the Bessel function.
+        (log_bessel_i0(a) - b - 1.0 / variance + std::f64::consts::LN_2 + (1.0 / (2.0 * variance)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / variance).sinh() * 2.0 * variance)
+    }
+}
+
+/// Modified Bessel function of the first kind of order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut term = 1.0;
+    let quarter_square = x * x / 4.0;
+    for i in 1..=10 {
+        value += term;
+        term *= quarter_square / f64::from(i * i);
+    }
+    value
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        0.5f64.mul_add(-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x), x)
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+/// Wraps an angle into the range [-π, π].
+fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Density of the logistic distribution with scale `scale`, limited to the range from `low` to `high`.
+fn trimmed_logistic(x: f64, scale: f64, low: f64, high: f64) -> f64 {
+    let logistic = |x: f64| {
+        let e = (-x.abs() / scale).exp();
+        e / (scale * (1.0 + e) * (1.0 + e))
+    };
+    let logistic_cdf = |x: f64| 1.0 / (1.0 + (-x / scale).exp());
+    logistic(x) / (logistic_cdf(high) - logistic_cdf(low))
+}
+
+/// Samples the logistic distribution with scale `scale`, limited to the range from `low` to `high`.
+fn sample_trimmed_logistic(u: f64, scale: f64, low: f64, high: f64) -> f64 {
+    let logistic_cdf = |x: f64| 1.0 / (1.0 + (-x / scale).exp());
+    let range = logistic_cdf(high) - logistic_cdf(low);
+    let sample = -scale * (1.0 / u.mul_add(range, logistic_cdf(low)) - 1.0).ln();
+    sample.clamp(low, high)
+}
+
+/// Samples a direction scattered away from the unit vector `forward` according to the Henyey-Greenstein phase function.
+pub(crate) fn sample_henyey_greenstein(forward: Vec3, g: f64, u: (f64, f64)) -> Vec3 {
+    let cos_theta = sample_henyey_greenstein_cosine(g, u.0);
+    let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+
+    Onb::from_normal(forward).to_world(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+}
+
+/// Samples the cosine of the angle between the incoming and scattered direction of the Henyey-Greenstein phase function.
+fn sample_henyey_greenstein_cosine(g: f64, u: f64) -> f64 {
+    if g.abs() < 1e-3 {
+        return 2.0f64.mul_add(-u, 1.0);
+    }
+    let square = g.mul_add(-g, 1.0) / (2.0 * g).mul_add(-u, 1.0 + g);
+    (g.mul_add(g, 1.0) - square * square) / (2.0 * g)
 }
 
 const fn reflectance(cosine: f64, ior: f64) -> f64 {
@@ -88,4 +1737,302 @@ const fn reflectance(cosine: f64, ior: f64) -> f64 {
 
 const fn const_pow5(a: f64) -> f64 {
     a * a * a * a * a
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_hit(material: &BRDF, normal: Vec3, tangent: Vec3) -> HitRecord {
+        HitRecord {
+            point: Vec3::new(0.0, 0.0, 0.0),
+            normal,
+            geometric_normal: normal,
+            tangent,
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
+            front_face: true,
+            brdf: material.clone(),
+        }
+    }
+
+    /// Integrates `f` over the sphere of directions with the midpoint rule.
+    fn integrate_sphere<F: FnMut(Vec3) -> f64>(mut f: F, resolution: u32) -> f64 {
+        let d_cos = 2.0 / f64::from(resolution);
+        let d_phi = 2.0 * PI / f64::from(resolution);
+        let mut sum = 0.0;
+        for i in 0..resolution {
+            let cos_theta = (f64::from(i) + 0.5).mul_add(d_cos, -1.0);
+            let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).sqrt();
+            for j in 0..resolution {
+                let phi = (f64::from(j) + 0.5) * d_phi;
+                sum += f(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)) * d_cos * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn pdfs_integrate_to_one() {
+        let incoming = Ray::new(Vec3::new(0.3, 0.2, 1.0), Vec3::new(-0.3, -0.2, -1.0));
+        // Microfacet materials lose the reflections that end up below the surface, so their pdf integrates to less.
+        let materials = [
+            (make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)), true),
+            (make_henyey_greenstein_phase_function(Color::new(1.0, 1.0, 1.0), 0.6), true),
+            (make_hair_brdf(hair_absorption_from_melanin(1.3, 0.0), 0.4, 0.4, 2.0), true),
+            (make_cloth_brdf(Color::new(0.5, 0.1, 0.1), Color::new(1.0, 1.0, 1.0), 0.5), true),
+            (make_rough_metal_brdf(ComplexIor::GOLD, 0.6, 0.4), false),
+            (make_principled_brdf(PrincipledParameters { clearcoat: 1.0, ..PrincipledParameters::default() }), false),
+            (make_layered_brdf(make_principled_brdf(PrincipledParameters::default()), CoatParameters { roughness: 0.5, ..CoatParameters::default() }), false),
+        ];
+        for (material, lossless) in &materials {
+            let hit = make_hit(material, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+            let total = integrate_sphere(|direction| material.pdf(incoming, &hit, direction), 400);
+            if *lossless {
+                assert!((total - 1.0).abs() < 0.01);
+            } else {
+                assert!(total > 0.8 && total < 1.01);
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_attenuation_matches_eval_over_pdf() {
+        let incoming = Ray::new(Vec3::new(0.5, 0.0, 1.0), Vec3::new(-0.5, 0.0, -1.0));
+        let materials = [
+            make_lambertian_diffuse_brdf(Color::new(0.5, 0.3, 0.1)),
+            make_rough_metal_brdf(ComplexIor::COPPER, 0.5, 0.0),
+            make_rough_glass_brdf(1.5, 0.4, Vec3::default()),
+            make_cloth_brdf(Color::new(0.5, 0.3, 0.1), Color::new(0.5, 0.5, 0.5), 0.5),
+        ];
+        for material in &materials {
+            let hit = make_hit(material, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+            for _ in 0..100 {
+                let Some(reflection) = material.sample(incoming, &hit) else {
+                    continue;
+                };
+                let direction = reflection.reflected.direction();
+                let expected = material.eval(incoming, &hit, direction) / material.pdf(incoming, &hit, direction);
+                assert!((reflection.attenuation - expected).length() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn lights_can_be_brighter_than_white() {
+        let incoming = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let lamp = make_diffuse_light_brdf(Color::new(1.0, 0.5, 0.25), 4.0);
+        let mut hit = make_hit(&lamp, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(lamp.emitted(incoming, &hit), Vec3::new(4.0, 2.0, 1.0));
+        hit.front_face = false;
+        assert_eq!(lamp.emitted(incoming, &hit), Vec3::default());
+    }
+
+    #[test]
+    fn specular_materials_can_not_be_evaluated() {
+        let incoming = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let glass = make_glass_brdf(1.5, Color::new(1.0, 1.0, 1.0));
+        let hit = make_hit(&glass, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+
+        assert!(glass.flags().contains(MaterialFlags::SPECULAR | MaterialFlags::TRANSMISSIVE));
+        assert!(!glass.flags().contains(MaterialFlags::DIFFUSE));
+        assert_eq!(glass.eval(incoming, &hit, Vec3::new(0.0, 0.0, 1.0)), Vec3::default());
+    }
+
+    #[test]
+    fn layered_materials_conserve_energy() {
+        let incoming = Ray::new(Vec3::new(0.5, 0.0, 1.0), Vec3::new(-0.5, 0.0, -1.0));
+        let lacquered = make_layered_brdf(make_lambertian_diffuse_brdf(Color::new(1.0, 1.0, 1.0)), CoatParameters::default());
+        let hit = make_hit(&lacquered, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let samples = 20000;
+        let total: f64 = (0..samples)
+            .filter_map(|_| lacquered.sample(incoming, &hit))
+            .map(|reflection| reflection.attenuation.x())
+            .sum();
+        let albedo = total / f64::from(samples);
+        assert!(albedo > 0.95 && albedo <= 1.0);
+    }
+
+    #[test]
+    fn layered_eval_agrees_with_sampling() {
+        let incoming = Ray::new(Vec3::new(0.5, 0.0, 1.0), Vec3::new(-0.5, 0.0, -1.0));
+        let coat = CoatParameters { roughness: 0.3, thickness: 0.1, absorption: Vec3::new(0.5, 1.0, 2.0), ..CoatParameters::default() };
+        let bases = [
+            make_principled_brdf(PrincipledParameters { roughness: 0.6, ..PrincipledParameters::default() }),
+            make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        ];
+        for base in bases {
+            let varnished = make_layered_brdf(base, coat);
+            let hit = make_hit(&varnished, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+
+            let samples = 20000;
+            let sampled_albedo: f64 = (0..samples)
+                .filter_map(|_| varnished.sample(incoming, &hit))
+                .map(|reflection| reflection.attenuation.x())
+                .sum::<f64>() / f64::from(samples);
+            let evaluated_albedo = integrate_sphere(|direction| varnished.eval(incoming, &hit, direction).x(), 200);
+            assert!((sampled_albedo - evaluated_albedo).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn layered_bases_see_the_incoming_footprint() {
+        let base = make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5));
+        let hit = HitRecord {
+            dpdu: Vec3::new(2.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.5, 1.0, 0.0),
+            ..make_hit(&base, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0))
+        };
+        let origin = Vec3::new(0.5, 0.0, 1.0);
+        let incoming = Ray::new(origin, -origin).set_differentials(RayDifferentials {
+            x_origin: origin,
+            x_direction: Vec3::new(-0.49, 0.0, -1.0),
+            y_origin: origin,
+            y_direction: Vec3::new(-0.5, 0.02, -1.0),
+        });
+        let camera = hit.footprint(incoming);
+        assert!(camera.dudx > 0.0 && camera.dvdy > 0.0);
+
+        let frame = shading_frame(&hit);
+        for wo in [Vec3::new(0.0, 0.0, 1.0), unit_vector(Vec3::new(0.6, -0.3, 0.5))] {
+            let footprint = hit.footprint(Layered::base_incoming(&frame, incoming, &hit, wo));
+            for (actual, expected) in [
+                (footprint.dudx, camera.dudx),
+                (footprint.dvdx, camera.dvdx),
+                (footprint.dudy, camera.dudy),
+                (footprint.dvdy, camera.dvdy),
+            ] {
+                assert!((actual - expected).abs() < 1e-9, "{footprint:?} {camera:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn white_cloth_reflects_all_light() {
+        for roughness in [0.1, 0.35, 0.8] {
+            let cloth = make_cloth_brdf(Color::new(1.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0), roughness);
+            let hit = make_hit(&cloth, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+            for cos_theta in [1.0f64, 0.6, 0.2] {
+                let origin = Vec3::new(cos_theta.mul_add(-cos_theta, 1.0).sqrt(), 0.0, cos_theta);
+                let incoming = Ray::new(origin, -origin);
+                let albedo = integrate_sphere(|direction| cloth.eval(incoming, &hit, direction).x(), 400);
+                assert!((albedo - 1.0).abs() < 0.02, "{roughness} {cos_theta} {albedo}");
+            }
+        }
+    }
+
+    #[test]
+    fn cloth_sheen_grows_at_grazing_angles() {
+        let sheen_only = make_cloth_brdf(Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0), 0.5);
+        let hit = make_hit(&sheen_only, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        // Light comes back the way it came, compared per unit of cosine.
+        let retro_reflection = |direction: Vec3| {
+            let incoming = Ray::new(direction, -direction);
+            sheen_only.eval(incoming, &hit, direction).x() / direction.normalized().z()
+        };
+        assert!(retro_reflection(Vec3::new(0.95, 0.0, 0.3)) > 2.0 * retro_reflection(Vec3::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn shiny_measurements_keep_their_highlights() {
+        // A faint diffuse reflection with a bright highlight for half angles below about 4.4 degrees.
+        let stored = |index: usize| if index < 20 * 90 * 180 {12000.0} else {150.0};
+        let mut bytes = Vec::new();
+        for dimension in [90_i32, 90, 180] {
+            bytes.extend_from_slice(&dimension.to_le_bytes());
+        }
+        for _ in 0..3 {
+            for index in 0..90 * 90 * 180 {
+                bytes.extend_from_slice(&f64::to_le_bytes(stored(index)));
+            }
+        }
+        let measured = Arc::new(Measured { measurement: MeasuredBrdf::decode(&bytes).expect("valid MERL data") });
+        let material: BRDF = measured.clone();
+        let hit = make_hit(&material, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        let incoming = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let side = 128;
+        let weights: Vec<f64> = (0..side * side)
+            .filter_map(|index| measured.sample_with(incoming, &hit, sampling::stratify(index, side, side, (0.5, 0.5))))
+            .map(|reflection| reflection.attenuation.x())
+            .collect();
+        let sampled_albedo = weights.iter().sum::<f64>() / f64::from(side * side);
+        let evaluated_albedo = integrate_sphere(|direction| material.eval(incoming, &hit, direction).x(), 800);
+
+        // Weights of the highlight are far above 1, limiting them would lose most of its light.
+        assert!(weights.iter().any(|weight| *weight > 20.0));
+        assert!(evaluated_albedo > 0.8 && evaluated_albedo < 1.0, "{evaluated_albedo}");
+        assert!((sampled_albedo - evaluated_albedo).abs() < 0.03, "{sampled_albedo} {evaluated_albedo}");
+    }
+
+    #[test]
+    fn mix_blends_by_weight() {
+        let incoming = Ray::new(Vec3::new(0.3, 0.2, 1.0), Vec3::new(-0.3, -0.2, -1.0));
+        let red = make_lambertian_diffuse_brdf(Color::new(1.0, 0.0, 0.0));
+        let blue = make_lambertian_diffuse_brdf(Color::new(0.0, 0.0, 1.0));
+        let mix = make_mix_brdf(red.clone(), blue.clone(), 0.25);
+        let hit = make_hit(&mix, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let direction = Vec3::new(0.2, -0.4, 1.0);
+        let expected = 0.75 * red.eval(incoming, &hit, direction) + 0.25 * blue.eval(incoming, &hit, direction);
+        assert!((mix.eval(incoming, &hit, direction) - expected).length() < 1e-12);
+        assert!((mix.pdf(incoming, &hit, direction) - red.pdf(incoming, &hit, direction)).abs() < 1e-12);
+
+        let samples = 10000;
+        let blue_samples: f64 = (0..samples)
+            .filter_map(|_| mix.sample(incoming, &hit))
+            .map(|reflection| reflection.attenuation.z())
+            .sum();
+        assert!((blue_samples / f64::from(samples) - 0.25).abs() < 0.03);
+    }
+
+    #[test]
+    fn thin_film_glass_reflects_totally_inside() {
+        // At grazing angles the reflectance of the film rounds to just below 1, the largest random numbers must not
+        // pick refraction there.
+        let glass = Glass { ior: 1.5, albedo: Color::new(1.0, 1.0, 1.0), film: Some(ThinFilm::new(300.0, 1.38)), dispersion: None };
+        for cos_theta in [0.003, 0.05, 0.3] {
+            for u in [0.0, 0.5, 1.0 - f64::EPSILON / 2.0] {
+                assert_eq!(glass.choose_reflection(cos_theta, 1.5, false, u), (true, Vec3::new(1.0, 1.0, 1.0)), "{cos_theta} {u}");
+            }
+        }
+
+        let coated = make_thin_film_glass_brdf(1.5, Color::new(1.0, 1.0, 1.0), ThinFilm::new(300.0, 1.38));
+        let hit = HitRecord { front_face: false, ..make_hit(&coated, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)) };
+        // Light inside the glass grazing the surface, far beyond the critical angle.
+        let incoming = Ray::new(Vec3::new(-1.0, 0.0, 0.05), Vec3::new(1.0, 0.0, -0.05));
+
+        for _ in 0..1000 {
+            let reflection = coated.sample(incoming, &hit).expect("glass always scatters");
+            let direction = reflection.reflected.direction();
+            assert!(direction.x().is_finite() && direction.z() > 0.0, "{direction:?}");
+            assert!((reflection.attenuation - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn dispersive_glass_bends_blue_light_further() {
+        let prism = make_dispersive_glass_brdf(Dispersion::DIAMOND, Color::new(1.0, 1.0, 1.0));
+        let hit = make_hit(&prism, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        let incoming = Ray::new(Vec3::new(1.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, -1.0));
+        // Sideways component of the refracted direction, smaller for light that bends further.
+        let refracted = |ray: Ray| {
+            let direction = (0..1000)
+                .filter_map(|_| prism.sample(ray, &hit))
+                .map(|reflection| reflection.reflected.direction())
+                .find(|direction| direction.z() < 0.0)
+                .expect("most light is refracted");
+            -direction.normalized().x()
+        };
+
+        let blue = refracted(incoming.set_wavelength(450.0));
+        let red = refracted(incoming.set_wavelength(650.0));
+        let plain = refracted(incoming);
+        assert!(blue < red);
+        assert!((plain * Dispersion::DIAMOND.ior(spectral::D_LINE_WAVELENGTH) - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((red * Dispersion::DIAMOND.ior(650.0) - 0.5f64.sqrt()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{HitRecord, Hittable, interval::Interval, ray_math::Ray, textures::Texture, vec_math::{Onb, Vec3, dot}};
+
+/// Step used to estimate the slope of height textures, both in texture coordinates and in space.
+const BUMP_STEP: f64 = 1e-4;
+
+/// Tilts the shading normals of an object according to a tangent space normal map.
+///
+/// The red, green and blue channels of the texture map from [0, 1] to the [-1, 1] components of the normal along the
+/// tangent, the bitangent and the surface normal, as in the usual light blue normal maps. The geometric normal is
+/// left alone, so new rays still leave from the correct side of the surface.
+/// # Example
+/// ```
+/// use renders::{Sphere, brdfs, bump::NormalMapped, colors::Color, pixelbuffer::PixelBuffer, textures::ImageTexture, vec_math::Vec3};
+/// let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+/// let normal_map = ImageTexture::new(PixelBuffer::new(64, 64));
+/// let detailed = NormalMapped::new(sphere, normal_map).set_strength(0.5);
+/// ```
+pub struct NormalMapped<H: Hittable, T: Texture> {
+    object: H,
+    normal_map: T,
+    strength: f64,
+}
+
+impl<H: Hittable, T: Texture> NormalMapped<H, T> {
+    #[must_use]
+    pub const fn new(object: H, normal_map: T) -> Self {
+        Self { object, normal_map, strength: 1.0 }
+    }
+
+    /// Scales the tilt of the normals, 0 gives the flat surface and 1 the normal map as is.
+    #[must_use]
+    pub fn set_strength(self, strength: f64) -> Self {
+        Self { strength, ..self }
+    }
+}
+
+impl<H: Hittable, T: Texture> Hittable for NormalMapped<H, T> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut hit = self.object.hit(ray, ray_t)?;
+        let encoded: Vec3 = self.normal_map.filtered_value(hit.u, hit.v, hit.point, hit.footprint(ray)).into();
+        let local = 2.0 * encoded - Vec3::new(1.0, 1.0, 1.0);
+        tilt_normal(&mut hit, Vec3::new(self.strength * local.x(), self.strength * local.y(), local.z()));
+        Some(hit)
+    }
+}
+
+/// Tilts the shading normals of an object as if its surface was displaced by a height texture.
+///
+/// The slope of the height is estimated by stepping along the tangent frame, moving the texture coordinates and the
+/// position together, so both image and solid textures can be used. One unit of height corresponds to one unit of
+/// texture coordinates, use the strength to scale it. The geometric normal is left alone, so new rays still leave from
+/// the correct side of the surface.
+/// # Example
+/// ```
+/// use renders::{Sphere, brdfs, bump::BumpMapped, colors::Color, noise::NoiseTexture, vec_math::Vec3};
+/// let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+/// let bumpy = BumpMapped::new(sphere, NoiseTexture::new(3, 20.0)).set_strength(0.02);
+/// ```
+pub struct BumpMapped<H: Hittable, T: Texture> {
+    object: H,
+    height: T,
+    strength: f64,
+}
+
+impl<H: Hittable, T: Texture> BumpMapped<H, T> {
+    #[must_use]
+    pub const fn new(object: H, height: T) -> Self {
+        Self { object, height, strength: 1.0 }
+    }
+
+    /// Scales the height, negative values turn bumps into dents.
+    #[must_use]
+    pub fn set_strength(self, strength: f64) -> Self {
+        Self { strength, ..self }
+    }
+}
+
+impl<H: Hittable, T: Texture> Hittable for BumpMapped<H, T> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut hit = self.object.hit(ray, ray_t)?;
+        let (tangent, bitangent, _) = tangent_frame(&hit);
+
+        let height = self.height.scalar_value(hit.u, hit.v, hit.point);
+        let height_u = self.height.scalar_value(hit.u + BUMP_STEP, hit.v, hit.point + BUMP_STEP * tangent);
+        let height_v = self.height.scalar_value(hit.u, hit.v + BUMP_STEP, hit.point + BUMP_STEP * bitangent);
+        let slope_u = self.strength * (height_u - height) / BUMP_STEP;
+        let slope_v = self.strength * (height_v - height) / BUMP_STEP;
+
+        tilt_normal(&mut hit, Vec3::new(-slope_u, -slope_v, 1.0));
+        Some(hit)
+    }
+}
+
+/// Returns the tangent, bitangent and normal on the outside of the surface at a hit.
+fn tangent_frame(hit: &HitRecord) -> (Vec3, Vec3, Vec3) {
+    let normal = if hit.front_face {hit.normal} else {-hit.normal};
+    let frame = Onb::from_normal_and_tangent(normal, hit.tangent);
+    (frame.tangent(), frame.bitangent(), normal)
+}
+
+/// Replaces the shading normal of a hit with a normal given in its tangent frame, keeping the tangent perpendicular.
+fn tilt_normal(hit: &mut HitRecord, local: Vec3) {
+    let (tangent, bitangent, normal) = tangent_frame(hit);
+    let tilted = local.x() * tangent + local.y() * bitangent + local.z() * normal;
+    if tilted.near_zero() {
+        return;
+    }
+    let tilted = tilted.normalized();
+
+    hit.normal = if hit.front_face {tilted} else {-tilted};
+    hit.tangent = (tangent - dot(tangent, tilted) * tilted).normalized();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sphere, brdfs, colors::Color};
+
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _point: Vec3) -> Color {
+            Color::new(u, u, u)
+        }
+    }
+
+    fn sphere() -> Sphere {
+        Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn ray_towards(target: Vec3) -> Ray {
+        Ray::new(Vec3::new(0.0, 0.0, 5.0), target - Vec3::new(0.0, 0.0, 5.0))
+    }
+
+    #[test]
+    fn sphere_tangent_frame_follows_texture_coordinates() {
+        let target = Vec3::new(0.3, 0.2, 0.9);
+        let hit = sphere().hit(ray_towards(target), Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+        let (tangent, bitangent, _) = tangent_frame(&hit);
+
+        let along_u = sphere().hit(ray_towards(hit.point + 0.01 * tangent), Interval::new(0.0, f64::INFINITY)).expect("still on the sphere");
+        let along_v = sphere().hit(ray_towards(hit.point + 0.01 * bitangent), Interval::new(0.0, f64::INFINITY)).expect("still on the sphere");
+        assert!(along_u.u > hit.u && (along_u.v - hit.v).abs() < 1e-3);
+        assert!(along_v.v > hit.v && (along_v.u - hit.u).abs() < 1e-3);
+    }
+
+    #[test]
+    fn flat_maps_keep_the_normal() {
+        let ray = ray_towards(Vec3::new(0.3, 0.2, 0.9));
+        let plain = sphere().hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+        let mapped = NormalMapped::new(sphere(), Color::new(0.5, 0.5, 1.0)).hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+        let bumped = BumpMapped::new(sphere(), 0.7).hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+
+        assert!((mapped.normal - plain.normal).length() < 1e-6);
+        assert!((bumped.normal - plain.normal).length() < 1e-6);
+    }
+
+    #[test]
+    fn bumps_tilt_the_normal_downhill() {
+        let ray = ray_towards(Vec3::new(0.3, 0.2, 0.9));
+        let plain = sphere().hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+        let bumped = BumpMapped::new(sphere(), Ramp).set_strength(0.5).hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+
+        // The height rises along the tangent, so the normal leans back against it.
+        assert!(dot(bumped.normal, plain.tangent) < -0.01);
+        assert!((bumped.geometric_normal - plain.geometric_normal).length() < 1e-12);
+        assert!(dot(bumped.normal, bumped.tangent).abs() < 1e-9);
+    }
+
+    #[test]
+    fn spawned_rays_leave_the_surface() {
+        let sphere = sphere();
+        let hit = sphere.hit(ray_towards(Vec3::new(0.3, 0.2, 0.9)), Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+
+        let outwards = hit.spawn_ray(hit.geometric_normal + Vec3::new(0.0, 0.0, 0.1));
+        assert!(sphere.hit(outwards, Interval::new(0.0, f64::INFINITY)).is_none());
+
+        let inwards = hit.spawn_ray(-hit.geometric_normal);
+        let exit = sphere.hit(inwards, Interval::new(0.0, f64::INFINITY)).expect("ray through the sphere");
+        assert!(exit.t > 1.0);
+    }
+}
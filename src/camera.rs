@@ -1,4 +1,4 @@
-use crate::{Hittable, colors::Color, interval::Interval, pixelbuffer::PixelBuffer, ray_math::Ray, vec_math::{Vec3, cross, unit_vector}};
+use crate::{Hittable, colors::Color, interval::Interval, pixelbuffer::PixelBuffer, ray_math::{Ray, RayDifferentials}, spectral, vec_math::{Vec3, cross, unit_vector}};
 use rand;
 use std::{
     fs::File, io::{BufWriter, prelude::*}, sync::{Arc, Mutex}, thread
@@ -30,6 +30,7 @@ use std::{
 /// - `vfov`: 50.0,
 /// - `look_from`: (0.0, 0.0, 0.0)
 /// - `look_at`: (0.0, 0.0, -1.0)
+/// - `spectral`: false
 #[derive(Debug, PartialEq)]
 pub struct CameraBuilder {
     aspect_ratio: f64,
@@ -42,6 +43,7 @@ pub struct CameraBuilder {
     look_from: Vec3,
     look_at: Vec3,
     vfov: f64,
+    spectral: bool,
 }
 
 impl CameraBuilder {
@@ -58,6 +60,7 @@ impl CameraBuilder {
             vfov: 50.0,
             look_from: Vec3::new(0.0, 0.0, 0.0),
             look_at: Vec3::new(0.0, 0.0, -1.0),
+            spectral: false,
         }
     }
 
@@ -74,6 +77,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -90,6 +94,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -106,6 +111,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -122,6 +128,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -138,6 +145,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -154,6 +162,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -170,6 +179,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -186,6 +196,7 @@ impl CameraBuilder {
             vfov,
             look_at: self.look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -202,6 +213,7 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at,
             look_from: self.look_from,
+            spectral: self.spectral,
         }
     }
 
@@ -218,6 +230,27 @@ impl CameraBuilder {
             vfov: self.vfov,
             look_at: self.look_at,
             look_from,
+            spectral: self.spectral,
+        }
+    }
+
+    /// Renders spectrally, with each camera ray carrying a single randomly chosen wavelength that is converted to a
+    /// color afterwards. Needed for dispersion, at the cost of some color noise. The RGB colors of materials and
+    /// lights are upsampled to smooth spectra.
+    #[must_use]
+    pub const fn set_spectral(self, spectral: bool) -> Self {
+        Self {
+            aspect_ratio: self.aspect_ratio,
+            image_width: self.image_width,
+            camera_up: self.camera_up,
+            focal_length: self.focal_length,
+            samples_per_pixel: self.samples_per_pixel,
+            max_bounces: self.max_bounces,
+            nr_threads: self.nr_threads,
+            vfov: self.vfov,
+            look_at: self.look_at,
+            look_from: self.look_from,
+            spectral,
         }
     }
 
@@ -262,6 +295,7 @@ impl CameraBuilder {
             samples_per_pixel: self.samples_per_pixel,
             max_bounces: self.max_bounces,
             nr_threads: self.nr_threads,
+            spectral: self.spectral,
         }
     }
 }
@@ -307,6 +341,7 @@ pub struct Camera {
     samples_per_pixel: u32,
     max_bounces: u32,
     nr_threads: usize,
+    spectral: bool,
 }
 
 impl Camera {
@@ -337,14 +372,21 @@ impl Camera {
                             })
                     };
                     for (x, y) in pixel_iter {
-                        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                        let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
 
                         for _ in 0..self.samples_per_pixel {
                             let camera_ray = self.get_ray(x.try_into().expect("An image with a width representable as a usize but not as a u32 is almost impossible."), y.try_into().expect("An image with height representable as a usize but not as a u32 is almost impossible."));
-                            pixel_color += ray_color(camera_ray, self.max_bounces, world) * self.pixel_samples_scale;
+                            let sample = if self.spectral {
+                                let wavelength = spectral::sample_wavelength();
+                                ray_radiance(camera_ray.set_wavelength(wavelength), self.max_bounces, world) * spectral::wavelength_to_rgb(wavelength)
+                            } else {
+                                ray_color(camera_ray, self.max_bounces, world)
+                            };
+                            pixel_color += sample * self.pixel_samples_scale;
                         }
 
-                        pixel_color = pixel_color.to_gamma();
+                        // Samples may carry more light than fits in a color, so they are only clamped once averaged.
+                        let pixel_color = Color::from(pixel_color).to_gamma();
 
                         let mut out = output.lock().expect("This lock should be available in a reasonable time.");
                         out.set_pixel(x, y, pixel_color);
@@ -367,7 +409,15 @@ impl Camera {
             + ((f64::from(y) + offset.y()) * self.pixel_delta_v);
                     
         let ray_direction = pixel_sample - self.center;
-        Ray::new(self.center, ray_direction)
+
+        // Several samples share each pixel, so the footprint of one sample is smaller than the pixel.
+        let footprint_scale = (1.0 / f64::from(self.samples_per_pixel).sqrt()).max(0.125);
+        Ray::new(self.center, ray_direction).set_differentials(RayDifferentials {
+            x_origin: self.center,
+            x_direction: ray_direction + footprint_scale * self.pixel_delta_u,
+            y_origin: self.center,
+            y_direction: ray_direction + footprint_scale * self.pixel_delta_v,
+        })
     }
 }
 
@@ -379,21 +429,45 @@ fn sample_square() -> Vec3 {
     )
 }
 
-fn ray_color<T: Hittable>(ray: Ray, depth: u32, world: &T) -> Color {
+/// Radiance arriving along `ray`, not limited to 1 until the samples of a pixel are averaged.
+fn ray_color<T: Hittable>(ray: Ray, depth: u32, world: &T) -> Vec3 {
     if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
+        return Vec3::default();
     }
 
     world
         .hit(ray, Interval::new(0.00001, f64::INFINITY))
         .map_or_else(
-            || {
-                let a = 0.5 * (ray.direction().normalized().y() + 1.0);
-                ((1.0 - a) * Vec3::new(1.0, 1.0, 1.0) + a * Vec3::new(0.5, 0.7, 1.0)).into()
-            },
-            |hit| (hit.brdf)(ray, &hit).map_or_else(
-                || Color::new(0.0, 0.0, 0.0),
+            || background(ray),
+            |hit| hit.brdf.emitted(ray, &hit) + hit.brdf.sample(ray, &hit).map_or_else(
+                Vec3::default,
                 |reflection| reflection.attenuation * ray_color(reflection.reflected, depth - 1, world)
             )
         )
 }
+
+/// Radiance at the wavelength carried by `ray`, the spectral counterpart of [`ray_color`].
+fn ray_radiance<T: Hittable>(ray: Ray, depth: u32, world: &T) -> f64 {
+    let Some(wavelength) = ray.wavelength() else {
+        return 0.0;
+    };
+    if depth == 0 {
+        return 0.0;
+    }
+    let upsample = |color: Vec3| spectral::rgb_to_spectrum(color, wavelength);
+
+    world
+        .hit(ray, Interval::new(0.00001, f64::INFINITY))
+        .map_or_else(
+            || upsample(background(ray)),
+            |hit| upsample(hit.brdf.emitted(ray, &hit)) + hit.brdf.sample(ray, &hit).map_or(
+                0.0,
+                |reflection| upsample(reflection.attenuation) * ray_radiance(reflection.reflected.set_wavelength(wavelength), depth - 1, world)
+            )
+        )
+}
+
+fn background(ray: Ray) -> Vec3 {
+    let a = 0.5 * (ray.direction().normalized().y() + 1.0);
+    (1.0 - a) * Vec3::new(1.0, 1.0, 1.0) + a * Vec3::new(0.5, 0.7, 1.0)
+}
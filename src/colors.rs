@@ -29,6 +29,43 @@ impl Color {
         Self{vector}
     }
 
+    /// Approximates the color of a black body radiator at `temperature` Kelvin, in linear color space.
+    /// The result is normalized so that the brightest channel is 1, it does not contain the intensity of the radiation.
+    /// # Example:
+    /// ```
+    /// use renders::colors::Color;
+    /// let candle = Color::from_temperature(1900.0);  // Deep orange.
+    /// let daylight = Color::from_temperature(6500.0); // Close to white.
+    /// ```
+    #[must_use]
+    pub fn from_temperature(temperature: f64) -> Self {
+        // Fit of the black body curve by Tanner Helland, producing gamma space values in the range [0, 255].
+        let t = temperature.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+        };
+        let g = if t <= 66.0 {
+            99.470_802_586_1f64.mul_add(t.ln(), -161.119_568_166_1)
+        } else {
+            288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_731_223_1f64.mul_add((t - 10.0).ln(), -305.044_792_730_7)
+        };
+
+        let gamma_to_linear = |component: f64| {
+            let component = (component / 255.0).clamp(0.0, 1.0);
+            component * component
+        };
+        Self::new(gamma_to_linear(r), gamma_to_linear(g), gamma_to_linear(b))
+    }
+
     /// Converts a color from linear colorspace into the gamma colorspace.
     /// # Example:
     /// ```
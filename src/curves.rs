@@ -0,0 +1,251 @@
+use crate::{HitRecord, Hittable, brdfs::BRDF, calculate_face_normal, first_opaque_hit, interval::Interval, ray_math::Ray, vec_math::{Vec3, cross, dot, lerp, orthonormal_basis}};
+
+/// A thin cubic Bézier curve with a width that changes linearly along its length, for hair, fur and grass.
+///
+/// The curve is intersected directly as a ribbon that always faces the incoming ray,
+/// by recursively splitting it until the pieces are flat enough to be treated as line segments.
+/// The normal is bent across the width of the ribbon, so it shades like a thin cylinder.
+///
+/// # Example
+/// ```
+/// use renders::{brdfs, curves::Curve, vec_math::Vec3};
+/// let strand = Curve::new(
+///     [
+///         Vec3::new(0.0, 0.0, -1.0),
+///         Vec3::new(0.0, 0.3, -1.0),
+///         Vec3::new(0.1, 0.6, -1.0),
+///         Vec3::new(0.3, 0.8, -1.0),
+///     ],
+///     0.01,
+///     0.002,
+///     brdfs::make_hair_brdf(brdfs::hair_absorption_from_melanin(1.3, 0.0), 0.3, 0.3, 2.0),
+/// );
+/// ```
+pub struct Curve {
+    control_points: [Vec3; 4],
+    start_width: f64,
+    end_width: f64,
+    max_depth: u32,
+    surface_shader: BRDF,
+}
+
+impl Curve {
+    /// Creates a curve from its four Bézier control points and its width at the start and end.
+    /// # Panics
+    /// Panics if either width is negative.
+    #[must_use]
+    pub fn new(control_points: [Vec3; 4], start_width: f64, end_width: f64, surface_shader: BRDF) -> Self {
+        assert!(start_width >= 0.0 && end_width >= 0.0);
+
+        // Split until the pieces deviate from a straight line by less than 5% of the width.
+        let [p0, p1, p2, p3] = control_points;
+        let deviation = (p0 - 2.0 * p1 + p2).length().max((p1 - 2.0 * p2 + p3).length());
+        let epsilon = start_width.max(end_width) * 0.05;
+        let max_depth = if epsilon > 0.0 {
+            let depth = (std::f64::consts::SQRT_2 * 6.0 * deviation / (8.0 * epsilon)).log2() / 2.0;
+            #[allow(clippy::cast_possible_truncation)]
+            #[allow(clippy::cast_sign_loss)]
+            let depth = depth.clamp(0.0, 10.0).round() as u32;
+            depth
+        } else {
+            0
+        };
+
+        Self { control_points, start_width, end_width, max_depth, surface_shader }
+    }
+
+    fn width(&self, u: f64) -> f64 {
+        lerp(self.start_width, self.end_width, u)
+    }
+
+    /// Searches the piece of the curve between `u0` and `u1` for the closest intersection with a ray along the z axis.
+    /// The control points are in the coordinate system of the ray, returns the distance along the ray and u.
+    fn recursive_hit(&self, points: [Vec3; 4], u0: f64, u1: f64, depth: u32, z_range: Interval) -> Option<(f64, f64)> {
+        let half_width = 0.5 * self.width(u0).max(self.width(u1));
+        let (low, high) = points.iter().fold(
+            (Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY), Vec3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY)),
+            |(low, high), point| (
+                Vec3::new(low.x().min(point.x()), low.y().min(point.y()), low.z().min(point.z())),
+                Vec3::new(high.x().max(point.x()), high.y().max(point.y()), high.z().max(point.z())),
+            ),
+        );
+        if low.x() - half_width > 0.0 || high.x() + half_width < 0.0
+            || low.y() - half_width > 0.0 || high.y() + half_width < 0.0
+            || low.z() - half_width > z_range.max() || high.z() + half_width < z_range.min()
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let [first, second] = split_bezier(points);
+            let middle = 0.5 * (u0 + u1);
+            let near = self.recursive_hit(first, u0, middle, depth - 1, z_range);
+            let far_range = Interval::new(z_range.min(), near.map_or_else(|| z_range.max(), |(z, _)| z));
+            return self.recursive_hit(second, middle, u1, depth - 1, far_range).or(near);
+        }
+
+        self.segment_hit(points, u0, u1, z_range)
+    }
+
+    /// Intersects a piece of the curve that is flat enough to be treated as a line segment.
+    fn segment_hit(&self, points: [Vec3; 4], u0: f64, u1: f64, z_range: Interval) -> Option<(f64, f64)> {
+        let [p0, p1, p2, p3] = points;
+
+        // Reject hits beyond the ends of the segment, the neighbouring segments are responsible for them.
+        let start_edge = (p1.y() - p0.y()).mul_add(-p0.y(), p0.x() * (p0.x() - p1.x()));
+        let end_edge = (p2.y() - p3.y()).mul_add(-p3.y(), p3.x() * (p3.x() - p2.x()));
+        if start_edge < 0.0 || end_edge < 0.0 {
+            return None;
+        }
+
+        let segment = Vec3::new(p3.x() - p0.x(), p3.y() - p0.y(), 0.0);
+        let square_length = segment.square_length();
+        if square_length == 0.0 {
+            return None;
+        }
+        let w = dot(Vec3::new(-p0.x(), -p0.y(), 0.0), segment) / square_length;
+        let u = lerp(u0, u1, w).clamp(u0, u1);
+        let hit_width = self.width(u);
+
+        let (closest, _) = evaluate_bezier(points, w.clamp(0.0, 1.0));
+        let square_distance = closest.x().mul_add(closest.x(), closest.y() * closest.y());
+        if square_distance > hit_width * hit_width * 0.25 || !z_range.surrounds(closest.z()) {
+            return None;
+        }
+
+        Some((closest.z(), u))
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.intersect(ray, ray_t), rand::random::<f64>)
+    }
+}
+
+impl Curve {
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let direction_length = ray.direction().length();
+        let forward = ray.direction() / direction_length;
+        let (right, up) = orthonormal_basis(forward);
+        let to_ray_space = |point: Vec3| {
+            let relative = point - ray.origin();
+            Vec3::new(dot(relative, right), dot(relative, up), dot(relative, forward))
+        };
+        let points = self.control_points.map(to_ray_space);
+
+        let z_range = Interval::new(ray_t.min() * direction_length, ray_t.max() * direction_length);
+        let (z, u) = self.recursive_hit(points, 0.0, 1.0, self.max_depth, z_range)?;
+
+        let t = z / direction_length;
+        let point = ray.at(t);
+        let (center, derivative) = evaluate_bezier(self.control_points, u);
+        let tangent = if derivative.near_zero() {
+            unit_vector_or(self.control_points[3] - self.control_points[0], right)
+        } else {
+            derivative.normalized()
+        };
+
+        // Bend the normal from the side facing the ray towards the edges, like the surface of a cylinder.
+        let facing = unit_vector_or(-forward - dot(-forward, tangent) * tangent, up);
+        let side = cross(facing, tangent);
+        let half_width = 0.5 * self.width(u);
+        let h = if half_width > 0.0 { (dot(point - center, side) / half_width).clamp(-1.0, 1.0) } else { 0.0 };
+        let outward_normal = (h.mul_add(-h, 1.0).sqrt() * facing + h * side).normalized();
+        let (front_face, normal) = calculate_face_normal(ray, outward_normal);
+
+        Some(HitRecord {
+            t,
+            point,
+            normal,
+            // The ribbon facing the ray is the surface that is actually intersected.
+            geometric_normal: facing,
+            tangent,
+            // u runs along the curve and v across its width.
+            u,
+            v: 0.5 * (h + 1.0),
+            dpdu: derivative,
+            dpdv: 2.0 * half_width * side,
+            front_face,
+            brdf: self.surface_shader.clone(),
+        })
+    }
+}
+
+fn unit_vector_or(v: Vec3, fallback: Vec3) -> Vec3 {
+    if v.near_zero() { fallback } else { v.normalized() }
+}
+
+fn lerp_points(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+/// Splits a cubic Bézier curve in the middle into two curves.
+fn split_bezier(points: [Vec3; 4]) -> [[Vec3; 4]; 2] {
+    let [p0, p1, p2, p3] = points;
+    let first = [0.5 * (p0 + p1), 0.5 * (p1 + p2), 0.5 * (p2 + p3)];
+    let second = [0.5 * (first[0] + first[1]), 0.5 * (first[1] + first[2])];
+    let middle = 0.5 * (second[0] + second[1]);
+    [[p0, first[0], second[0], middle], [middle, second[1], first[2], p3]]
+}
+
+/// Evaluates a cubic Bézier curve at `u`, returns the point and the derivative.
+fn evaluate_bezier(points: [Vec3; 4], u: f64) -> (Vec3, Vec3) {
+    let [p0, p1, p2, p3] = points;
+    let a = [lerp_points(p0, p1, u), lerp_points(p1, p2, u), lerp_points(p2, p3, u)];
+    let b = [lerp_points(a[0], a[1], u), lerp_points(a[1], a[2], u)];
+    let derivative = if (b[1] - b[0]).near_zero() { p3 - p0 } else { 3.0 * (b[1] - b[0]) };
+    (lerp_points(b[0], b[1], u), derivative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{brdfs, colors::Color};
+
+    #[test]
+    fn ray_hits_middle_of_curve() {
+        let curve = Curve::new(
+            [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-0.3, 0.0, 0.0), Vec3::new(0.3, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+            0.1,
+            0.1,
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        );
+        let ray = Ray::new(Vec3::new(0.2, 0.0, 4.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit = curve.hit(ray, Interval::new(0.00001, f64::INFINITY)).expect("Ray is aimed at the curve.");
+
+        assert!((hit.t - 2.0).abs() < 1e-6);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-3);
+        assert!(hit.tangent.x().abs() > 0.99);
+    }
+
+    #[test]
+    fn ray_misses_beside_curve() {
+        let curve = Curve::new(
+            [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-0.3, 0.0, 0.0), Vec3::new(0.3, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+            0.1,
+            0.1,
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        );
+        let beside = Ray::new(Vec3::new(0.2, 0.06, 4.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(curve.hit(beside, Interval::new(0.00001, f64::INFINITY)).is_none());
+
+        let past_end = Ray::new(Vec3::new(1.2, 0.0, 4.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(curve.hit(past_end, Interval::new(0.00001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn normal_bends_towards_edges() {
+        let curve = Curve::new(
+            [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-0.3, 0.0, 0.0), Vec3::new(0.3, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+            0.1,
+            0.1,
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        );
+        let near_edge = Ray::new(Vec3::new(0.0, 0.04, 4.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = curve.hit(near_edge, Interval::new(0.00001, f64::INFINITY)).expect("Ray is within the width of the curve.");
+
+        assert!(hit.normal.y() > 0.5);
+        assert!(hit.normal.z() > 0.0);
+    }
+}
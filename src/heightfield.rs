@@ -0,0 +1,372 @@
+use std::{io, path::Path};
+use crate::{HitRecord, Hittable, brdfs::BRDF, calculate_face_normal, first_opaque_hit, image::{ColorEncoding, Image}, interval::Interval, invalid_data, ray_math::{Ray, box_crossings}, vec_math::{Vec3, cross, dot}};
+
+/// Terrain defined by a regular grid of heights.
+///
+/// The grid is spread over the rectangle from `min` to `min + size` on the xz plane, with sample (0, 0) at `min`.
+/// A height of 0 lies at `min.y()` and a height of 1 at `min.y() + size.y()`.
+///
+/// Rays are intersected by walking through the cells of the grid they pass over, so only the few cells
+/// below the ray are tested. Normals are interpolated over each cell for smooth shading.
+///
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, heightfield::Heightfield, vec_math::Vec3};
+/// let heights = vec![
+///     0.0, 0.2, 0.0,
+///     0.2, 1.0, 0.2,
+///     0.0, 0.2, 0.0,
+/// ];
+/// let hill = Heightfield::new(
+///     heights, 3, 3,
+///     Vec3::new(-1.0, -0.5, -2.0),
+///     Vec3::new(2.0, 0.5, 2.0),
+///     brdfs::make_lambertian_diffuse_brdf(Color::new(0.3, 0.6, 0.2)),
+/// );
+/// ```
+pub struct Heightfield {
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    resolution_x: usize,
+    resolution_z: usize,
+    min: Vec3,
+    size: Vec3,
+    cell_width: f64,
+    cell_depth: f64,
+    min_height: f64,
+    max_height: f64,
+    surface_shader: BRDF,
+}
+
+impl Heightfield {
+    /// Creates a heightfield from `resolution_x * resolution_z` heights, ordered x fastest and then z.
+    /// # Panics
+    /// Panics if either resolution is smaller than 2, if the amount of heights does not match the resolution
+    /// or if the size is not positive along x and z.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(heights: Vec<f64>, resolution_x: usize, resolution_z: usize, min: Vec3, size: Vec3, surface_shader: BRDF) -> Self {
+        assert!(resolution_x >= 2 && resolution_z >= 2);
+        assert_eq!(heights.len(), resolution_x * resolution_z);
+        assert!(size.x() > 0.0 && size.z() > 0.0);
+
+        let cell_width = size.x() / (resolution_x - 1) as f64;
+        let cell_depth = size.z() / (resolution_z - 1) as f64;
+        let min_height = heights.iter().copied().fold(f64::INFINITY, f64::min);
+        let max_height = heights.iter().copied().fold(-f64::INFINITY, f64::max);
+
+        let mut heightfield = Self {
+            heights,
+            normals: Vec::new(),
+            resolution_x,
+            resolution_z,
+            min,
+            size,
+            cell_width,
+            cell_depth,
+            min_height,
+            max_height,
+            surface_shader,
+        };
+        heightfield.normals = heightfield.vertex_normals();
+        heightfield
+    }
+
+    /// Creates a heightfield from a grayscale image file such as a PGM, where black is height 0 and white is height 1.
+    /// # Errors
+    /// Returns an error if the file can not be read, or one of kind `InvalidData` if it is not a supported image
+    /// or is smaller than 2 by 2 pixels.
+    /// # Panics
+    /// Panics if the size is not positive along x and z.
+    pub fn from_pgm<P: AsRef<Path>>(path: P, min: Vec3, size: Vec3, surface_shader: BRDF) -> io::Result<Self> {
+        Self::from_image(&Image::load(path, ColorEncoding::Linear)?, min, size, surface_shader)
+    }
+
+    /// Creates a heightfield from the red channel of an image, where pixel (0, 0) is the sample at `min`.
+    /// # Errors
+    /// Returns an error of kind `InvalidData` if the image is smaller than 2 by 2 pixels.
+    /// # Panics
+    /// Panics if the size is not positive along x and z.
+    pub fn from_image(image: &Image, min: Vec3, size: Vec3, surface_shader: BRDF) -> io::Result<Self> {
+        if image.width() < 2 || image.height() < 2 {
+            return Err(invalid_data("heightfield images need at least 2 by 2 pixels"));
+        }
+        let heights = (0..image.height())
+            .flat_map(|z| (0..image.width()).map(move |x| image.get_pixel(x, z).x()))
+            .collect();
+        Ok(Self::new(heights, image.width(), image.height(), min, size, surface_shader))
+    }
+
+    fn vertex(&self, x: usize, z: usize) -> Vec3 {
+        #[allow(clippy::cast_precision_loss)]
+        Vec3::new(
+            self.cell_width.mul_add(x as f64, self.min.x()),
+            self.size.y().mul_add(self.height(x, z), self.min.y()),
+            self.cell_depth.mul_add(z as f64, self.min.z()),
+        )
+    }
+
+    fn height(&self, x: usize, z: usize) -> f64 {
+        self.heights[z * self.resolution_x + x]
+    }
+
+    /// Calculates smooth normals at every grid point from the slope to its neighbours.
+    fn vertex_normals(&self) -> Vec<Vec3> {
+        let mut normals = Vec::with_capacity(self.heights.len());
+        for z in 0..self.resolution_z {
+            for x in 0..self.resolution_x {
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(self.resolution_x - 1));
+                let (back, front) = (z.saturating_sub(1), (z + 1).min(self.resolution_z - 1));
+                let along_x = self.vertex(right, z) - self.vertex(left, z);
+                let along_z = self.vertex(x, front) - self.vertex(x, back);
+                normals.push(cross(along_z, along_x).normalized());
+            }
+        }
+        normals
+    }
+
+    fn normal(&self, x: usize, z: usize) -> Vec3 {
+        self.normals[z * self.resolution_x + x]
+    }
+
+    /// Intersects the two triangles of the cell at (`column`, `row`), returns the ray parameter and interpolated normal.
+    fn hit_cell(&self, ray: Ray, ray_t: Interval, column: usize, row: usize) -> Option<(f64, Vec3, Vec3)> {
+        let corners = [(column, row), (column + 1, row), (column + 1, row + 1), (column, row + 1)];
+        let mut closest: Option<(f64, Vec3, Vec3)> = None;
+
+        for [a, b, c] in [[corners[0], corners[2], corners[1]], [corners[0], corners[3], corners[2]]] {
+            let limit = closest.map_or_else(|| ray_t.max(), |(t, _, _)| t);
+            let Some((t, u, v)) = hit_triangle(
+                ray,
+                Interval::new(ray_t.min(), limit),
+                self.vertex(a.0, a.1),
+                self.vertex(b.0, b.1),
+                self.vertex(c.0, c.1),
+            ) else {
+                continue;
+            };
+            let normal = (1.0 - u - v) * self.normal(a.0, a.1) + u * self.normal(b.0, b.1) + v * self.normal(c.0, c.1);
+            let (vertex_a, vertex_b, vertex_c) = (self.vertex(a.0, a.1), self.vertex(b.0, b.1), self.vertex(c.0, c.1));
+            let facet_normal = cross(vertex_b - vertex_a, vertex_c - vertex_a).normalized();
+            closest = Some((t, normal.normalized(), facet_normal));
+        }
+
+        closest
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.intersect(ray, ray_t), rand::random::<f64>)
+    }
+}
+
+impl Heightfield {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::similar_names)]
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let bounds_min = Vec3::new(self.min.x(), self.size.y().mul_add(self.min_height, self.min.y()), self.min.z());
+        let bounds_max = Vec3::new(
+            self.min.x() + self.size.x(),
+            self.size.y().mul_add(self.max_height, self.min.y()),
+            self.min.z() + self.size.z(),
+        );
+        let (entry, exit) = box_crossings(bounds_min, bounds_max, ray, ray_t)?;
+
+        // Walk through the cells below the ray with a 2d digital differential analyzer.
+        let start = ray.at(entry);
+        let column_count = self.resolution_x - 1;
+        let row_count = self.resolution_z - 1;
+        let to_cell = |offset: f64, cell_size: f64, cells: usize| {
+            ((offset / cell_size).floor().max(0.0) as usize).min(cells - 1)
+        };
+        let mut cell_x = to_cell(start.x() - self.min.x(), self.cell_width, column_count);
+        let mut cell_z = to_cell(start.z() - self.min.z(), self.cell_depth, row_count);
+
+        let direction = ray.direction();
+        let axis_setup = |origin: f64, direction: f64, cell: usize, cell_size: f64, grid_min: f64| {
+            if direction.abs() < 1e-12 {
+                (f64::INFINITY, f64::INFINITY, 0_isize)
+            } else if direction > 0.0 {
+                let boundary = cell_size.mul_add((cell + 1) as f64, grid_min);
+                ((boundary - origin) / direction, cell_size / direction, 1)
+            } else {
+                let boundary = cell_size.mul_add(cell as f64, grid_min);
+                ((boundary - origin) / direction, -cell_size / direction, -1)
+            }
+        };
+        let (mut next_x, delta_x, step_x) = axis_setup(ray.origin().x(), direction.x(), cell_x, self.cell_width, self.min.x());
+        let (mut next_z, delta_z, step_z) = axis_setup(ray.origin().z(), direction.z(), cell_z, self.cell_depth, self.min.z());
+
+        let mut cell_entry = entry;
+        loop {
+            let cell_exit = next_x.min(next_z).min(exit);
+
+            // Skip cells where the ray passes fully above or below the terrain.
+            let heights = [
+                self.height(cell_x, cell_z), self.height(cell_x + 1, cell_z),
+                self.height(cell_x, cell_z + 1), self.height(cell_x + 1, cell_z + 1),
+            ];
+            let cell_low = self.size.y().mul_add(heights.iter().copied().fold(f64::INFINITY, f64::min), self.min.y());
+            let cell_high = self.size.y().mul_add(heights.iter().copied().fold(-f64::INFINITY, f64::max), self.min.y());
+            let (y_entry, y_exit) = (ray.at(cell_entry).y(), ray.at(cell_exit).y());
+            let above = y_entry.min(y_exit) > cell_high + 1e-9;
+            let below = y_entry.max(y_exit) < cell_low - 1e-9;
+
+            if !above && !below
+                && let Some((t, outward_normal, facet_normal)) = self.hit_cell(ray, ray_t, cell_x, cell_z)
+            {
+                let point = ray.at(t);
+                let (front_face, normal) = calculate_face_normal(ray, outward_normal);
+                let geometric_normal = if front_face {facet_normal} else {-facet_normal};
+                // Follow the slope of the cell along x for u and along -z for v.
+                let dpdu = self.size.x() * Vec3::new(1.0, -facet_normal.x() / facet_normal.y(), 0.0);
+                let dpdv = -self.size.z() * Vec3::new(0.0, -facet_normal.z() / facet_normal.y(), 1.0);
+                // Terrain normals always point up, so the x axis is never parallel to them.
+                let tangent = (Vec3::new(1.0, 0.0, 0.0) - outward_normal.x() * outward_normal).normalized();
+                return Some(HitRecord {
+                    t,
+                    point,
+                    normal,
+                    geometric_normal,
+                    tangent,
+                    // Texture coordinates span the terrain along x and -z, so images seen from above are not mirrored.
+                    u: ((point.x() - self.min.x()) / self.size.x()).clamp(0.0, 1.0),
+                    v: (1.0 - (point.z() - self.min.z()) / self.size.z()).clamp(0.0, 1.0),
+                    dpdu,
+                    dpdv,
+                    front_face,
+                    brdf: self.surface_shader.clone(),
+                });
+            }
+
+            if cell_exit >= exit {
+                return None;
+            }
+
+            if next_x < next_z {
+                cell_x = cell_x.checked_add_signed(step_x).filter(|next| *next < column_count)?;
+                next_x += delta_x;
+            } else {
+                cell_z = cell_z.checked_add_signed(step_z).filter(|next| *next < row_count)?;
+                next_z += delta_z;
+            }
+            cell_entry = cell_exit;
+        }
+    }
+}
+
+/// Möller-Trumbore ray triangle intersection, returns the ray parameter and barycentric coordinates of `b` and `c`.
+#[allow(clippy::many_single_char_names)]
+fn hit_triangle(ray: Ray, ray_t: Interval, a: Vec3, b: Vec3, c: Vec3) -> Option<(f64, f64, f64)> {
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = cross(ray.direction(), edge_2);
+    let determinant = dot(edge_1, p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let to_origin = ray.origin() - a;
+    let u = dot(to_origin, p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = cross(to_origin, edge_1);
+    let v = dot(ray.direction(), q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = dot(edge_2, q) * inverse_determinant;
+    ray_t.surrounds(t).then_some((t, u, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{brdfs, colors::Color};
+
+    #[test]
+    fn flat_terrain_hit_from_above() {
+        let terrain = Heightfield::new(
+            vec![0.5; 16],
+            4,
+            4,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 1.0, 2.0),
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        );
+        let ray = Ray::new(Vec3::new(0.3, 2.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+        let hit = terrain.hit(ray, Interval::new(0.00001, f64::INFINITY)).expect("Ray points straight at the terrain.");
+
+        assert!((hit.t - 1.5).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn grazing_ray_walks_cells() {
+        let mut heights = vec![0.0; 25];
+        heights[3 * 5 + 4] = 1.0;
+        let terrain = Heightfield::new(
+            heights,
+            5,
+            5,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 1.0, 2.0),
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        );
+
+        // Travels low over the flat part and hits the peak near the far corner.
+        let ray = Ray::new(Vec3::new(-2.0, 0.1, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        let hit = terrain.hit(ray, Interval::new(0.00001, f64::INFINITY)).expect("Ray should hit the slope of the peak.");
+        assert!(hit.point.x() > 0.5 && hit.point.x() < 1.0);
+        assert!(hit.normal.x() < 0.0);
+
+        let miss = Ray::new(Vec3::new(-2.0, 1.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(terrain.hit(miss, Interval::new(0.00001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn heightfields_from_images() {
+        let image = Image::decode(b"P2\n# comment\n2 2\n4\n0 1\n2 4\n", ColorEncoding::Linear).expect("Valid plain PGM.");
+        let terrain = Heightfield::from_image(
+            &image,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 1.0, 2.0),
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        )
+        .expect("Large enough image.");
+        assert_eq!(terrain.heights, vec![0.0, 0.25, 0.5, 1.0]);
+
+        let line = Image::decode(b"P5 2 1 255\n\x00\xff", ColorEncoding::Linear).expect("Valid binary PGM.");
+        let error = Heightfield::from_image(
+            &line,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 1.0, 2.0),
+            brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)),
+        );
+        assert_eq!(error.map(|_| ()).map_err(|error| error.kind()), Err(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn cutout_terrain_lets_rays_through() {
+        let terrain = |opacity: f64| Heightfield::new(
+            vec![0.5; 4],
+            2,
+            2,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 1.0, 2.0),
+            brdfs::make_cutout_brdf(brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)), opacity),
+        );
+        let ray = Ray::new(Vec3::new(0.3, 2.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+
+        assert!(terrain(0.0).hit(ray, Interval::new(0.00001, f64::INFINITY)).is_none());
+        assert!(terrain(1.0).hit(ray, Interval::new(0.00001, f64::INFINITY)).is_some());
+    }
+}
@@ -0,0 +1,865 @@
+//! Images of linear, unclamped colors and the loading of them from files.
+//!
+//! | Format        | Variants                                                          |
+//! |---------------|-------------------------------------------------------------------|
+//! | PNG           | gray, RGB, paletted, with alpha, 1 to 16 bits, interlaced         |
+//! | PBM, PGM, PPM | plain text (P1, P2, P3) and binary (P4, P5, P6), 8 and 16 bits    |
+//! | PFM           | gray (Pf) and RGB (PF), either byte order                         |
+//! | Radiance HDR  | RGBE, flat and run length encoded scanlines                       |
+//! | TGA           | gray, true color and color mapped, raw and run length encoded     |
+//!
+//! The format is recognized from the contents of the file. Integer formats store values in [0, 1] that are
+//! decoded according to a [`ColorEncoding`], floating point formats are always linear.
+
+use std::{fs, io::{self, Read}, path::Path};
+use crate::{inflate::zlib_decompress, invalid_data, pixelbuffer::PixelBuffer, vec_math::Vec3};
+
+/// How the color channels of integer image formats map to linear values. Alpha is always linear.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ColorEncoding {
+    /// Colors are stored with the sRGB transfer function, as in nearly all photos and color textures.
+    #[default]
+    Srgb,
+    /// Values are stored as is, for data such as normal, roughness and height maps.
+    Linear,
+}
+
+impl ColorEncoding {
+    /// Converts a stored value in [0, 1] to a linear value.
+    /// # Example
+    /// ```
+    /// use renders::image::ColorEncoding;
+    /// assert!((ColorEncoding::Srgb.to_linear(0.5) - 0.214).abs() < 0.001);
+    /// assert_eq!(ColorEncoding::Linear.to_linear(0.5), 0.5);
+    /// ```
+    #[must_use]
+    pub fn to_linear(self, value: f64) -> f64 {
+        match self {
+            Self::Srgb if value <= 0.040_45 => value / 12.92,
+            Self::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Self::Linear => value,
+        }
+    }
+}
+
+/// An image of linear colors that are not limited to [0, 1], with an alpha channel.
+/// # Example
+/// ```no_run
+/// use renders::{image::{ColorEncoding, Image}, textures::ImageTexture};
+/// let albedo = ImageTexture::new(Image::load("bricks.png", ColorEncoding::Srgb)?);
+/// let roughness = ImageTexture::new(Image::load("bricks_roughness.png", ColorEncoding::Linear)?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Image {
+    colors: Vec<Vec3>,
+    alpha: Vec<f64>,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    /// Creates an image with all pixels black and opaque.
+    /// # Panics
+    /// Panics if the number of pixels overflows.
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        let pixels = width.checked_mul(height).expect("image too large");
+        Self {
+            colors: vec![Vec3::new(0.0, 0.0, 0.0); pixels],
+            alpha: vec![1.0; pixels],
+            width,
+            height,
+        }
+    }
+
+    /// Returns the width of the image in pixels.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Gets the color at pixel x, y, counted from the top left.
+    /// # Panics
+    /// Panics if x or y fail a bounds check.
+    #[must_use]
+    pub fn get_pixel(&self, x: usize, y: usize) -> Vec3 {
+        assert!(x < self.width && y < self.height);
+        self.colors[y * self.width + x]
+    }
+
+    /// Sets the color at pixel x, y, counted from the top left.
+    /// # Panics
+    /// Panics if x or y fail a bounds check.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Vec3) {
+        assert!(x < self.width && y < self.height);
+        self.colors[y * self.width + x] = color;
+    }
+
+    /// Gets the alpha at pixel x, y, where 0 is fully transparent and 1 is opaque.
+    /// # Panics
+    /// Panics if x or y fail a bounds check.
+    #[must_use]
+    pub fn get_alpha(&self, x: usize, y: usize) -> f64 {
+        assert!(x < self.width && y < self.height);
+        self.alpha[y * self.width + x]
+    }
+
+    /// Sets the alpha at pixel x, y.
+    /// # Panics
+    /// Panics if x or y fail a bounds check.
+    pub fn set_alpha(&mut self, x: usize, y: usize, alpha: f64) {
+        assert!(x < self.width && y < self.height);
+        self.alpha[y * self.width + x] = alpha;
+    }
+
+    /// Loads an image file in any of the supported formats.
+    /// # Errors
+    /// Returns an error if the file can not be read, or one of kind `InvalidData` if it is not a supported image.
+    pub fn load<P: AsRef<Path>>(path: P, encoding: ColorEncoding) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?, encoding)
+    }
+
+    /// Reads an image in any of the supported formats.
+    /// # Errors
+    /// Returns an error if reading fails, or one of kind `InvalidData` if the data is not a supported image.
+    pub fn read<R: Read>(mut reader: R, encoding: ColorEncoding) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::decode(&bytes, encoding)
+    }
+
+    /// Decodes an image in any of the supported formats from the contents of a file.
+    /// # Errors
+    /// Returns an error of kind `InvalidData` if the data is not a supported image.
+    pub fn decode(bytes: &[u8], encoding: ColorEncoding) -> io::Result<Self> {
+        match bytes {
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => decode_png(&bytes[8..], encoding),
+            [b'P', b'F' | b'f', ..] => decode_pfm(bytes),
+            [b'P', b'1'..=b'6', ..] => decode_pnm(bytes, encoding),
+            [b'#', b'?', ..] => decode_hdr(bytes),
+            // Targa files have no signature at the start.
+            _ => decode_tga(bytes, encoding),
+        }
+    }
+}
+
+/// Converts the displayed colors of a pixel buffer into an opaque image without changing their values.
+impl From<PixelBuffer> for Image {
+    fn from(buffer: PixelBuffer) -> Self {
+        let mut image = Self::new(buffer.width(), buffer.height());
+        for (color, x, y) in &buffer {
+            image.set_pixel(x, y, color.into());
+        }
+        image
+    }
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() != 13 {
+            return Err(invalid_data("invalid PNG header"));
+        }
+        let header = Self {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize,
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+            bit_depth: data[8],
+            color_type: data[9],
+            interlaced: data[12] == 1,
+        };
+
+        let valid_depth = match header.color_type {
+            0 => matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(header.bit_depth, 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(header.bit_depth, 8 | 16),
+            _ => false,
+        };
+        if !valid_depth || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+            return Err(invalid_data("unsupported PNG format"));
+        }
+        if header.width == 0 || header.height == 0 {
+            return Err(invalid_data("PNG image has no pixels"));
+        }
+        Ok(header)
+    }
+
+    const fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// Starting pixel and spacing of the seven passes of Adam7 interlacing.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2),
+];
+
+/// Decodes the chunks of a PNG file following its signature.
+fn decode_png(mut chunks: &[u8], encoding: ColorEncoding) -> io::Result<Image> {
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+
+    loop {
+        let length = chunks.get(..4).ok_or_else(|| invalid_data("truncated PNG file"))?;
+        let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+        let chunk = chunks.get(4..length + 12).ok_or_else(|| invalid_data("truncated PNG file"))?;
+        let (kind_and_data, crc) = chunk.split_at(length + 4);
+        if crc32(kind_and_data) != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+            return Err(invalid_data("PNG chunk checksum mismatch"));
+        }
+        let (kind, data) = kind_and_data.split_at(4);
+
+        match kind {
+            b"IHDR" => header = Some(PngHeader::parse(data)?),
+            b"PLTE" => palette = data,
+            b"tRNS" => transparency = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            // Chunks starting with a lowercase letter are safe to ignore, other chunks are needed to decode the image.
+            _ if kind[0].is_ascii_uppercase() => return Err(invalid_data("unsupported critical PNG chunk")),
+            _ => {}
+        }
+        chunks = &chunks[length + 12..];
+    }
+
+    let header = header.ok_or_else(|| invalid_data("missing PNG header"))?;
+    let data = zlib_decompress(&compressed)?;
+    let bits_per_pixel = header.channels() * usize::from(header.bit_depth);
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8);
+
+    // Every pixel is stored once among the passes, so an image larger than the data can not be valid.
+    if image_size(header.width, header.height, bits_per_pixel)? / 8 > data.len() {
+        return Err(invalid_data("missing PNG pixel data"));
+    }
+
+    let passes: &[_] = if header.interlaced {&ADAM7_PASSES} else {&[(0, 0, 1, 1)]};
+    let mut image = Image::new(header.width, header.height);
+    let mut offset = 0;
+    for &(x0, y0, step_x, step_y) in passes {
+        let pass_width = header.width.saturating_sub(x0).div_ceil(step_x);
+        let pass_height = header.height.saturating_sub(y0).div_ceil(step_y);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+
+        let row_length = (pass_width * bits_per_pixel).div_ceil(8);
+        let filtered = data.get(offset..offset + pass_height * (row_length + 1)).ok_or_else(|| invalid_data("missing PNG pixel data"))?;
+        offset += filtered.len();
+        let rows = unfilter(filtered, row_length, bytes_per_pixel)?;
+
+        for (row, y) in rows.chunks_exact(row_length).zip((y0..).step_by(step_y)) {
+            for (column, x) in (0..pass_width).zip((x0..).step_by(step_x)) {
+                let (color, alpha) = png_pixel(&header, row, column, palette, transparency, encoding)?;
+                image.set_pixel(x, y, color);
+                image.set_alpha(x, y, alpha);
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Reverses the filter applied to each row of PNG pixel data, returning the rows without their filter bytes.
+fn unfilter(filtered: &[u8], row_length: usize, bytes_per_pixel: usize) -> io::Result<Vec<u8>> {
+    let mut rows = vec![0u8; filtered.len() / (row_length + 1) * row_length];
+    for (index, line) in filtered.chunks_exact(row_length + 1).enumerate() {
+        let (before, current) = rows.split_at_mut(index * row_length);
+        let previous = before.get(before.len().saturating_sub(row_length)..).filter(|_| index > 0);
+        let current = &mut current[..row_length];
+
+        for i in 0..row_length {
+            let left = if i >= bytes_per_pixel {current[i - bytes_per_pixel]} else {0};
+            let above = previous.map_or(0, |previous| previous[i]);
+            let above_left = if i >= bytes_per_pixel {previous.map_or(0, |previous| previous[i - bytes_per_pixel])} else {0};
+            let prediction = match line[0] {
+                0 => 0,
+                1 => left,
+                2 => above,
+                3 => u8::try_from(u16::midpoint(u16::from(left), u16::from(above))).unwrap_or(u8::MAX),
+                4 => paeth(left, above, above_left),
+                _ => return Err(invalid_data("invalid PNG filter type")),
+            };
+            current[i] = line[i + 1].wrapping_add(prediction);
+        }
+    }
+    Ok(rows)
+}
+
+/// Predicts a byte from whichever of its neighbours is closest to the gradient through them.
+fn paeth(left: u8, above: u8, above_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(above) - i16::from(above_left);
+    let distance = |value: u8| (estimate - i16::from(value)).abs();
+    if distance(left) <= distance(above) && distance(left) <= distance(above_left) {
+        left
+    } else if distance(above) <= distance(above_left) {
+        above
+    } else {
+        above_left
+    }
+}
+
+/// Returns the linear color and alpha of a pixel in an unfiltered PNG row.
+fn png_pixel(header: &PngHeader, row: &[u8], column: usize, palette: &[u8], transparency: &[u8], encoding: ColorEncoding) -> io::Result<(Vec3, f64)> {
+    let channels = header.channels();
+    let depth = usize::from(header.bit_depth);
+    let sample = |channel: usize| -> u16 {
+        let index = column * channels + channel;
+        match depth {
+            16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+            8 => u16::from(row[index]),
+            _ => {
+                let bit = index * depth;
+                u16::from(row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+            }
+        }
+    };
+    let max = f64::from((1u32 << depth) - 1);
+    let normalize = |value: u16| f64::from(value) / max;
+    let color = |red: u16, green: u16, blue: u16| {
+        Vec3::new(encoding.to_linear(normalize(red)), encoding.to_linear(normalize(green)), encoding.to_linear(normalize(blue)))
+    };
+    // Gray and true color images may mark a single color as transparent.
+    let key = |channel: usize| transparency.get(2 * channel..2 * channel + 2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+
+    Ok(match header.color_type {
+        0 => {
+            let gray = sample(0);
+            (color(gray, gray, gray), if key(0) == Some(gray) {0.0} else {1.0})
+        }
+        2 => {
+            let (red, green, blue) = (sample(0), sample(1), sample(2));
+            let transparent = [key(0), key(1), key(2)] == [Some(red), Some(green), Some(blue)];
+            (color(red, green, blue), if transparent {0.0} else {1.0})
+        }
+        3 => {
+            let index = usize::from(sample(0));
+            let entry = palette.get(3 * index..3 * index + 3).ok_or_else(|| invalid_data("PNG palette index out of range"))?;
+            let alpha = transparency.get(index).map_or(1.0, |alpha| f64::from(*alpha) / 255.0);
+            let to_linear = |value: u8| encoding.to_linear(f64::from(value) / 255.0);
+            (Vec3::new(to_linear(entry[0]), to_linear(entry[1]), to_linear(entry[2])), alpha)
+        }
+        4 => {
+            let gray = sample(0);
+            (color(gray, gray, gray), normalize(sample(1)))
+        }
+        _ => (color(sample(0), sample(1), sample(2)), normalize(sample(3))),
+    })
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+#[allow(clippy::cast_possible_truncation)]
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 == 1 {0xedb8_8320 ^ (value >> 1)} else {value >> 1};
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+    table
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(u32::MAX, |crc, &byte| CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Splits the text header of PNM, PFM and Radiance files into tokens, skipping comments.
+struct Tokens<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn next_token(&mut self) -> Option<&'a str> {
+        loop {
+            match self.bytes.get(self.position)? {
+                b'#' => while self.bytes.get(self.position).is_some_and(|byte| *byte != b'\n') { self.position += 1; },
+                byte if byte.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position]).ok()
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> io::Result<T> {
+        self.next_token()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| invalid_data("invalid number in image header"))
+    }
+
+    /// Returns the binary data after the header, which is separated from it by exactly one whitespace character.
+    fn binary_data(&self) -> &'a [u8] {
+        self.bytes.get(self.position + 1..).unwrap_or_default()
+    }
+}
+
+/// Decodes the plain text and binary variants of PBM, PGM and PPM images.
+fn decode_pnm(bytes: &[u8], encoding: ColorEncoding) -> io::Result<Image> {
+    let mut tokens = Tokens::new(bytes);
+    let magic = tokens.next_token().unwrap_or_default();
+    let width: usize = tokens.number()?;
+    let height: usize = tokens.number()?;
+    let (channels, bitmap) = match magic {
+        "P1" | "P4" => (1, true),
+        "P2" | "P5" => (1, false),
+        "P3" | "P6" => (3, false),
+        _ => return Err(invalid_data("not a PNM image")),
+    };
+    let max_value: u16 = if bitmap {1} else {tokens.number()?};
+    if max_value == 0 {
+        return Err(invalid_data("invalid maximum value in PNM image"));
+    }
+    let count = image_size(width, height, channels)?;
+    // Every sample takes at least one byte, or one bit in packed bitmaps, so larger headers can not be backed by the data.
+    let least_size = if magic == "P4" {image_size(width.div_ceil(8), height, 1)?} else {count};
+    if least_size > tokens.binary_data().len() {
+        return Err(invalid_data("missing PNM pixel data"));
+    }
+
+    let samples: Vec<u16> = match magic {
+        "P1" => {
+            // Bits may be written without whitespace between them.
+            let digits = tokens.binary_data().iter().filter(|byte| matches!(byte, b'0' | b'1'));
+            digits.map(|digit| u16::from(*digit == b'1')).take(count).collect()
+        }
+        "P2" | "P3" => (0..count).map(|_| tokens.number()).collect::<io::Result<_>>()?,
+        "P4" => {
+            let row_length = width.div_ceil(8);
+            let data = tokens.binary_data().get(..row_length * height).ok_or_else(|| invalid_data("missing PNM pixel data"))?;
+            data.chunks_exact(row_length)
+                .flat_map(|row| (0..width).map(move |x| u16::from(row[x / 8] >> (7 - x % 8) & 1)))
+                .collect()
+        }
+        _ if max_value < 256 => tokens.binary_data().iter().take(count).map(|value| u16::from(*value)).collect(),
+        _ => tokens.binary_data()
+            .chunks_exact(2)
+            .take(count)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect(),
+    };
+    if samples.len() != count {
+        return Err(invalid_data("missing PNM pixel data"));
+    }
+
+    let mut image = Image::new(width, height);
+    for (index, pixel) in samples.chunks_exact(channels).enumerate() {
+        let channel = |value: u16| {
+            let value = f64::from(value.min(max_value)) / f64::from(max_value);
+            // In bitmaps 1 is black.
+            if bitmap {1.0 - value} else {encoding.to_linear(value)}
+        };
+        let color = if channels == 1 {
+            let gray = channel(pixel[0]);
+            Vec3::new(gray, gray, gray)
+        } else {
+            Vec3::new(channel(pixel[0]), channel(pixel[1]), channel(pixel[2]))
+        };
+        image.set_pixel(index % width, index / width, color);
+    }
+    Ok(image)
+}
+
+/// Decodes a portable float map, which stores its rows from bottom to top.
+fn decode_pfm(bytes: &[u8]) -> io::Result<Image> {
+    let mut tokens = Tokens::new(bytes);
+    let channels = match tokens.next_token() {
+        Some("PF") => 3,
+        Some("Pf") => 1,
+        _ => return Err(invalid_data("not a PFM image")),
+    };
+    let width: usize = tokens.number()?;
+    let height: usize = tokens.number()?;
+    // The sign of the scale gives the byte order, negative for little endian.
+    let scale: f64 = tokens.number()?;
+
+    let data = tokens.binary_data().get(..image_size(width, height, channels * 4)?).ok_or_else(|| invalid_data("missing PFM pixel data"))?;
+    let values: Vec<f64> = data
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            f64::from(if scale < 0.0 {f32::from_le_bytes(bytes)} else {f32::from_be_bytes(bytes)})
+        })
+        .collect();
+
+    let mut image = Image::new(width, height);
+    for (index, pixel) in values.chunks_exact(channels).enumerate() {
+        let color = if channels == 1 {Vec3::new(pixel[0], pixel[0], pixel[0])} else {Vec3::new(pixel[0], pixel[1], pixel[2])};
+        image.set_pixel(index % width, height - 1 - index / width, color);
+    }
+    Ok(image)
+}
+
+/// Decodes a Radiance RGBE image, where each pixel stores three mantissas sharing one exponent.
+fn decode_hdr(bytes: &[u8]) -> io::Result<Image> {
+    let mut lines = bytes.split(|byte| *byte == b'\n');
+    let mut header_length = 0;
+    let mut exposure = 1.0;
+    for line in lines.by_ref() {
+        header_length += line.len() + 1;
+        let line = std::str::from_utf8(line).map_err(|_| invalid_data("invalid Radiance header"))?.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") && format != "32-bit_rle_rgbe" {
+            return Err(invalid_data("unsupported Radiance pixel format"));
+        }
+        if let Some(value) = line.strip_prefix("EXPOSURE=") {
+            exposure *= value.trim().parse::<f64>().map_err(|_| invalid_data("invalid Radiance exposure"))?;
+        }
+    }
+
+    let resolution = lines.next().ok_or_else(|| invalid_data("missing Radiance resolution"))?;
+    header_length += resolution.len() + 1;
+    let mut tokens = Tokens::new(resolution);
+    let flipped = match tokens.next_token() {
+        Some("-Y") => false,
+        Some("+Y") => true,
+        _ => return Err(invalid_data("unsupported Radiance image orientation")),
+    };
+    let height: usize = tokens.number()?;
+    if tokens.next_token() != Some("+X") {
+        return Err(invalid_data("unsupported Radiance image orientation"));
+    }
+    let width: usize = tokens.number()?;
+
+    let mut data = bytes.get(header_length..).unwrap_or_default();
+    // Run length encoded scanlines are the smallest, with runs of at most 127 pixels in each of the four channels.
+    let least_row_size = if (8..0x8000).contains(&width) {4 + 8 * width.div_ceil(127)} else {image_size(width, 4, 1)?};
+    if image_size(least_row_size, height, 1)? > data.len() {
+        return Err(invalid_data("missing Radiance pixel data"));
+    }
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![0u8; width * 4];
+    for row in 0..height {
+        data = read_rgbe_scanline(data, &mut scanline)?;
+        let y = if flipped {height - 1 - row} else {row};
+        for (x, pixel) in scanline.chunks_exact(4).enumerate() {
+            let scale = if pixel[3] == 0 {0.0} else {(f64::from(pixel[3]) - 136.0).exp2() / exposure};
+            image.set_pixel(x, y, scale * Vec3::new(f64::from(pixel[0]), f64::from(pixel[1]), f64::from(pixel[2])));
+        }
+    }
+    Ok(image)
+}
+
+/// Reads one scanline of RGBE pixels, returning the remaining data.
+fn read_rgbe_scanline<'a>(data: &'a [u8], scanline: &mut [u8]) -> io::Result<&'a [u8]> {
+    let width = scanline.len() / 4;
+    let truncated = || invalid_data("missing Radiance pixel data");
+
+    // Run length encoded scanlines start with two 2s followed by the width, storing each channel separately.
+    let encoded = (8..0x8000).contains(&width) && data.len() >= 4 && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+    if !encoded {
+        let (pixels, rest) = data.split_at_checked(scanline.len()).ok_or_else(truncated)?;
+        scanline.copy_from_slice(pixels);
+        return Ok(rest);
+    }
+    if usize::from(data[2]) << 8 | usize::from(data[3]) != width {
+        return Err(invalid_data("Radiance scanline width mismatch"));
+    }
+
+    let mut position = 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = usize::from(*data.get(position).ok_or_else(truncated)?);
+            position += 1;
+            let (run, literal) = if count > 128 {(count - 128, false)} else {(count, true)};
+            if run == 0 || x + run > width {
+                return Err(invalid_data("invalid Radiance run length"));
+            }
+            for offset in 0..run {
+                scanline[(x + offset) * 4 + channel] = *data.get(position + if literal {offset} else {0}).ok_or_else(truncated)?;
+            }
+            position += if literal {run} else {1};
+            x += run;
+        }
+    }
+    Ok(&data[position..])
+}
+
+/// Decodes a Truevision TGA image.
+fn decode_tga(bytes: &[u8], encoding: ColorEncoding) -> io::Result<Image> {
+    let header = bytes.get(..18).ok_or_else(|| invalid_data("not a supported image format"))?;
+    let image_type = header[2];
+    let color_mapped = matches!(image_type, 1 | 9);
+    let gray = matches!(image_type, 3 | 11);
+    let run_length_encoded = image_type >= 9;
+    if !matches!(image_type, 1 | 2 | 3 | 9 | 10 | 11) || header[1] > 1 || (color_mapped && header[1] != 1) {
+        return Err(invalid_data("not a supported image format"));
+    }
+
+    let map_first = usize::from(u16::from_le_bytes([header[3], header[4]]));
+    let map_length = usize::from(u16::from_le_bytes([header[5], header[6]]));
+    let map_depth = header[7];
+    let width = usize::from(u16::from_le_bytes([header[12], header[13]]));
+    let height = usize::from(u16::from_le_bytes([header[14], header[15]]));
+    let depth = header[16];
+    let has_alpha = header[17] & 0x0f > 0;
+    let right_to_left = header[17] & 0x10 != 0;
+    let top_to_bottom = header[17] & 0x20 != 0;
+
+    let valid_depth = match (color_mapped, gray) {
+        (true, _) => matches!(depth, 8 | 16) && matches!(map_depth, 15 | 16 | 24 | 32),
+        (_, true) => matches!(depth, 8 | 16),
+        _ => matches!(depth, 15 | 16 | 24 | 32),
+    };
+    if !valid_depth {
+        return Err(invalid_data("unsupported TGA pixel depth"));
+    }
+
+    // The color map follows the image id and is stored even for images that do not use it.
+    let map_start = 18 + usize::from(header[0]);
+    let map_size = if header[1] == 1 {map_length * usize::from(map_depth).div_ceil(8)} else {0};
+    let color_map = bytes.get(map_start..map_start + map_size).ok_or_else(|| invalid_data("missing TGA color map"))?;
+    let data = &bytes[map_start + map_size..];
+
+    let pixel_size = usize::from(depth).div_ceil(8);
+    let count = image_size(width, height, 1)?;
+    let pixels = if run_length_encoded {
+        expand_tga_runs(data, pixel_size, count)?
+    } else {
+        data.get(..image_size(count, pixel_size, 1)?).ok_or_else(|| invalid_data("missing TGA pixel data"))?.to_vec()
+    };
+
+    let to_linear = |value: f64| encoding.to_linear(value);
+    let true_color = |pixel: &[u8], depth: u8| -> (Vec3, f64) {
+        match depth {
+            15 | 16 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let channel = |shift: u16| to_linear(f64::from((value >> shift) & 31) / 31.0);
+                let alpha = if depth == 16 && has_alpha {f64::from(value >> 15)} else {1.0};
+                (Vec3::new(channel(10), channel(5), channel(0)), alpha)
+            }
+            _ => {
+                let channel = |index: usize| to_linear(f64::from(pixel[index]) / 255.0);
+                let alpha = if depth == 32 && has_alpha {f64::from(pixel[3]) / 255.0} else {1.0};
+                (Vec3::new(channel(2), channel(1), channel(0)), alpha)
+            }
+        }
+    };
+
+    let mut image = Image::new(width, height);
+    for (index, pixel) in pixels.chunks_exact(pixel_size).enumerate() {
+        let (color, alpha) = if color_mapped {
+            let entry = usize::from(pixel[0]) | pixel.get(1).map_or(0, |high| usize::from(*high) << 8);
+            let entry_size = usize::from(map_depth).div_ceil(8);
+            let offset = entry.checked_sub(map_first).filter(|offset| *offset < map_length).ok_or_else(|| invalid_data("TGA color map index out of range"))?;
+            true_color(&color_map[offset * entry_size..(offset + 1) * entry_size], map_depth)
+        } else if gray {
+            let value = to_linear(f64::from(pixel[0]) / 255.0);
+            (Vec3::new(value, value, value), pixel.get(1).map_or(1.0, |alpha| f64::from(*alpha) / 255.0))
+        } else {
+            true_color(pixel, depth)
+        };
+
+        let (column, row) = (index % width, index / width);
+        let x = if right_to_left {width - 1 - column} else {column};
+        let y = if top_to_bottom {row} else {height - 1 - row};
+        image.set_pixel(x, y, color);
+        image.set_alpha(x, y, alpha);
+    }
+    Ok(image)
+}
+
+/// Returns the size of `width` by `height` pixels of `pixel_size` each, failing if it overflows.
+fn image_size(width: usize, height: usize, pixel_size: usize) -> io::Result<usize> {
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(pixel_size))
+        .ok_or_else(|| invalid_data("image too large"))
+}
+
+/// Expands run length encoded TGA packets into `count` pixels of `pixel_size` bytes.
+fn expand_tga_runs(mut data: &[u8], pixel_size: usize, count: usize) -> io::Result<Vec<u8>> {
+    let truncated = || invalid_data("missing TGA pixel data");
+    // Every packet holds at most 128 pixels.
+    if count.div_ceil(128) * (pixel_size + 1) > data.len() {
+        return Err(truncated());
+    }
+    let mut pixels = Vec::with_capacity(count * pixel_size);
+    while pixels.len() < count * pixel_size {
+        let (&packet, rest) = data.split_first().ok_or_else(truncated)?;
+        let length = usize::from(packet & 0x7f) + 1;
+        if packet & 0x80 == 0 {
+            let (raw, rest) = rest.split_at_checked(length * pixel_size).ok_or_else(truncated)?;
+            pixels.extend_from_slice(raw);
+            data = rest;
+        } else {
+            let (pixel, rest) = rest.split_at_checked(pixel_size).ok_or_else(truncated)?;
+            for _ in 0..length {
+                pixels.extend_from_slice(pixel);
+            }
+            data = rest;
+        }
+    }
+    pixels.truncate(count * pixel_size);
+    Ok(pixels)
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    /// Builds a PNG file, storing the pixel data uncompressed.
+    fn png(header: [u8; 13], extra_chunks: &[(&[u8; 4], &[u8])], pixel_data: &[u8]) -> Vec<u8> {
+        let length = u16::try_from(pixel_data.len()).expect("small test image");
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(pixel_data);
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in pixel_data {
+            a = (a + u32::from(*byte)) % 65521;
+            b = (b + a) % 65521;
+        }
+        zlib.extend_from_slice(&(b << 16 | a).to_be_bytes());
+
+        let mut file = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        let chunks = std::iter::once((b"IHDR", &header[..])).chain(extra_chunks.iter().copied()).chain([(b"IDAT", &zlib[..]), (b"IEND", &[][..])]);
+        for (kind, data) in chunks {
+            let mut kind_and_data = kind.to_vec();
+            kind_and_data.extend_from_slice(data);
+            file.extend_from_slice(&u32::try_from(data.len()).expect("small chunk").to_be_bytes());
+            file.extend_from_slice(&kind_and_data);
+            file.extend_from_slice(&crc32(&kind_and_data).to_be_bytes());
+        }
+        file
+    }
+
+    fn header(width: u8, height: u8, bit_depth: u8, color_type: u8, interlace: u8) -> [u8; 13] {
+        [0, 0, 0, width, 0, 0, 0, height, bit_depth, color_type, 0, 0, interlace]
+    }
+
+    #[test]
+    fn png_color_types() {
+        // Two rows of two RGBA pixels, the second row using the up filter to repeat the first.
+        let data = [0, 255, 0, 0, 255, 0, 0, 255, 128, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+        let image = Image::decode(&png(header(2, 2, 8, 6, 0), &[], &data), ColorEncoding::Srgb).expect("valid PNG");
+        assert_eq!(image.get_pixel(0, 0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.get_alpha(1, 0), 128.0 / 255.0);
+        assert_eq!(image.get_pixel(1, 1), Vec3::new(0.0, 0.0, 1.0));
+
+        // 16 bit gray stays linear when asked to.
+        let data = [0, 0x80, 0x00, 0xff, 0xff];
+        let image = Image::decode(&png(header(2, 1, 16, 0, 0), &[], &data), ColorEncoding::Linear).expect("valid PNG");
+        assert_eq!(image.get_pixel(0, 0), Vec3::new(32768.0 / 65535.0, 32768.0 / 65535.0, 32768.0 / 65535.0));
+        assert_eq!(image.get_pixel(1, 0), Vec3::new(1.0, 1.0, 1.0));
+
+        // 1 bit palette with a transparent first entry.
+        let palette: &[u8] = &[0, 0, 0, 255, 255, 255];
+        let data = [0, 0b0100_0000];
+        let image = Image::decode(&png(header(3, 1, 1, 3, 0), &[(b"PLTE", palette), (b"tRNS", &[0])], &data), ColorEncoding::Srgb).expect("valid PNG");
+        assert_eq!(image.get_alpha(0, 0), 0.0);
+        assert_eq!(image.get_pixel(1, 0), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(image.get_alpha(1, 0), 1.0);
+    }
+
+    #[test]
+    fn interlaced_png_matches_plain_png() {
+        // 3x3 gray image holding the values 0 to 8; Adam7 spreads it over passes 1, 4, 5, 6 and 7.
+        let plain = [0, 0, 1, 2, 0, 3, 4, 5, 0, 6, 7, 8];
+        let interlaced = [0, 0, 0, 2, 0, 6, 8, 0, 1, 0, 7, 0, 3, 4, 5];
+        let plain = Image::decode(&png(header(3, 3, 8, 0, 0), &[], &plain), ColorEncoding::Linear).expect("valid PNG");
+        let interlaced = Image::decode(&png(header(3, 3, 8, 0, 1), &[], &interlaced), ColorEncoding::Linear).expect("valid PNG");
+        assert_eq!(plain, interlaced);
+    }
+
+    #[test]
+    fn corrupt_png_is_rejected() {
+        let mut file = png(header(1, 1, 8, 0, 0), &[], &[0, 7]);
+        let last = file.len() - 20;
+        file[last] ^= 1;
+        assert_eq!(Image::decode(&file, ColorEncoding::Srgb).expect_err("bad checksum").kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn netpbm_formats() {
+        let text = Image::decode(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 51\n", ColorEncoding::Linear).expect("valid PPM");
+        assert_eq!(text.get_pixel(1, 0), Vec3::new(0.0, 0.0, 0.2));
+
+        let binary = Image::decode(b"P5 2 1 255\n\xff\x00", ColorEncoding::Srgb).expect("valid PGM");
+        assert_eq!(binary.get_pixel(0, 0), Vec3::new(1.0, 1.0, 1.0));
+
+        let bitmap = Image::decode(b"P1 3 1 101", ColorEncoding::Srgb).expect("valid PBM");
+        assert_eq!(bitmap.get_pixel(1, 0), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(bitmap.get_pixel(2, 0), Vec3::new(0.0, 0.0, 0.0));
+
+        let mut float = b"Pf\n1 2\n-1.0\n".to_vec();
+        float.extend_from_slice(&0.25f32.to_le_bytes());
+        float.extend_from_slice(&4.0f32.to_le_bytes());
+        let float = Image::decode(&float, ColorEncoding::Srgb).expect("valid PFM");
+        assert_eq!(float.get_pixel(0, 0), Vec3::new(4.0, 4.0, 4.0));
+        assert_eq!(float.get_pixel(0, 1), Vec3::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn radiance_run_length_encoding() {
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        file.extend_from_slice(&[2, 2, 0, 8]);
+        // Red is a run, green and blue are literals, the exponents are a run.
+        file.extend_from_slice(&[136, 128]);
+        file.extend_from_slice(&[8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        file.extend_from_slice(&[136, 0]);
+        file.extend_from_slice(&[136, 129]);
+        let image = Image::decode(&file, ColorEncoding::Srgb).expect("valid HDR");
+        assert_eq!(image.get_pixel(3, 0), Vec3::new(1.0, 3.0 / 128.0, 0.0));
+    }
+
+    #[test]
+    fn targa_run_length_encoding() {
+        // 2x2 true color image stored bottom to top, a run of two red pixels followed by two raw pixels.
+        let mut file = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0];
+        file.extend_from_slice(&[0x81, 0, 0, 255]);
+        file.extend_from_slice(&[0x01, 255, 0, 0, 0, 255, 0]);
+        let image = Image::decode(&file, ColorEncoding::Srgb).expect("valid TGA");
+        assert_eq!(image.get_pixel(0, 1), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.get_pixel(0, 0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(image.get_pixel(1, 0), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn huge_headers_are_rejected() {
+        let invalid_kind = |bytes: &[u8]| Image::decode(bytes, ColorEncoding::Srgb).map_err(|error| error.kind());
+        let png_header = [0x80, 0, 0, 0, 0x80, 0, 0, 0, 8, 0, 0, 0, 0];
+        assert_eq!(invalid_kind(&png(png_header, &[], &[0, 7])), Err(io::ErrorKind::InvalidData));
+        assert_eq!(invalid_kind(b"#?RADIANCE\n\n-Y 100000 +X 100000\n\x02\x02"), Err(io::ErrorKind::InvalidData));
+        assert_eq!(invalid_kind(b"P5 100000 100000 255\n\x00"), Err(io::ErrorKind::InvalidData));
+        assert_eq!(invalid_kind(b"P6 4294967296 4294967296 255\n\x00"), Err(io::ErrorKind::InvalidData));
+        assert_eq!(invalid_kind(b"PF 4294967296 4294967296 -1.0\n\x00"), Err(io::ErrorKind::InvalidData));
+        assert_eq!(invalid_kind(&[0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 255, 255, 32, 0, 0xff]), Err(io::ErrorKind::InvalidData));
+    }
+}
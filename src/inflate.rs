@@ -0,0 +1,308 @@
+//! Decompression of zlib streams, as used by PNG images.
+//!
+//! Implements the DEFLATE format from RFC 1951 inside the zlib wrapper from RFC 1950. Huffman codes are decoded
+//! one bit at a time from canonical code length counts, which is slow compared to table based decoders but
+//! plenty fast for loading textures.
+
+use std::io;
+use crate::invalid_data;
+
+const MAX_CODE_LENGTH: usize = 15;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// Order in which the lengths of the code length code are stored in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses a zlib stream, checking its header and Adler-32 checksum.
+/// # Errors
+/// Returns an error of kind `InvalidData` if the stream is malformed, uses a preset dictionary or fails the checksum.
+/// # Example
+/// ```
+/// use renders::inflate::zlib_decompress;
+/// // "hi" stored in a single uncompressed block.
+/// let stream = [0x78, 0x01, 0x01, 0x02, 0x00, 0xfd, 0xff, b'h', b'i', 0x01, 0x3b, 0x00, 0xd2];
+/// assert_eq!(zlib_decompress(&stream).unwrap(), b"hi");
+/// ```
+pub fn zlib_decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let [method, flags, ..] = *data else {
+        return Err(invalid_data("zlib stream is too short"));
+    };
+    if method & 0x0f != 8 || (u16::from(method) << 8 | u16::from(flags)) % 31 != 0 {
+        return Err(invalid_data("invalid zlib header"));
+    }
+    if flags & 0x20 != 0 {
+        return Err(invalid_data("zlib preset dictionaries are not supported"));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let output = inflate_blocks(&mut reader)?;
+
+    let checksum = reader.aligned_bytes(4)?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&output) {
+        return Err(invalid_data("zlib checksum mismatch"));
+    }
+    Ok(output)
+}
+
+/// Decompresses raw DEFLATE data without a zlib wrapper.
+/// # Errors
+/// Returns an error of kind `InvalidData` if the data is malformed.
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    inflate_blocks(&mut BitReader::new(data))
+}
+
+fn inflate_blocks(reader: &mut BitReader) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                let header = reader.aligned_bytes(4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(invalid_data("corrupt stored block length"));
+                }
+                output.extend_from_slice(reader.aligned_bytes(usize::from(length))?);
+            }
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_block(reader, &literals, &distances, &mut output)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_block(reader, &literals, &distances, &mut output)?;
+            }
+            _ => return Err(invalid_data("invalid DEFLATE block type")),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// Decodes the symbols of a compressed block until its end of block symbol.
+fn inflate_block(reader: &mut BitReader, literals: &Huffman, distances: &Huffman, output: &mut Vec<u8>) -> io::Result<()> {
+    loop {
+        let symbol = usize::from(literals.decode(reader)?);
+        match symbol {
+            0..=255 => output.push(u8::try_from(symbol).map_err(|_| invalid_data("invalid literal"))?),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = usize::from(LENGTH_BASES[index]) + reader.bits(LENGTH_EXTRA_BITS[index])? as usize;
+
+                let index = usize::from(distances.decode(reader)?);
+                if index >= DISTANCE_BASES.len() {
+                    return Err(invalid_data("invalid DEFLATE distance code"));
+                }
+                let distance = usize::from(DISTANCE_BASES[index]) + reader.bits(DISTANCE_EXTRA_BITS[index])? as usize;
+                if distance > output.len() {
+                    return Err(invalid_data("DEFLATE distance reaches before the start of the data"));
+                }
+
+                // The copy may overlap the bytes it produces, so it has to go one byte at a time.
+                let start = output.len() - distance;
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            }
+            _ => return Err(invalid_data("invalid DEFLATE length code")),
+        }
+    }
+}
+
+fn fixed_codes() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| invalid_data("repeated code length without a previous length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return Err(invalid_data("too many DEFLATE code lengths"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths[256] == 0 {
+        return Err(invalid_data("DEFLATE block without an end of block code"));
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Builds the code from the code length of every symbol, where 0 means the symbol is unused.
+    /// Incomplete codes are allowed, as DEFLATE uses them for blocks with a single distance.
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut remaining = 1i32;
+        for &count in &counts[1..] {
+            remaining = remaining * 2 - i32::from(count);
+            if remaining < 0 {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_CODE_LENGTH + 1])];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+                symbols[usize::from(*offset)] = u16::try_from(symbol).map_err(|_| invalid_data("too many Huffman symbols"))?;
+                *offset += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        // Codes of each length follow on from the codes of the previous length, so walk down the lengths
+        // until the code read so far falls within the range of codes of the current length.
+        let (mut code, mut first, mut index) = (0usize, 0usize, 0usize);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as usize;
+            let count = usize::from(count);
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+}
+
+/// Reads bits starting at the least significant bit of each byte, as DEFLATE stores them.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    buffered: u8,
+}
+
+impl<'a> BitReader<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0, buffer: 0, buffered: 0 }
+    }
+
+    fn bits(&mut self, count: u8) -> io::Result<u32> {
+        while self.buffered < count {
+            let byte = *self.data.get(self.position).ok_or_else(|| invalid_data("unexpected end of compressed data"))?;
+            self.position += 1;
+            self.buffer |= u32::from(byte) << self.buffered;
+            self.buffered += 8;
+        }
+        let value = self.buffer & ((1u32 << count) - 1);
+        self.buffer >>= count;
+        self.buffered -= count;
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary and returns the following `count` bytes.
+    fn aligned_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        // Whole bytes left in the buffer have not been used yet.
+        self.position -= usize::from(self.buffered / 8);
+        self.buffer = 0;
+        self.buffered = 0;
+
+        let bytes = self.data.get(self.position..self.position + count).ok_or_else(|| invalid_data("unexpected end of compressed data"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_huffman_block() {
+        // "hello hello hello" compressed by zlib at the default level, using a back reference.
+        let stream = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00, 0x3a, 0x2e, 0x06, 0x7d,
+        ];
+        assert_eq!(zlib_decompress(&stream).expect("valid stream"), b"hello hello hello");
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        let expected: Vec<u8> = (0..100u8).map(|i| (u16::from(i) * u16::from(i) % 7) as u8 * 3 + i % 5).collect();
+        let stream = [
+            0x78, 0xda, 0xa5, 0xca, 0xc7, 0x11, 0x00, 0x20, 0x08, 0x00, 0x30, 0x94, 0xce, 0xd1, 0xf6, 0x9f,
+            0xd6, 0x21, 0xcc, 0x3b, 0x40, 0xed, 0x91, 0x74, 0x65, 0x45, 0x5b, 0x08, 0xcb, 0x7c, 0xf1, 0xf0,
+            0x84, 0x14, 0xa3, 0xa6, 0xda, 0x28, 0xfc, 0x96, 0x07, 0x9c, 0x37, 0x03, 0x18,
+        ];
+        assert_eq!(zlib_decompress(&stream).expect("valid stream"), expected);
+    }
+
+    #[test]
+    fn corrupt_streams_are_rejected() {
+        let stream = [0x78, 0x01, 0x01, 0x02, 0x00, 0xfd, 0xff, b'h', b'i', 0x01, 0x3b, 0x00, 0xd3];
+        assert!(zlib_decompress(&stream).is_err());
+        assert!(zlib_decompress(&stream[..8]).is_err());
+        assert!(zlib_decompress(&[0x78, 0x9c, 0x07]).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 use colors::Color;
-use ray_math::Ray;
-use vec_math::{dot, Vec3};
-use std::vec::Vec;
+use ray_math::{Ray, RayDifferentials};
+use textures::Footprint;
+use vec_math::{cross, dot, Vec3};
+use std::{io, vec::Vec};
 use interval::Interval;
 use brdfs::BRDF;
 
@@ -11,7 +12,22 @@ pub mod colors;
 pub mod ray_math;
 pub mod camera;
 pub mod brdfs;
+pub mod bump;
+pub mod material_desc;
+pub mod measured;
+pub mod microfacet;
+pub mod noise;
 pub mod pixelbuffer;
+pub mod curves;
+pub mod heightfield;
+pub mod image;
+pub mod inflate;
+pub mod sampling;
+pub mod sdf;
+pub mod spectral;
+pub mod textures;
+pub mod volumes;
+pub mod voxel_grid;
 
 /// Calculates the color at the end of a ray.
 /// If a bad color value is produced, black is returned instead.
@@ -25,12 +41,30 @@ pub fn ray_color<T: Hittable>(ray: Ray, world: &T) -> Color {
     |hit| ((hit.normal + Vec3::new(1.0, 1.0, 1.0)) * 0.5).into())
 }
 
+/// Creates the error returned when a file being loaded is malformed.
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
 /// Type returned by all hits.
 pub struct HitRecord {
     /// Point where the ray hit the hittable.
     pub point: Vec3,
-    /// Surface normal at the hit point.
+    /// Shading normal at the hit point, facing against the ray. Normal and bump maps may tilt it.
     pub normal: Vec3,
+    /// Normal of the actual geometry at the hit point, facing against the ray. Used to move new rays off the surface.
+    pub geometric_normal: Vec3,
+    /// Unit vector tangent to the surface at the hit point, pointing in the direction of increasing `u`.
+    /// For curves it points along the curve.
+    pub tangent: Vec3,
+    /// Texture coordinate along the surface, in the range [0, 1].
+    pub u: f64,
+    /// Texture coordinate along the surface, in the range [0, 1].
+    pub v: f64,
+    /// Change in position per unit of `u`, zero for surfaces without a parameterization.
+    pub dpdu: Vec3,
+    /// Change in position per unit of `v`, zero for surfaces without a parameterization.
+    pub dpdv: Vec3,
     /// Distance* travelled by the ray from the camera to the surface.
     pub t: f64,
     /// True if the surface hit is a front-face.
@@ -39,12 +73,109 @@ pub struct HitRecord {
     pub brdf: BRDF,
 }
 
+impl HitRecord {
+    /// Creates a ray leaving the hit point in `direction`.
+    ///
+    /// The origin is moved slightly off the surface along the geometric normal, to the side the ray leaves on,
+    /// so rounding errors can not make the ray hit the same surface again right away.
+    #[must_use]
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let magnitude = self.point.x().abs().max(self.point.y().abs()).max(self.point.z().abs());
+        let offset = 1e-7 * (1.0 + magnitude);
+        let offset = if dot(direction, self.geometric_normal) < 0.0 {-offset} else {offset};
+        Ray::new(self.point + offset * self.geometric_normal, direction)
+    }
+
+    /// Creates a ray leaving the hit point in `direction` after a perfectly specular bounce of `incoming`.
+    ///
+    /// If the incoming ray has differentials, they are carried over by bending their directions with `bend`,
+    /// treating the surface as locally flat, so reflections and refractions of textures stay filtered.
+    #[must_use]
+    pub fn spawn_specular_ray<F: Fn(Vec3) -> Vec3>(&self, incoming: Ray, direction: Vec3, bend: F) -> Ray {
+        let ray = self.spawn_ray(direction);
+        let Some(differentials) = incoming.differentials() else {
+            return ray;
+        };
+        let Some((x_offset, y_offset)) = self.offsets(differentials) else {
+            return ray;
+        };
+
+        ray.set_differentials(RayDifferentials {
+            x_origin: ray.origin() + x_offset,
+            x_direction: bend(differentials.x_direction.normalized()),
+            y_origin: ray.origin() + y_offset,
+            y_direction: bend(differentials.y_direction.normalized()),
+        })
+    }
+
+    /// Estimates how much the texture coordinates change between neighbouring pixels, from the differentials of the
+    /// ray that made the hit. The footprint is zero if the ray has no differentials or the surface has no parameterization.
+    #[must_use]
+    #[allow(clippy::similar_names)]
+    pub fn footprint(&self, ray: Ray) -> Footprint {
+        let Some((x_offset, y_offset)) = ray.differentials().and_then(|differentials| self.offsets(differentials)) else {
+            return Footprint::default();
+        };
+
+        // Project the offsets onto the texture coordinate axes with least squares, as they need not be orthogonal.
+        let (uu, uv, vv) = (dot(self.dpdu, self.dpdu), dot(self.dpdu, self.dpdv), dot(self.dpdv, self.dpdv));
+        let determinant = uu.mul_add(vv, -(uv * uv));
+        if determinant.abs() < 1e-20 {
+            return Footprint::default();
+        }
+        let solve = |offset: Vec3| {
+            let (along_u, along_v) = (dot(self.dpdu, offset), dot(self.dpdv, offset));
+            (vv.mul_add(along_u, -(uv * along_v)) / determinant, uu.mul_add(along_v, -(uv * along_u)) / determinant)
+        };
+        let (dudx, dvdx) = solve(x_offset);
+        let (dudy, dvdy) = solve(y_offset);
+        Footprint { dudx, dvdx, dudy, dvdy }
+    }
+
+    /// Returns where the offset rays cross the tangent plane at the hit, relative to the hit point.
+    fn offsets(&self, differentials: RayDifferentials) -> Option<(Vec3, Vec3)> {
+        let plane_offset = |origin: Vec3, direction: Vec3| {
+            let facing = dot(self.geometric_normal, direction);
+            if facing.abs() < 1e-12 {
+                return None;
+            }
+            let t = dot(self.geometric_normal, self.point - origin) / facing;
+            Some(origin + t * direction - self.point)
+        };
+        Some((
+            plane_offset(differentials.x_origin, differentials.x_direction)?,
+            plane_offset(differentials.y_origin, differentials.y_direction)?,
+        ))
+    }
+}
+
 /// Trait to be implemented for all things that can be hit by a ray.
 pub trait Hittable {
     /// Intersects the ray with the surface and returns the hit if there was one.
     fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord>;
 }
 
+/// Returns the nearest hit found by `intersect` where the material is opaque, every shape uses it to support cutouts.
+///
+/// Hits are kept with a probability equal to the opacity of their material, decided with uniform random numbers in
+/// [0, 1) drawn from `random`, otherwise the shape is intersected again beyond the skipped hit. `intersect` must return
+/// the nearest hit strictly inside the interval it is given.
+pub fn first_opaque_hit<F, R>(ray: Ray, ray_t: Interval, intersect: F, mut random: R) -> Option<HitRecord>
+where
+    F: Fn(Interval) -> Option<HitRecord>,
+    R: FnMut() -> f64,
+{
+    let mut ray_t = ray_t;
+    loop {
+        let hit = intersect(ray_t)?;
+        let opacity = hit.brdf.opacity(ray, &hit);
+        if opacity >= 1.0 || opacity > random() {
+            return Some(hit);
+        }
+        ray_t = Interval::new(hit.t, ray_t.max());
+    }
+}
+
 /// Represents a sphere with a surface. 
 pub struct Sphere {
     center: Vec3,
@@ -76,8 +207,15 @@ pub fn calculate_face_normal(ray: Ray, outward_normal: Vec3) -> (bool, Vec3) {
 }
 
 impl Hittable for Sphere {
-    #[allow(clippy::suspicious_operation_groupings)]
     fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.intersect(ray, ray_t), rand::random::<f64>)
+    }
+}
+
+impl Sphere {
+    #[allow(clippy::suspicious_operation_groupings)]
+    #[allow(clippy::similar_names)]
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
         let oc = self.center - ray.origin();
         let a = ray.direction().square_length();
         let h = dot(ray.direction(), oc);
@@ -99,17 +237,110 @@ impl Hittable for Sphere {
         }
 
         let hit_point = ray.at(root);
-        let (front_face, normal) = calculate_face_normal(ray, (hit_point - self.center) / self.radius);
+        let outward_normal = (hit_point - self.center) / self.radius;
+        let (front_face, normal) = calculate_face_normal(ray, outward_normal);
+
+        // Tangent follows the lines of latitude around the y axis, in the direction of increasing longitude.
+        let around_y_axis = Vec3::new(outward_normal.z(), 0.0, -outward_normal.x());
+        let tangent = if around_y_axis.near_zero() {Vec3::new(1.0, 0.0, 0.0)} else {around_y_axis.normalized()};
+
+        // Longitude around the y axis and latitude from the bottom of the sphere.
+        let longitude = (-outward_normal.z()).atan2(outward_normal.x()).mul_add(0.5 / std::f64::consts::PI, 0.5);
+        let latitude = (-outward_normal.y()).clamp(-1.0, 1.0).acos() / std::f64::consts::PI;
+
+        // Circles of latitude shrink towards the poles, where the direction of increasing latitude is undefined.
+        let ring_radius = outward_normal.x().hypot(outward_normal.z());
+        let dpdu = 2.0 * std::f64::consts::PI * self.radius * Vec3::new(outward_normal.z(), 0.0, -outward_normal.x());
+        let dpdv = if ring_radius < 1e-9 {
+            Vec3::default()
+        } else {
+            let towards_top = -outward_normal.y() / ring_radius;
+            std::f64::consts::PI * self.radius * Vec3::new(towards_top * outward_normal.x(), ring_radius, towards_top * outward_normal.z())
+        };
 
         Some(HitRecord {
             t: root,
             point: hit_point,
+            geometric_normal: normal,
+            tangent,
+            u: longitude,
+            v: latitude,
+            dpdu,
+            dpdv,
             brdf: self.surface_shader.clone(),
             normal, front_face
         })
     }
 }
 
+/// A flat parallelogram spanned by the edges `u` and `v` from a corner, for walls, and with a cutout material
+/// for leaves and fences. The texture coordinates go from 0 to 1 along the edges.
+/// # Example
+/// ```
+/// use renders::{Quad, brdfs, colors::Color, vec_math::Vec3};
+/// let material = brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5));
+/// let floor = Quad::new(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), material);
+/// ```
+pub struct Quad {
+    corner: Vec3,
+    u: Vec3,
+    v: Vec3,
+    surface_shader: BRDF,
+}
+
+impl Quad {
+    /// Creates a new quad.
+    /// # Panics
+    /// Panics if the edges are parallel, which leaves no surface.
+    #[must_use]
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3, surface_shader: BRDF) -> Self {
+        assert!(!cross(u, v).near_zero());
+        Self { corner, u, v, surface_shader }
+    }
+
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let plane_normal = cross(self.u, self.v);
+        let denominator = dot(plane_normal, ray.direction());
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = dot(plane_normal, self.corner - ray.origin()) / denominator;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // Coordinates of the hit point along the edges.
+        let point = ray.at(t);
+        let offset = point - self.corner;
+        let w = plane_normal / plane_normal.square_length();
+        let u = dot(w, cross(offset, self.v));
+        let v = dot(w, cross(self.u, offset));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+
+        let (front_face, normal) = calculate_face_normal(ray, plane_normal.normalized());
+        Some(HitRecord {
+            t,
+            point,
+            geometric_normal: normal,
+            tangent: self.u.normalized(),
+            u,
+            v,
+            dpdu: self.u,
+            dpdv: self.v,
+            brdf: self.surface_shader.clone(),
+            normal, front_face
+        })
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.intersect(ray, ray_t), rand::random::<f64>)
+    }
+}
+
 /// A hittable collection of hittable items.
 pub struct Hittables {
     objects: Vec<Box<dyn Hittable + Send + Sync + 'static>>,
@@ -164,3 +395,53 @@ impl Hittable for Hittables {
         current
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_hit_inside_their_edges() {
+        let material = brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5));
+        let quad = Quad::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), material);
+        let hit = quad.hit(Ray::new(Vec3::new(0.5, -0.5, 2.0), Vec3::new(0.0, 0.0, -1.0)), Interval::new(0.0, f64::INFINITY))
+            .expect("ray aimed at the quad");
+        assert!((hit.t - 2.0).abs() < 1e-12);
+        assert!((hit.u - 0.75).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12);
+        assert!(hit.front_face);
+
+        let beside = Ray::new(Vec3::new(0.5, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(beside, Interval::new(0.0, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn cutouts_let_rays_through() {
+        let material = brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let quad = |opacity: f64| Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0),
+            brdfs::make_cutout_brdf(material.clone(), opacity),
+        );
+        // Counts the hits over evenly spread random numbers, where every hit tested draws the next number of a pair.
+        let hits = |intersect: &dyn Fn(Interval) -> Option<HitRecord>| {
+            let side = 100;
+            let mut hits = 0;
+            for index in 0..side * side {
+                let mut draws = [index / side, index % side].map(|i| (f64::from(i) + 0.5) / f64::from(side)).into_iter();
+                let random = || draws.next().expect("at most two surfaces are tested");
+                if first_opaque_hit(ray, Interval::new(0.0, f64::INFINITY), intersect, random).is_some() {
+                    hits += 1;
+                }
+            }
+            hits
+        };
+        let (hidden, solid, faded) = (quad(0.0), quad(1.0), quad(0.3));
+        assert_eq!(hits(&|ray_t| hidden.intersect(ray, ray_t)), 0);
+        assert_eq!(hits(&|ray_t| solid.intersect(ray, ray_t)), 10000);
+        assert_eq!(hits(&|ray_t| faded.intersect(ray, ray_t)), 3000);
+
+        // Rays passing through the front of a half transparent sphere may still hit its back.
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_cutout_brdf(material, 0.5));
+        assert_eq!(hits(&|ray_t| sphere.intersect(ray, ray_t)), 7500);
+    }
+}
@@ -0,0 +1,504 @@
+//! Plain data descriptions of materials that can be inspected, compared, printed and parsed.
+//!
+//! # Text format
+//! A material is written on a single line as its kind followed by `name=value` fields separated by whitespace:
+//! ```text
+//! lambertian albedo=0.8,0.8,0
+//! rough_metal eta=0.143119,0.374957,1.44248 k=3.98316,2.38572,1.60322 roughness=0.3 anisotropy=0
+//! principled base_color=0.6,0.05,0.05 roughness=0.4 clearcoat=1
+//! ```
+//! Colors and vectors are written as three comma separated numbers, the components of colors lie in [0, 1].
+//! Materials built from other materials write those in square brackets:
+//! ```text
+//! mix weight=0.3 first=[lambertian albedo=0.1,0.3,0.6] second=[metal albedo=0.4,0.15,0.05]
+//! ```
+//! Fields may appear in any order. All fields are required, except for `principled` where missing fields take their
+//! default value.
+//!
+//! | Kind                | Fields                                                                        |
+//! |---------------------|-------------------------------------------------------------------------------|
+//! | `lambertian`        | `albedo`                                                                      |
+//! | `metal`             | `albedo`                                                                      |
+//! | `rough_metal`       | `eta`, `k`, `roughness`, `anisotropy`                                         |
+//! | `thin_film_metal`   | `eta`, `k`, `roughness`, `anisotropy`, `film_thickness`, `film_ior`           |
+//! | `glass`             | `ior`, `albedo`                                                               |
+//! | `dispersive_glass`  | `ior`, `abbe`, `albedo`                                                       |
+//! | `thin_film_glass`   | `ior`, `albedo`, `film_thickness`, `film_ior`                                 |
+//! | `rough_glass`       | `ior`, `roughness`, `absorption`                                              |
+//! | `diffuse_light`     | `emission`, `strength`                                                        |
+//! | `isotropic`         | `albedo`                                                                      |
+//! | `henyey_greenstein` | `albedo`, `g`                                                                 |
+//! | `cloth`             | `albedo`, `sheen`, `roughness`                                                |
+//! | `hair`              | `absorption`, `longitudinal_roughness`, `azimuthal_roughness`, `scale_angle`  |
+//! | `principled`        | the fields of [`PrincipledParameters`]                                        |
+//! | `layered`           | `base`, `ior`, `roughness`, `thickness`, `absorption`                         |
+//! | `mix`               | `first`, `second`, `weight`                                                   |
+//! | `cutout`            | `material`, `opacity`                                                         |
+//!
+//! Only materials with constant parameters are described. Textured materials such as
+//! [`brdfs::make_textured_principled_brdf`] and measured ones from [`brdfs::make_measured_brdf`] have to be built in
+//! code, and subsurface scattering is a shape, [`crate::volumes::Subsurface`], rather than a material.
+
+use std::{error::Error, fmt::{self, Display}, str::FromStr};
+use crate::{brdfs::{self, BRDF, CoatParameters, PrincipledParameters}, colors::Color, microfacet::{ComplexIor, ThinFilm}, spectral::Dispersion, vec_math::Vec3};
+
+/// Description of a material, built into shading code with [`MaterialDesc::build`].
+/// # Example
+/// ```
+/// use renders::{colors::Color, material_desc::MaterialDesc};
+/// let description: MaterialDesc = "glass ior=1.5 albedo=1,1,1".parse().expect("Valid material.");
+/// assert_eq!(description, MaterialDesc::Glass { ior: 1.5, albedo: Color::new(1.0, 1.0, 1.0) });
+/// assert_eq!(description.to_string(), "glass ior=1.5 albedo=1,1,1");
+/// let material = description.build();
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub enum MaterialDesc {
+    Lambertian { albedo: Color },
+    Metal { albedo: Color },
+    RoughMetal { ior: ComplexIor, roughness: f64, anisotropy: f64 },
+    ThinFilmMetal { ior: ComplexIor, roughness: f64, anisotropy: f64, film: ThinFilm },
+    Glass { ior: f64, albedo: Color },
+    /// Glass that splits light into its colors, with the index of refraction `ior` at the d line and the Abbe number
+    /// `abbe`, see [`Dispersion::from_abbe`].
+    DispersiveGlass { ior: f64, abbe: f64, albedo: Color },
+    ThinFilmGlass { ior: f64, albedo: Color, film: ThinFilm },
+    RoughGlass { ior: f64, roughness: f64, absorption: Vec3 },
+    DiffuseLight { emission: Color, strength: f64 },
+    Isotropic { albedo: Color },
+    HenyeyGreenstein { albedo: Color, g: f64 },
+    Cloth { albedo: Color, sheen: Color, roughness: f64 },
+    Hair { absorption: Vec3, longitudinal_roughness: f64, azimuthal_roughness: f64, scale_angle: f64 },
+    Principled(PrincipledParameters),
+    Layered { base: Box<Self>, coat: CoatParameters },
+    Mix { first: Box<Self>, second: Box<Self>, weight: f64 },
+    Cutout { material: Box<Self>, opacity: f64 },
+}
+
+impl MaterialDesc {
+    /// Creates the material described.
+    /// # Panics
+    /// Panics if the Henyey-Greenstein asymmetry `g` is not in the range (-1, 1).
+    #[must_use]
+    pub fn build(&self) -> BRDF {
+        match self.clone() {
+            Self::Lambertian { albedo } => brdfs::make_lambertian_diffuse_brdf(albedo),
+            Self::Metal { albedo } => brdfs::make_metal_brdf(albedo),
+            Self::RoughMetal { ior, roughness, anisotropy } => brdfs::make_rough_metal_brdf(ior, roughness, anisotropy),
+            Self::ThinFilmMetal { ior, roughness, anisotropy, film } => {
+                brdfs::make_thin_film_metal_brdf(ior, roughness, anisotropy, film)
+            }
+            Self::Glass { ior, albedo } => brdfs::make_glass_brdf(ior, albedo),
+            Self::DispersiveGlass { ior, abbe, albedo } => brdfs::make_dispersive_glass_brdf(Dispersion::from_abbe(ior, abbe), albedo),
+            Self::ThinFilmGlass { ior, albedo, film } => brdfs::make_thin_film_glass_brdf(ior, albedo, film),
+            Self::RoughGlass { ior, roughness, absorption } => brdfs::make_rough_glass_brdf(ior, roughness, absorption),
+            Self::DiffuseLight { emission, strength } => brdfs::make_diffuse_light_brdf(emission, strength),
+            Self::Isotropic { albedo } => brdfs::make_isotropic_phase_function(albedo),
+            Self::HenyeyGreenstein { albedo, g } => brdfs::make_henyey_greenstein_phase_function(albedo, g),
+            Self::Cloth { albedo, sheen, roughness } => brdfs::make_cloth_brdf(albedo, sheen, roughness),
+            Self::Hair { absorption, longitudinal_roughness, azimuthal_roughness, scale_angle } => {
+                brdfs::make_hair_brdf(absorption, longitudinal_roughness, azimuthal_roughness, scale_angle)
+            }
+            Self::Principled(parameters) => brdfs::make_principled_brdf(parameters),
+            Self::Layered { base, coat } => brdfs::make_layered_brdf(base.build(), coat),
+            Self::Mix { first, second, weight } => brdfs::make_mix_brdf(first.build(), second.build(), weight),
+            Self::Cutout { material, opacity } => brdfs::make_cutout_brdf(material.build(), opacity),
+        }
+    }
+}
+
+impl Display for MaterialDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lambertian { albedo } => write!(f, "lambertian albedo={}", Triple::from(*albedo)),
+            Self::Metal { albedo } => write!(f, "metal albedo={}", Triple::from(*albedo)),
+            Self::RoughMetal { ior, roughness, anisotropy } => write!(
+                f, "rough_metal eta={} k={} roughness={roughness} anisotropy={anisotropy}",
+                Triple(ior.eta), Triple(ior.k),
+            ),
+            Self::ThinFilmMetal { ior, roughness, anisotropy, film } => write!(
+                f, "thin_film_metal eta={} k={} roughness={roughness} anisotropy={anisotropy} film_thickness={} film_ior={}",
+                Triple(ior.eta), Triple(ior.k), film.thickness, film.ior,
+            ),
+            Self::Glass { ior, albedo } => write!(f, "glass ior={ior} albedo={}", Triple::from(*albedo)),
+            Self::DispersiveGlass { ior, abbe, albedo } => write!(f, "dispersive_glass ior={ior} abbe={abbe} albedo={}", Triple::from(*albedo)),
+            Self::ThinFilmGlass { ior, albedo, film } => write!(
+                f, "thin_film_glass ior={ior} albedo={} film_thickness={} film_ior={}",
+                Triple::from(*albedo), film.thickness, film.ior,
+            ),
+            Self::RoughGlass { ior, roughness, absorption } => write!(
+                f, "rough_glass ior={ior} roughness={roughness} absorption={}", Triple(*absorption),
+            ),
+            Self::DiffuseLight { emission, strength } => write!(f, "diffuse_light emission={} strength={strength}", Triple::from(*emission)),
+            Self::Isotropic { albedo } => write!(f, "isotropic albedo={}", Triple::from(*albedo)),
+            Self::HenyeyGreenstein { albedo, g } => write!(f, "henyey_greenstein albedo={} g={g}", Triple::from(*albedo)),
+            Self::Cloth { albedo, sheen, roughness } => write!(
+                f, "cloth albedo={} sheen={} roughness={roughness}", Triple::from(*albedo), Triple::from(*sheen),
+            ),
+            Self::Hair { absorption, longitudinal_roughness, azimuthal_roughness, scale_angle } => write!(
+                f, "hair absorption={} longitudinal_roughness={longitudinal_roughness} azimuthal_roughness={azimuthal_roughness} scale_angle={scale_angle}",
+                Triple(*absorption),
+            ),
+            Self::Principled(parameters) => write!(
+                f,
+                "principled base_color={} metallic={} roughness={} specular={} specular_tint={} sheen={} sheen_tint={} \
+                clearcoat={} clearcoat_gloss={} subsurface={} anisotropic={} transmission={} ior={}",
+                Triple::from(parameters.base_color), parameters.metallic, parameters.roughness, parameters.specular,
+                parameters.specular_tint, parameters.sheen, parameters.sheen_tint, parameters.clearcoat,
+                parameters.clearcoat_gloss, parameters.subsurface, parameters.anisotropic, parameters.transmission,
+                parameters.ior,
+            ),
+            Self::Layered { base, coat } => write!(
+                f, "layered base=[{base}] ior={} roughness={} thickness={} absorption={}",
+                coat.ior, coat.roughness, coat.thickness, Triple(coat.absorption),
+            ),
+            Self::Mix { first, second, weight } => write!(f, "mix first=[{first}] second=[{second}] weight={weight}"),
+            Self::Cutout { material, opacity } => write!(f, "cutout material=[{material}] opacity={opacity}"),
+        }
+    }
+}
+
+impl FromStr for MaterialDesc {
+    type Err = ParseMaterialError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = split_tokens(s)?.into_iter();
+        let kind = tokens.next().ok_or(ParseMaterialError::Empty)?;
+        let mut fields = Fields::parse(tokens)?;
+
+        let description = match kind {
+            "lambertian" => Self::Lambertian { albedo: fields.color("albedo")? },
+            "metal" => Self::Metal { albedo: fields.color("albedo")? },
+            "rough_metal" => Self::RoughMetal {
+                ior: ComplexIor::new(fields.vector("eta")?, fields.vector("k")?),
+                roughness: fields.number("roughness")?,
+                anisotropy: fields.number("anisotropy")?,
+            },
+            "thin_film_metal" => Self::ThinFilmMetal {
+                ior: ComplexIor::new(fields.vector("eta")?, fields.vector("k")?),
+                roughness: fields.number("roughness")?,
+                anisotropy: fields.number("anisotropy")?,
+                film: fields.film()?,
+            },
+            "glass" => Self::Glass { ior: fields.number("ior")?, albedo: fields.color("albedo")? },
+            "dispersive_glass" => Self::DispersiveGlass {
+                ior: fields.number("ior")?,
+                abbe: fields.number("abbe")?,
+                albedo: fields.color("albedo")?,
+            },
+            "thin_film_glass" => Self::ThinFilmGlass {
+                ior: fields.number("ior")?,
+                albedo: fields.color("albedo")?,
+                film: fields.film()?,
+            },
+            "rough_glass" => Self::RoughGlass {
+                ior: fields.number("ior")?,
+                roughness: fields.number("roughness")?,
+                absorption: fields.vector("absorption")?,
+            },
+            "diffuse_light" => Self::DiffuseLight { emission: fields.color("emission")?, strength: fields.number("strength")? },
+            "isotropic" => Self::Isotropic { albedo: fields.color("albedo")? },
+            "henyey_greenstein" => {
+                let albedo = fields.color("albedo")?;
+                let g = fields.number("g")?;
+                if g <= -1.0 || g >= 1.0 {
+                    return Err(ParseMaterialError::InvalidValue("g".to_owned()));
+                }
+                Self::HenyeyGreenstein { albedo, g }
+            }
+            "cloth" => Self::Cloth {
+                albedo: fields.color("albedo")?,
+                sheen: fields.color("sheen")?,
+                roughness: fields.number("roughness")?,
+            },
+            "hair" => Self::Hair {
+                absorption: fields.vector("absorption")?,
+                longitudinal_roughness: fields.number("longitudinal_roughness")?,
+                azimuthal_roughness: fields.number("azimuthal_roughness")?,
+                scale_angle: fields.number("scale_angle")?,
+            },
+            "principled" => {
+                let defaults = PrincipledParameters::default();
+                Self::Principled(PrincipledParameters {
+                    base_color: fields.optional("base_color", parse_color)?.unwrap_or(defaults.base_color),
+                    metallic: fields.optional("metallic", parse_number)?.unwrap_or(defaults.metallic),
+                    roughness: fields.optional("roughness", parse_number)?.unwrap_or(defaults.roughness),
+                    specular: fields.optional("specular", parse_number)?.unwrap_or(defaults.specular),
+                    specular_tint: fields.optional("specular_tint", parse_number)?.unwrap_or(defaults.specular_tint),
+                    sheen: fields.optional("sheen", parse_number)?.unwrap_or(defaults.sheen),
+                    sheen_tint: fields.optional("sheen_tint", parse_number)?.unwrap_or(defaults.sheen_tint),
+                    clearcoat: fields.optional("clearcoat", parse_number)?.unwrap_or(defaults.clearcoat),
+                    clearcoat_gloss: fields.optional("clearcoat_gloss", parse_number)?.unwrap_or(defaults.clearcoat_gloss),
+                    subsurface: fields.optional("subsurface", parse_number)?.unwrap_or(defaults.subsurface),
+                    anisotropic: fields.optional("anisotropic", parse_number)?.unwrap_or(defaults.anisotropic),
+                    transmission: fields.optional("transmission", parse_number)?.unwrap_or(defaults.transmission),
+                    ior: fields.optional("ior", parse_number)?.unwrap_or(defaults.ior),
+                })
+            }
+            "layered" => Self::Layered {
+                base: fields.material("base")?,
+                coat: CoatParameters {
+                    ior: fields.number("ior")?,
+                    roughness: fields.number("roughness")?,
+                    thickness: fields.number("thickness")?,
+                    absorption: fields.vector("absorption")?,
+                },
+            },
+            "mix" => Self::Mix {
+                first: fields.material("first")?,
+                second: fields.material("second")?,
+                weight: fields.number("weight")?,
+            },
+            "cutout" => Self::Cutout { material: fields.material("material")?, opacity: fields.number("opacity")? },
+            _ => return Err(ParseMaterialError::UnknownMaterial(kind.to_owned())),
+        };
+
+        fields.finish()?;
+        Ok(description)
+    }
+}
+
+/// Error returned when parsing a material description fails.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseMaterialError {
+    /// The description is empty.
+    Empty,
+    /// The kind of material is not known.
+    UnknownMaterial(String),
+    /// A required field is missing.
+    MissingField(&'static str),
+    /// A field is not used by the kind of material, or appears more than once.
+    UnexpectedField(String),
+    /// A field is not of the form `name=value`, or its value could not be parsed.
+    InvalidValue(String),
+}
+
+impl Display for ParseMaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty material description"),
+            Self::UnknownMaterial(kind) => write!(f, "unknown material `{kind}`"),
+            Self::MissingField(name) => write!(f, "missing field `{name}`"),
+            Self::UnexpectedField(name) => write!(f, "unexpected field `{name}`"),
+            Self::InvalidValue(name) => write!(f, "invalid value for `{name}`"),
+        }
+    }
+}
+
+impl Error for ParseMaterialError {}
+
+/// Writes a vector as three comma separated numbers.
+struct Triple(Vec3);
+
+impl From<Color> for Triple {
+    fn from(color: Color) -> Self {
+        Self(color.into())
+    }
+}
+
+impl Display for Triple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.0.x(), self.0.y(), self.0.z())
+    }
+}
+
+/// The `name=value` fields of a description that have not been used yet.
+struct Fields<'a> {
+    entries: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Fields<'a> {
+    fn parse<I: Iterator<Item = &'a str>>(tokens: I) -> Result<Self, ParseMaterialError> {
+        let entries = tokens
+            .map(|token| token.split_once('=').ok_or_else(|| ParseMaterialError::InvalidValue(token.to_owned())))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { entries })
+    }
+
+    fn optional<T, F: Fn(&str) -> Option<T>>(&mut self, name: &'static str, parse: F) -> Result<Option<T>, ParseMaterialError> {
+        let Some(index) = self.entries.iter().position(|(key, _)| *key == name) else {
+            return Ok(None);
+        };
+        let (_, value) = self.entries.remove(index);
+        parse(value).map(Some).ok_or_else(|| ParseMaterialError::InvalidValue(name.to_owned()))
+    }
+
+    fn required<T, F: Fn(&str) -> Option<T>>(&mut self, name: &'static str, parse: F) -> Result<T, ParseMaterialError> {
+        self.optional(name, parse)?.ok_or(ParseMaterialError::MissingField(name))
+    }
+
+    fn number(&mut self, name: &'static str) -> Result<f64, ParseMaterialError> {
+        self.required(name, parse_number)
+    }
+
+    fn vector(&mut self, name: &'static str) -> Result<Vec3, ParseMaterialError> {
+        self.required(name, parse_vector)
+    }
+
+    fn color(&mut self, name: &'static str) -> Result<Color, ParseMaterialError> {
+        self.required(name, parse_color)
+    }
+
+    fn material(&mut self, name: &'static str) -> Result<Box<MaterialDesc>, ParseMaterialError> {
+        self.required(name, parse_material)
+    }
+
+    fn film(&mut self) -> Result<ThinFilm, ParseMaterialError> {
+        Ok(ThinFilm::new(self.number("film_thickness")?, self.number("film_ior")?))
+    }
+
+    /// Fails if any field was not used.
+    fn finish(self) -> Result<(), ParseMaterialError> {
+        self.entries.first().map_or(Ok(()), |(key, _)| Err(ParseMaterialError::UnexpectedField((*key).to_owned())))
+    }
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    value.parse().ok().filter(|number: &f64| number.is_finite())
+}
+
+fn parse_vector(value: &str) -> Option<Vec3> {
+    let mut numbers = value.split(',').map(parse_number);
+    let vector = Vec3::new(numbers.next()??, numbers.next()??, numbers.next()??);
+    numbers.next().is_none().then_some(vector)
+}
+
+/// Parses a color, rejecting components outside of [0, 1] instead of clamping them.
+fn parse_color(value: &str) -> Option<Color> {
+    let color = parse_vector(value)?;
+    [color.x(), color.y(), color.z()].iter().all(|component| (0.0..=1.0).contains(component)).then(|| color.into())
+}
+
+/// Parses a material written in square brackets.
+fn parse_material(value: &str) -> Option<Box<MaterialDesc>> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?;
+    inner.parse().ok().map(Box::new)
+}
+
+/// Splits a description at whitespace that is not inside of square brackets.
+fn split_tokens(s: &str) -> Result<Vec<&str>, ParseMaterialError> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut start = None;
+    for (index, character) in s.char_indices() {
+        match character {
+            '[' => depth += 1,
+            ']' => depth = depth.checked_sub(1).ok_or_else(|| ParseMaterialError::InvalidValue(s.to_owned()))?,
+            _ if character.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    tokens.push(&s[start..index]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(index);
+    }
+    if depth > 0 {
+        return Err(ParseMaterialError::InvalidValue(s.to_owned()));
+    }
+    tokens.extend(start.map(|start| &s[start..]));
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_round_trip() {
+        let descriptions = [
+            MaterialDesc::Lambertian { albedo: Color::new(0.8, 0.8, 0.0) },
+            MaterialDesc::Metal { albedo: Color::new(0.8, 0.6, 0.2) },
+            MaterialDesc::RoughMetal { ior: ComplexIor::GOLD, roughness: 0.3, anisotropy: -0.5 },
+            MaterialDesc::ThinFilmMetal {
+                ior: ComplexIor::SILVER,
+                roughness: 0.1,
+                anisotropy: 0.0,
+                film: ThinFilm::new(300.0, 1.45),
+            },
+            MaterialDesc::Glass { ior: 1.5, albedo: Color::new(1.0, 1.0, 1.0) },
+            MaterialDesc::DispersiveGlass { ior: 2.42, abbe: 55.0, albedo: Color::new(1.0, 1.0, 1.0) },
+            MaterialDesc::ThinFilmGlass { ior: 1.0, albedo: Color::new(1.0, 1.0, 1.0), film: ThinFilm::new(500.0, 1.33) },
+            MaterialDesc::RoughGlass { ior: 1.33, roughness: 0.1, absorption: Vec3::new(0.1, 0.2, 0.3) },
+            MaterialDesc::DiffuseLight { emission: Color::new(1.0, 0.9, 0.7), strength: 4.0 },
+            MaterialDesc::Isotropic { albedo: Color::new(0.5, 0.5, 0.5) },
+            MaterialDesc::HenyeyGreenstein { albedo: Color::new(0.9, 0.9, 0.9), g: 0.7 },
+            MaterialDesc::Cloth { albedo: Color::new(0.3, 0.02, 0.05), sheen: Color::new(0.9, 0.4, 0.5), roughness: 0.6 },
+            MaterialDesc::Hair {
+                absorption: brdfs::hair_absorption_from_melanin(1.3, 0.2),
+                longitudinal_roughness: 0.3,
+                azimuthal_roughness: 0.25,
+                scale_angle: 2.0,
+            },
+            MaterialDesc::Principled(PrincipledParameters {
+                base_color: Color::new(1.0 / 3.0, 0.1, 0.7),
+                metallic: 0.5,
+                clearcoat: 1.0,
+                ..PrincipledParameters::default()
+            }),
+            MaterialDesc::Layered {
+                base: Box::new(MaterialDesc::Lambertian { albedo: Color::new(0.5, 0.3, 0.1) }),
+                coat: CoatParameters { roughness: 0.1, thickness: 0.01, ..CoatParameters::default() },
+            },
+            MaterialDesc::Cutout {
+                material: Box::new(MaterialDesc::Mix {
+                    first: Box::new(MaterialDesc::Lambertian { albedo: Color::new(0.1, 0.3, 0.6) }),
+                    second: Box::new(MaterialDesc::Metal { albedo: Color::new(0.4, 0.15, 0.05) }),
+                    weight: 0.3,
+                }),
+                opacity: 0.5,
+            },
+        ];
+
+        for description in descriptions {
+            let text = description.to_string();
+            assert_eq!(text.parse::<MaterialDesc>(), Ok(description), "{text}");
+        }
+    }
+
+    #[test]
+    fn written_descriptions_match_typed_ones() {
+        let scene = [
+            ("lambertian albedo=0.8,0.8,0", MaterialDesc::Lambertian { albedo: Color::new(0.8, 0.8, 0.0) }),
+            ("glass ior=1.5 albedo=1,1,1", MaterialDesc::Glass { ior: 1.5, albedo: Color::new(1.0, 1.0, 1.0) }),
+            ("glass ior=0.6666666666666666 albedo=1,1,1", MaterialDesc::Glass { ior: 1.0 / 1.5, albedo: Color::new(1.0, 1.0, 1.0) }),
+            ("metal albedo=0.8,0.6,0.2", MaterialDesc::Metal { albedo: Color::new(0.8, 0.6, 0.2) }),
+            (
+                "layered base=[lambertian albedo=0.5,0.3,0.1] ior=1.5 roughness=0.1 thickness=0.01 absorption=0,0,0",
+                MaterialDesc::Layered {
+                    base: Box::new(MaterialDesc::Lambertian { albedo: Color::new(0.5, 0.3, 0.1) }),
+                    coat: CoatParameters { ior: 1.5, roughness: 0.1, thickness: 0.01, absorption: Vec3::default() },
+                },
+            ),
+        ];
+
+        for (text, description) in scene {
+            assert_eq!(text.parse::<MaterialDesc>(), Ok(description.clone()), "{text}");
+            assert_eq!(description.to_string().parse::<MaterialDesc>(), Ok(description), "{text}");
+        }
+    }
+
+    #[test]
+    fn principled_fields_are_optional() {
+        let description: MaterialDesc = "principled  metallic=1\troughness=0.2".parse().expect("Valid material.");
+        assert_eq!(description, MaterialDesc::Principled(PrincipledParameters {
+            metallic: 1.0,
+            roughness: 0.2,
+            ..PrincipledParameters::default()
+        }));
+    }
+
+    #[test]
+    fn invalid_descriptions_are_rejected() {
+        assert_eq!("".parse::<MaterialDesc>(), Err(ParseMaterialError::Empty));
+        assert_eq!("plastic albedo=1,1,1".parse::<MaterialDesc>(), Err(ParseMaterialError::UnknownMaterial("plastic".to_owned())));
+        assert_eq!("glass ior=1.5".parse::<MaterialDesc>(), Err(ParseMaterialError::MissingField("albedo")));
+        assert_eq!("metal albedo=1,1,1 ior=1".parse::<MaterialDesc>(), Err(ParseMaterialError::UnexpectedField("ior".to_owned())));
+        assert_eq!("metal albedo=1,1".parse::<MaterialDesc>(), Err(ParseMaterialError::InvalidValue("albedo".to_owned())));
+        assert_eq!("metal albedo".parse::<MaterialDesc>(), Err(ParseMaterialError::InvalidValue("albedo".to_owned())));
+        assert_eq!("henyey_greenstein albedo=1,1,1 g=1".parse::<MaterialDesc>(), Err(ParseMaterialError::InvalidValue("g".to_owned())));
+        assert_eq!("diffuse_light emission=4,4,4 strength=1".parse::<MaterialDesc>(), Err(ParseMaterialError::InvalidValue("emission".to_owned())));
+        assert_eq!("cutout material=[metal opacity=1".parse::<MaterialDesc>(), Err(ParseMaterialError::InvalidValue("cutout material=[metal opacity=1".to_owned())));
+        assert_eq!("cutout material=[metal] opacity=1".parse::<MaterialDesc>(), Err(ParseMaterialError::InvalidValue("material".to_owned())));
+    }
+}
@@ -0,0 +1,161 @@
+//! Measured reflectance of real materials, for comparing analytic materials against.
+//!
+//! Supports the binary format of the MERL BRDF database: three little endian 32 bit integers giving the resolution
+//! (90 by 90 by 180), followed by the red, green and blue tables as little endian 64 bit floats. The tables are
+//! indexed by the half and difference angles of Rusinkiewicz, which only describe isotropic materials.
+
+use std::{f64::consts::PI, fs, io::{self, Read}, path::Path};
+use crate::{invalid_data, vec_math::Vec3};
+
+const THETA_HALF_RESOLUTION: usize = 90;
+const THETA_DIFFERENCE_RESOLUTION: usize = 90;
+const PHI_DIFFERENCE_RESOLUTION: usize = 180;
+const TABLE_SIZE: usize = THETA_HALF_RESOLUTION * THETA_DIFFERENCE_RESOLUTION * PHI_DIFFERENCE_RESOLUTION;
+
+/// Scales from the stored values to reflectance, per color channel.
+const CHANNEL_SCALES: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+/// A tabulated isotropic BRDF measured from a real material, see [`crate::brdfs::make_measured_brdf`].
+/// # Example
+/// ```no_run
+/// use renders::{brdfs, measured::MeasuredBrdf};
+/// let gold_paint = brdfs::make_measured_brdf(MeasuredBrdf::load("gold-metallic-paint.binary")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct MeasuredBrdf {
+    values: Vec<Vec3>,
+}
+
+impl MeasuredBrdf {
+    /// Loads a BRDF in the MERL binary format.
+    /// # Errors
+    /// Returns an error if the file can not be read, or one of kind `InvalidData` if it is not a MERL BRDF.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    /// Reads a BRDF in the MERL binary format.
+    /// # Errors
+    /// Returns an error if reading fails, or one of kind `InvalidData` if the data is not a MERL BRDF.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::decode(&bytes)
+    }
+
+    /// Decodes a BRDF in the MERL binary format from the contents of a file.
+    /// # Errors
+    /// Returns an error of kind `InvalidData` if the data is not a MERL BRDF.
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let (header, tables) = bytes.split_at_checked(12).ok_or_else(|| invalid_data("MERL BRDF too short"))?;
+        let dimensions: Vec<_> = header
+            .chunks_exact(4)
+            .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        if dimensions != [90, 90, 180] {
+            return Err(invalid_data("unsupported MERL BRDF resolution"));
+        }
+        if tables.len() != 3 * TABLE_SIZE * 8 {
+            return Err(invalid_data("MERL BRDF has the wrong size"));
+        }
+
+        // Negative values mark directions that were not measured.
+        let value = |channel: usize, index: usize| {
+            let start = 8 * (channel * TABLE_SIZE + index);
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&tables[start..start + 8]);
+            (f64::from_le_bytes(bytes) * CHANNEL_SCALES[channel]).max(0.0)
+        };
+        let values = (0..TABLE_SIZE).map(|index| Vec3::new(value(0, index), value(1, index), value(2, index))).collect();
+        Ok(Self { values })
+    }
+
+    /// Returns the BRDF for directions given in a frame with the normal along z, both pointing away from the surface.
+    /// Directions below the surface reflect nothing.
+    #[must_use]
+    pub fn value(&self, incoming: Vec3, outgoing: Vec3) -> Vec3 {
+        if incoming.z() <= 0.0 || outgoing.z() <= 0.0 {
+            return Vec3::default();
+        }
+        let incoming = incoming.normalized();
+        let half = (incoming + outgoing.normalized()).normalized();
+        let theta_half = half.z().clamp(-1.0, 1.0).acos();
+        let phi_half = half.y().atan2(half.x());
+
+        // The incoming direction seen from a frame with the half vector as its normal.
+        let difference = rotate_y(rotate_z(incoming, -phi_half), -theta_half);
+        let theta_difference = difference.z().clamp(-1.0, 1.0).acos();
+        // Reciprocity makes the tables symmetric, so only half of the difference angles are stored.
+        let phi_difference = difference.y().atan2(difference.x()).rem_euclid(PI);
+
+        self.values[table_index(theta_half, theta_difference, phi_difference)]
+    }
+}
+
+/// Position in the tables, the half angle is spaced more densely near the normal where highlights are sharp.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_precision_loss)]
+fn table_index(theta_half: f64, theta_difference: f64, phi_difference: f64) -> usize {
+    let index = |value: f64, resolution: usize| (value.max(0.0) as usize).min(resolution - 1);
+    let theta_half = index((theta_half / (0.5 * PI)).sqrt() * THETA_HALF_RESOLUTION as f64, THETA_HALF_RESOLUTION);
+    let theta_difference = index(theta_difference / (0.5 * PI) * THETA_DIFFERENCE_RESOLUTION as f64, THETA_DIFFERENCE_RESOLUTION);
+    let phi_difference = index(phi_difference / PI * PHI_DIFFERENCE_RESOLUTION as f64, PHI_DIFFERENCE_RESOLUTION);
+    (theta_half * THETA_DIFFERENCE_RESOLUTION + theta_difference) * PHI_DIFFERENCE_RESOLUTION + phi_difference
+}
+
+fn rotate_z(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(v.x().mul_add(cos, -v.y() * sin), v.x().mul_add(sin, v.y() * cos), v.z())
+}
+
+fn rotate_y(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(v.x().mul_add(cos, v.z() * sin), v.y(), v.z().mul_add(cos, -v.x() * sin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a MERL file whose stored values are given per table index, the same for all channels.
+    fn encode<F: Fn(usize) -> f64>(stored: F) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + 3 * TABLE_SIZE * 8);
+        for dimension in [90_i32, 90, 180] {
+            bytes.extend_from_slice(&dimension.to_le_bytes());
+        }
+        for _ in 0..3 {
+            for index in 0..TABLE_SIZE {
+                bytes.extend_from_slice(&stored(index).to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn measurements_are_looked_up_by_half_angle() {
+        // Only the first row of half angles, around the mirror direction, reflects.
+        let shiny = MeasuredBrdf::decode(&encode(|index| {
+            if index < THETA_DIFFERENCE_RESOLUTION * PHI_DIFFERENCE_RESOLUTION {1500.0} else {-1.0}
+        })).expect("valid MERL data");
+
+        let incoming = Vec3::new(0.6, 0.0, 0.8);
+        let mirrored = Vec3::new(-0.6, 0.0, 0.8);
+        let value = shiny.value(incoming, mirrored);
+        assert!((value.x() - 1.0).abs() < 1e-12 && (value.y() - 1.15).abs() < 1e-12 && (value.z() - 1.66).abs() < 1e-12);
+        assert_eq!(shiny.value(mirrored, incoming), value);
+        assert_eq!(shiny.value(incoming, Vec3::new(0.0, 0.6, 0.8)), Vec3::default());
+        assert_eq!(shiny.value(incoming, Vec3::new(-0.6, 0.0, -0.8)), Vec3::default());
+    }
+
+    #[test]
+    fn invalid_measurements_are_rejected() {
+        let mut bytes = encode(|_| 1.0);
+        bytes.pop();
+        assert_eq!(MeasuredBrdf::decode(&bytes).map_err(|error| error.kind()), Err(io::ErrorKind::InvalidData));
+        bytes[0] = 45;
+        assert_eq!(MeasuredBrdf::decode(&bytes).map_err(|error| error.kind()), Err(io::ErrorKind::InvalidData));
+        assert!(MeasuredBrdf::decode(&[0; 8]).is_err());
+    }
+}
@@ -0,0 +1,744 @@
+//! Microfacet distributions shared by the rough materials in `brdfs`.
+//!
+//! All directions are unit vectors in the local shading frame, where the z axis is the surface normal,
+//! the x axis the tangent and the y axis the bitangent.
+
+use std::{f64::consts::PI, ops, sync::LazyLock};
+use crate::{spectral, vec_math::{Vec3, cross, dot}};
+
+/// The GGX or Trowbridge-Reitz microfacet distribution, with separate roughness along the tangent and bitangent.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    /// Creates a distribution from the alpha of the distribution along the tangent and bitangent.
+    /// Alpha is limited to be at least 0.001 so very smooth surfaces stay numerically stable.
+    #[must_use]
+    pub const fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x: alpha_x.max(0.001), alpha_y: alpha_y.max(0.001) }
+    }
+
+    /// Creates a distribution from the perceptual roughness in [0, 1] and anisotropy in [-1, 1], where
+    /// positive anisotropy stretches highlights along the tangent and negative along the bitangent.
+    #[must_use]
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = 0.9f64.mul_add(-anisotropy.abs().min(1.0), 1.0).sqrt();
+        if anisotropy >= 0.0 {
+            Self::new(alpha / aspect, alpha * aspect)
+        } else {
+            Self::new(alpha * aspect, alpha / aspect)
+        }
+    }
+
+    /// Density of microfacets with normal `wm`.
+    #[must_use]
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let cos_2_theta = wm.z() * wm.z();
+        if cos_2_theta <= 0.0 {
+            return 0.0;
+        }
+        let e = (wm.y() / self.alpha_y).mul_add(wm.y() / self.alpha_y, (wm.x() / self.alpha_x).powi(2)) + cos_2_theta;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith's auxiliary function, the ratio of hidden to visible microfacet area from direction `w`.
+    #[must_use]
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let cos_2_theta = w.z() * w.z();
+        if cos_2_theta <= 0.0 {
+            return 0.0;
+        }
+        let tan_2_theta_alpha_2 = (w.y() * self.alpha_y).mul_add(w.y() * self.alpha_y, (w.x() * self.alpha_x).powi(2)) / cos_2_theta;
+        ((1.0 + tan_2_theta_alpha_2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of microfacets visible from direction `w`.
+    #[must_use]
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`.
+    #[must_use]
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of microfacet normals `wm` as seen from direction `w`.
+    #[must_use]
+    pub fn d_visible(&self, w: Vec3, wm: Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z().abs() * self.d(wm) * (dot(w, wm) * w.z().signum()).max(0.0)
+    }
+
+    /// Samples a microfacet normal visible from direction `w` from two uniform random numbers in [0, 1),
+    /// using the method of Heitz 2018.
+    #[must_use]
+    #[allow(clippy::many_single_char_names)]
+    pub fn sample_visible_normal(&self, w: Vec3, u: (f64, f64)) -> Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere of radius 1.
+        let flip = if w.z() < 0.0 {-1.0} else {1.0};
+        let wh = Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).normalized() * flip;
+
+        let t1 = if wh.z() < 0.99999 {cross(Vec3::new(0.0, 0.0, 1.0), wh).normalized()} else {Vec3::new(1.0, 0.0, 0.0)};
+        let t2 = cross(wh, t1);
+
+        // Uniformly sample the projected disk, warped towards the visible half.
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let (x, y) = (r * phi.cos(), r * phi.sin());
+        let h = x.mul_add(-x, 1.0).max(0.0).sqrt();
+        let y = f64::midpoint(1.0, wh.z()).mul_add(y, (1.0 - f64::midpoint(1.0, wh.z())) * h);
+
+        let z = x.mul_add(-x, y.mul_add(-y, 1.0)).max(0.0).sqrt();
+        let nh = x * t1 + y * t2 + z * wh;
+
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)).normalized()
+    }
+
+    /// Density of sampling microfacet normal `wm` with `sample_visible_normal` from direction `w`.
+    #[must_use]
+    pub fn pdf(&self, w: Vec3, wm: Vec3) -> f64 {
+        self.d_visible(w, wm)
+    }
+}
+
+/// The generalized Trowbridge-Reitz distribution with an exponent of 1, used for the clear coat of the principled BRDF.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Gtr1 {
+    alpha: f64,
+}
+
+impl Gtr1 {
+    #[must_use]
+    pub const fn new(alpha: f64) -> Self {
+        Self { alpha: alpha.clamp(0.001, 0.999) }
+    }
+
+    /// Density of microfacets with normal `wm`.
+    #[must_use]
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let alpha_2 = self.alpha * self.alpha;
+        let t = (alpha_2 - 1.0).mul_add(wm.z() * wm.z(), 1.0);
+        (alpha_2 - 1.0) / (PI * alpha_2.ln() * t)
+    }
+
+    /// Samples a microfacet normal proportional to `d(wm) * wm.z()` from two uniform random numbers in [0, 1).
+    #[must_use]
+    pub fn sample_normal(&self, u: (f64, f64)) -> Vec3 {
+        let alpha_2 = self.alpha * self.alpha;
+        let cos_theta = ((1.0 - alpha_2.powf(1.0 - u.0)) / (1.0 - alpha_2)).max(0.0).sqrt();
+        let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    /// Density of sampling microfacet normal `wm` with `sample_normal`.
+    #[must_use]
+    pub fn pdf(&self, wm: Vec3) -> f64 {
+        self.d(wm) * wm.z().abs()
+    }
+}
+
+/// Resolution of the table of sheen albedos, along the roughness from 0.1 to 1 and along the cosine of the viewing angle.
+const SHEEN_ALBEDO_ROUGHNESSES: usize = 16;
+const SHEEN_ALBEDO_COSINES: usize = 32;
+
+/// Fraction of light reflected by the sheen of [`Charlie`] per roughness and viewing angle, both from 0 to 1.
+static SHEEN_ALBEDO: LazyLock<Vec<f64>> = LazyLock::new(|| {
+    let steps = 64;
+    let mut table = Vec::with_capacity(SHEEN_ALBEDO_ROUGHNESSES * SHEEN_ALBEDO_COSINES);
+    for roughness in 0..SHEEN_ALBEDO_ROUGHNESSES {
+        let sheen = Charlie::from_roughness(0.9f64.mul_add(grid_value(roughness, SHEEN_ALBEDO_ROUGHNESSES), 0.1));
+        for cosine in 0..SHEEN_ALBEDO_COSINES {
+            let cos_o = grid_value(cosine, SHEEN_ALBEDO_COSINES).max(1e-3);
+            let wo = Vec3::new(cos_o.mul_add(-cos_o, 1.0).sqrt(), 0.0, cos_o);
+            // Midpoint rule over the hemisphere, using the mirror symmetry around the plane of `wo`.
+            let mut albedo = 0.0;
+            for i in 0..steps {
+                let cos_i = (f64::from(i) + 0.5) / f64::from(steps);
+                let sin_i = cos_i.mul_add(-cos_i, 1.0).sqrt();
+                for j in 0..steps {
+                    let phi = PI * (f64::from(j) + 0.5) / f64::from(steps);
+                    let wi = Vec3::new(sin_i * phi.cos(), sin_i * phi.sin(), cos_i);
+                    albedo += sheen.f(wo, wi) * cos_i * 2.0 * PI / f64::from(steps * steps);
+                }
+            }
+            table.push(albedo);
+        }
+    }
+    table
+});
+
+/// The "Charlie" sheen distribution of Estevez and Kulla, for the velvety reflections of cloth.
+///
+/// Its microfacets lie mostly along the surface like the fibers of cloth, which makes it reflect most at grazing
+/// angles. Shadowing is approximated with the smooth visibility term of Neubelt and Pettineo.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Charlie {
+    roughness: f64,
+    alpha: f64,
+}
+
+impl Charlie {
+    /// Creates a distribution from the perceptual roughness in [0, 1].
+    #[must_use]
+    pub fn from_roughness(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.1, 1.0);
+        Self { roughness, alpha: roughness * roughness }
+    }
+
+    /// Density of microfacets with normal `wm`.
+    #[must_use]
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let inverse_alpha = 1.0 / self.alpha;
+        let sin_2 = wm.z().mul_add(-wm.z(), 1.0).max(0.0);
+        (2.0 + inverse_alpha) * sin_2.powf(0.5 * inverse_alpha) / (2.0 * PI)
+    }
+
+    /// The sheen BRDF for light arriving from `wi` and leaving towards `wo`, without Fresnel or color.
+    #[must_use]
+    pub fn f(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let visibility = 1.0 / (4.0 * wi.z().mul_add(-wo.z(), wi.z() + wo.z()));
+        self.d((wo + wi).normalized()) * visibility
+    }
+
+    /// Fraction of the light from `wo` that the sheen reflects, looked up in a precomputed table.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn albedo(&self, wo: Vec3) -> f64 {
+        let position = |value: f64, size: usize| {
+            let x = value.clamp(0.0, 1.0) * (size - 1) as f64;
+            let lower = (x.floor() as usize).min(size - 2);
+            (lower, x - lower as f64)
+        };
+        let (row, t_row) = position((self.roughness - 0.1) / 0.9, SHEEN_ALBEDO_ROUGHNESSES);
+        let (column, t_column) = position(wo.z(), SHEEN_ALBEDO_COSINES);
+        let at = |row: usize, column: usize| SHEEN_ALBEDO[row * SHEEN_ALBEDO_COSINES + column];
+        let lower = (at(row, column + 1) - at(row, column)).mul_add(t_column, at(row, column));
+        let upper = (at(row + 1, column + 1) - at(row + 1, column)).mul_add(t_column, at(row + 1, column));
+        (upper - lower).mul_add(t_row, lower)
+    }
+}
+
+/// Value at `index` of a grid of `size` evenly spaced values from 0 to 1.
+#[allow(clippy::cast_precision_loss)]
+fn grid_value(index: usize, size: usize) -> f64 {
+    index as f64 / (size - 1) as f64
+}
+
+/// Exact Fresnel reflectance of unpolarized light at the boundary of a dielectric with relative index of refraction `ior`.
+///
+/// `cos_theta_i` is the cosine of the angle between the incoming light and the normal, negative when arriving from inside.
+#[must_use]
+pub fn fresnel_dielectric(cos_theta_i: f64, ior: f64) -> f64 {
+    let (cos_theta_i, ior) = if cos_theta_i < 0.0 { (-cos_theta_i, 1.0 / ior) } else { (cos_theta_i, ior) };
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin_2_theta_i = cos_theta_i.mul_add(-cos_theta_i, 1.0);
+    let sin_2_theta_t = sin_2_theta_i / (ior * ior);
+    if sin_2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin_2_theta_t).sqrt();
+
+    let parallel = ior.mul_add(cos_theta_i, -cos_theta_t) / ior.mul_add(cos_theta_i, cos_theta_t);
+    let perpendicular = ior.mul_add(-cos_theta_t, cos_theta_i) / ior.mul_add(cos_theta_t, cos_theta_i);
+    parallel.mul_add(parallel, perpendicular * perpendicular) / 2.0
+}
+
+/// Schlick's weight `(1 - cos_theta)^5` used to interpolate towards grazing reflectance.
+#[must_use]
+pub fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Exact Fresnel reflectance of a conductor with complex index of refraction `eta + i k`,
+/// for light arriving at an angle with cosine `cos_theta_i` from a medium with an index of refraction of 1.
+#[must_use]
+pub fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin_2 = 1.0 - cos_2;
+    let eta_2 = eta * eta;
+    let k_2 = k * k;
+
+    let t0 = eta_2 - k_2 - sin_2;
+    let a_2_plus_b_2 = t0.mul_add(t0, 4.0 * eta_2 * k_2).sqrt();
+    let t1 = a_2_plus_b_2 + cos_2;
+    let a = (0.5 * (a_2_plus_b_2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_2.sqrt() * a;
+    let perpendicular = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos_2.mul_add(a_2_plus_b_2, sin_2 * sin_2);
+    let t4 = t2 * sin_2;
+    let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+
+    0.5 * (parallel + perpendicular)
+}
+
+/// Refracts `w` through a surface with normal `n` on the same side as `w`.
+///
+/// `ior` is the index of refraction on the other side relative to the side of `w`.
+/// Returns `None` on total internal reflection.
+#[must_use]
+pub fn refract(w: Vec3, n: Vec3, ior: f64) -> Option<Vec3> {
+    let cos_theta_i = dot(n, w);
+    let sin_2_theta_i = cos_theta_i.mul_add(-cos_theta_i, 1.0).max(0.0);
+    let sin_2_theta_t = sin_2_theta_i / (ior * ior);
+    if sin_2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin_2_theta_t).sqrt();
+    Some(-w / ior + (cos_theta_i / ior - cos_theta_t) * n)
+}
+
+/// Reflects `w` around normal `n`, both pointing away from the surface.
+#[must_use]
+pub fn reflect(w: Vec3, n: Vec3) -> Vec3 {
+    -w + 2.0 * dot(w, n) * n
+}
+
+/// Complex index of refraction of a conductor for the red, green and blue channels.
+/// `eta` is the real part and `k` the absorption coefficient.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ComplexIor {
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+impl ComplexIor {
+    pub const GOLD: Self = Self::new(Vec3::new(0.143_119, 0.374_957, 1.442_48), Vec3::new(3.983_16, 2.385_72, 1.603_22));
+    pub const COPPER: Self = Self::new(Vec3::new(0.200_438, 0.924_033, 1.102_21), Vec3::new(3.912_95, 2.452_85, 2.142_19));
+    pub const ALUMINIUM: Self = Self::new(Vec3::new(1.657_46, 0.880_369, 0.521_229), Vec3::new(9.223_87, 6.269_52, 4.837));
+    pub const SILVER: Self = Self::new(Vec3::new(0.155_265, 0.116_723, 0.138_342), Vec3::new(4.828_35, 3.122_25, 2.146_96));
+
+    #[must_use]
+    pub const fn new(eta: Vec3, k: Vec3) -> Self {
+        Self { eta, k }
+    }
+
+    /// Fresnel reflectance of each channel for light arriving at an angle with cosine `cos_theta_i`.
+    #[must_use]
+    pub fn fresnel(&self, cos_theta_i: f64) -> Vec3 {
+        Vec3::new(
+            fresnel_conductor(cos_theta_i, self.eta.x(), self.k.x()),
+            fresnel_conductor(cos_theta_i, self.eta.y(), self.k.y()),
+            fresnel_conductor(cos_theta_i, self.eta.z(), self.k.z()),
+        )
+    }
+}
+
+/// A thin transparent film on top of a surface, like soap, oil or an oxide layer, whose reflections interfere with
+/// each other and color the surface depending on the viewing angle.
+/// # Example
+/// ```
+/// use renders::microfacet::{ComplexIor, ThinFilm};
+/// let soap = ThinFilm::new(400.0, 1.33);
+/// let rainbow = soap.reflectance_dielectric(0.7, 1.0);
+/// let oily_steel = ThinFilm::new(300.0, 1.45).reflectance_conductor(0.9, ComplexIor::SILVER);
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ThinFilm {
+    /// Thickness of the film in nanometers, interference colors are strongest between about 100 and 1000.
+    pub thickness: f64,
+    /// Index of refraction of the film.
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    #[must_use]
+    pub const fn new(thickness: f64, ior: f64) -> Self {
+        Self { thickness, ior }
+    }
+
+    /// Reflectance of each channel of the film on a dielectric with relative index of refraction `ior`, computed across
+    /// the visible wavelengths. The film lies on the side of the normal, light may arrive from either side.
+    ///
+    /// `cos_theta_i` is the cosine of the angle between the incoming light and the normal, negative when arriving from inside.
+    #[must_use]
+    pub fn reflectance_dielectric(&self, cos_theta_i: f64, ior: f64) -> Vec3 {
+        let (outside, base) = if cos_theta_i < 0.0 {(ior, 1.0)} else {(1.0, ior)};
+        let cos_theta_i = cos_theta_i.abs().min(1.0);
+        spectral::reflectance_to_rgb(|wavelength| self.reflectance(cos_theta_i, outside, Complex::new(base, 0.0), wavelength))
+    }
+
+    /// Reflectance of each channel of the film on a conductor, computed across the visible wavelengths.
+    /// The index of refraction of the conductor is interpolated between its red, green and blue values.
+    #[must_use]
+    pub fn reflectance_conductor(&self, cos_theta_i: f64, ior: ComplexIor) -> Vec3 {
+        let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+        spectral::reflectance_to_rgb(|wavelength| {
+            let base = Complex::new(interpolate_rgb(ior.eta, wavelength), interpolate_rgb(ior.k, wavelength));
+            self.reflectance(cos_theta_i, 1.0, base, wavelength)
+        })
+    }
+
+    /// Reflectance at a single wavelength of light arriving through a medium with index of refraction `outside`,
+    /// summing all reflections inside the film with the Airy formula, averaged over both polarizations.
+    fn reflectance(&self, cos_theta_i: f64, outside: f64, base: Complex, wavelength: f64) -> f64 {
+        let sin_2_theta_i = cos_theta_i.mul_add(-cos_theta_i, 1.0);
+        // Snell's law with complex cosines, which covers total internal reflection and absorbing bases.
+        let cos_film = Complex::new(1.0 - sin_2_theta_i * (outside / self.ior).powi(2), 0.0).sqrt();
+        let cos_base = (Complex::new(1.0, 0.0) - Complex::new(outside * outside * sin_2_theta_i, 0.0) / (base * base)).sqrt();
+
+        let outside = Complex::new(outside, 0.0);
+        let film = Complex::new(self.ior, 0.0);
+        let cos_theta_i = Complex::new(cos_theta_i, 0.0);
+
+        // Phase difference between light reflected at the top and at the bottom of the film.
+        let phase = Complex::new(0.0, 4.0 * PI * self.thickness / wavelength) * film * cos_film;
+        let delay = phase.exp();
+
+        let airy = |top: Complex, bottom: Complex| ((top + bottom * delay) / (Complex::new(1.0, 0.0) + top * bottom * delay)).norm_sqr();
+        let perpendicular = airy(
+            fresnel_amplitude(outside, cos_theta_i, film, cos_film),
+            fresnel_amplitude(film, cos_film, base, cos_base),
+        );
+        let parallel = airy(
+            fresnel_amplitude(outside, cos_film, film, cos_theta_i),
+            fresnel_amplitude(film, cos_base, base, cos_film),
+        );
+        (0.5 * (perpendicular + parallel)).clamp(0.0, 1.0)
+    }
+}
+
+/// Fresnel amplitude coefficient `(n_i cos_i - n_t cos_t) / (n_i cos_i + n_t cos_t)` of perpendicular polarized light,
+/// swapping the cosines gives the coefficient of parallel polarized light.
+fn fresnel_amplitude(ior_i: Complex, cos_i: Complex, ior_t: Complex, cos_t: Complex) -> Complex {
+    (ior_i * cos_i - ior_t * cos_t) / (ior_i * cos_i + ior_t * cos_t)
+}
+
+/// Interpolates a per channel value over wavelength, placing red, green and blue at 650, 550 and 450 nanometers.
+fn interpolate_rgb(value: Vec3, wavelength: f64) -> f64 {
+    if wavelength < 550.0 {
+        let t = ((wavelength - 450.0) / 100.0).clamp(0.0, 1.0);
+        (value.y() - value.z()).mul_add(t, value.z())
+    } else {
+        let t = ((wavelength - 550.0) / 100.0).clamp(0.0, 1.0);
+        (value.x() - value.y()).mul_add(t, value.y())
+    }
+}
+
+/// Minimal complex numbers for the wave optics of thin films.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re.mul_add(self.re, self.im * self.im)
+    }
+
+    /// Principal square root, with a non negative real part.
+    fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 {-im} else {im})
+    }
+
+    fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Self::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.re.mul_add(rhs.re, -self.im * rhs.im), self.re.mul_add(rhs.im, self.im * rhs.re))
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let denominator = rhs.norm_sqr();
+        Self::new(
+            self.re.mul_add(rhs.re, self.im * rhs.im) / denominator,
+            self.im.mul_add(rhs.re, -self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+/// Microfacet reflection off a conductor, light is either reflected or absorbed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RoughConductor {
+    pub distribution: TrowbridgeReitz,
+    pub ior: ComplexIor,
+    /// Optional thin film on top of the conductor.
+    pub film: Option<ThinFilm>,
+}
+
+impl RoughConductor {
+    /// Fresnel reflectance of each channel for light arriving at an angle with cosine `cos_theta_i` to a microfacet.
+    #[must_use]
+    pub fn fresnel(&self, cos_theta_i: f64) -> Vec3 {
+        self.film.map_or_else(|| self.ior.fresnel(cos_theta_i), |film| film.reflectance_conductor(cos_theta_i, self.ior))
+    }
+
+    /// Evaluates the scattering function times the cosine of `wi`, for light going from `wi` to `wo`.
+    #[must_use]
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return Vec3::default();
+        }
+        let wm = wm.normalized();
+        self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * wo.z()) * self.fresnel(dot(wo, wm))
+    }
+
+    /// Density of sampling `wi` with `sample` given `wo`.
+    #[must_use]
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return 0.0;
+        }
+        let wm = wm.normalized();
+        self.distribution.pdf(wo, wm) / (4.0 * dot(wo, wm))
+    }
+
+    /// Samples an incoming direction for outgoing direction `wo` from two uniform random numbers in [0, 1).
+    #[must_use]
+    pub fn sample(&self, wo: Vec3, u: (f64, f64)) -> Option<Vec3> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wi = reflect(wo, self.distribution.sample_visible_normal(wo, u));
+        (wi.z() > 0.0).then_some(wi)
+    }
+}
+
+/// A rough boundary between two dielectrics, such as frosted glass, that both reflects and refracts light.
+///
+/// `ior` is the index of refraction on the side opposite of the normal relative to the side of the normal.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RoughDielectric {
+    pub distribution: TrowbridgeReitz,
+    pub ior: f64,
+}
+
+impl RoughDielectric {
+    /// Evaluates the scattering function times the cosine of `wi`, for light going from `wi` to `wo`.
+    /// `wo` is expected to lie on the side of the normal.
+    ///
+    /// The scaling of radiance by the squared ratio of the indices of refraction is left out,
+    /// so light entering and leaving a closed object keeps its brightness.
+    #[must_use]
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+
+        if wi.z() > 0.0 {
+            let wm = wo + wi;
+            if wm.near_zero() {
+                return 0.0;
+            }
+            let wm = wm.normalized();
+            let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+            return self.distribution.d(wm) * self.distribution.g(wo, wi) * fresnel / (4.0 * wo.z());
+        }
+
+        let Some(wm) = self.transmission_normal(wo, wi) else {
+            return 0.0;
+        };
+        let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+        let denominator = dot(wi, wm).mul_add(self.ior, dot(wo, wm)).powi(2) / (self.ior * self.ior);
+        self.distribution.d(wm) * self.distribution.g(wo, wi) * (1.0 - fresnel)
+            * (dot(wi, wm) * dot(wo, wm) / (wo.z() * denominator)).abs()
+    }
+
+    /// Density of sampling `wi` with `sample` given `wo`.
+    #[must_use]
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+
+        if wi.z() > 0.0 {
+            let wm = wo + wi;
+            if wm.near_zero() {
+                return 0.0;
+            }
+            let wm = wm.normalized();
+            let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+            return self.distribution.pdf(wo, wm) / (4.0 * dot(wo, wm).abs()) * fresnel;
+        }
+
+        let Some(wm) = self.transmission_normal(wo, wi) else {
+            return 0.0;
+        };
+        let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+        let denominator = dot(wi, wm).mul_add(self.ior, dot(wo, wm)).powi(2) / (self.ior * self.ior);
+        let jacobian = dot(wi, wm).abs() / denominator;
+        self.distribution.pdf(wo, wm) * jacobian * (1.0 - fresnel)
+    }
+
+    /// Samples an incoming direction for outgoing direction `wo`, choosing between reflection and refraction
+    /// by their Fresnel weights using uniform random numbers in [0, 1).
+    #[must_use]
+    pub fn sample(&self, wo: Vec3, u: (f64, f64), u_lobe: f64) -> Option<Vec3> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wm = self.distribution.sample_visible_normal(wo, u);
+        let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+
+        let wi = if u_lobe < fresnel {
+            reflect(wo, wm)
+        } else {
+            refract(wo, wm, self.ior)?
+        };
+        let correct_side = if u_lobe < fresnel { wi.z() > 0.0 } else { wi.z() < 0.0 };
+        correct_side.then_some(wi)
+    }
+
+    /// Microfacet normal that refracts `wi` into `wo`, oriented towards the side of the normal.
+    fn transmission_normal(&self, wo: Vec3, wi: Vec3) -> Option<Vec3> {
+        let wm = self.ior * wi + wo;
+        if wm.near_zero() {
+            return None;
+        }
+        let wm = wm.normalized();
+        let wm = if wm.z() < 0.0 {-wm} else {wm};
+        // Discard back facing microfacets.
+        (dot(wm, wi) * wi.z() >= 0.0 && dot(wm, wo) * wo.z() >= 0.0).then_some(wm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integrate<F: Fn(Vec3) -> f64>(f: F, resolution: u32) -> f64 {
+        // Midpoint rule over the hemisphere in spherical coordinates.
+        let mut sum = 0.0;
+        let d_theta = PI / 2.0 / f64::from(resolution);
+        let d_phi = 2.0 * PI / f64::from(resolution);
+        for i in 0..resolution {
+            let theta = (f64::from(i) + 0.5) * d_theta;
+            for j in 0..resolution {
+                let phi = (f64::from(j) + 0.5) * d_phi;
+                let w = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                sum += f(w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn ggx_normals_are_normalized() {
+        let distribution = TrowbridgeReitz::new(0.5, 0.3);
+        let projected_area = integrate(|wm| distribution.d(wm) * wm.z(), 400);
+        assert!((projected_area - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn visible_normals_integrate_to_one() {
+        let distribution = TrowbridgeReitz::new(0.4, 0.4);
+        let wo = Vec3::new(0.5, 0.1, 0.8).normalized();
+        let total = integrate(|wm| distribution.d_visible(wo, wm), 400);
+        assert!((total - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn conductor_fresnel_limits() {
+        let gold = ComplexIor::GOLD.fresnel(1.0);
+        assert!(gold.x() > gold.y() && gold.y() > gold.z());
+        assert!((fresnel_conductor(0.0, 0.2, 3.9) - 1.0).abs() < 1e-9);
+
+        // Without absorption the conductor Fresnel equals the dielectric one.
+        for cos_theta in [0.1, 0.5, 0.9] {
+            assert!((fresnel_conductor(cos_theta, 1.5, 0.0) - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn sampled_visible_normals_face_viewer() {
+        let distribution = TrowbridgeReitz::new(0.6, 0.2);
+        let wo = Vec3::new(0.7, 0.0, 0.3).normalized();
+        for i in 0..100 {
+            let u = (f64::from(i) / 100.0, f64::from(i * 37 % 100) / 100.0);
+            let wm = distribution.sample_visible_normal(wo, u);
+            assert!((wm.length() - 1.0).abs() < 1e-9);
+            assert!(wm.z() > 0.0);
+            assert!(dot(wo, wm) >= -1e-9);
+        }
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-9);
+        assert!((fresnel_dielectric(-0.1, 1.5) - 1.0).abs() < 1e-9);
+        assert!((fresnel_dielectric(1.0, 1.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thin_film_without_thickness_matches_fresnel() {
+        let film = ThinFilm::new(0.0, 1.33);
+        for cos_theta in [0.9, 0.4, -0.8] {
+            let reflectance = film.reflectance_dielectric(cos_theta, 1.5);
+            let expected = fresnel_dielectric(cos_theta, 1.5);
+            assert!((reflectance - Vec3::new(expected, expected, expected)).length() < 1e-9);
+        }
+
+        let gray_metal = ComplexIor::new(Vec3::new(1.2, 1.2, 1.2), Vec3::new(3.0, 3.0, 3.0));
+        let reflectance = film.reflectance_conductor(0.7, gray_metal);
+        let expected = fresnel_conductor(0.7, 1.2, 3.0);
+        assert!((reflectance - Vec3::new(expected, expected, expected)).length() < 1e-9);
+    }
+
+    #[test]
+    fn soap_films_color_reflections() {
+        let soap = ThinFilm::new(400.0, 1.33);
+        let straight = soap.reflectance_dielectric(1.0, 1.0);
+        let grazing = soap.reflectance_dielectric(0.5, 1.0);
+        for reflectance in [straight, grazing] {
+            let (min, max) = (reflectance.x().min(reflectance.y()).min(reflectance.z()), reflectance.x().max(reflectance.y()).max(reflectance.z()));
+            assert!(min >= 0.0 && max <= 1.0);
+            assert!(max - min > 0.02);
+        }
+        // The color changes with the viewing angle.
+        assert!((straight - grazing).length() > 0.02);
+    }
+}
@@ -0,0 +1,457 @@
+//! Procedural noise and the textures built from it.
+//!
+//! All noise is generated from a seed, the same seed always gives the same noise.
+
+use crate::{colors::Color, textures::Texture, vec_math::{Vec3, dot, lerp}};
+
+/// Gradient noise as described by Ken Perlin, smoothly varying between -1 and 1 with features about 1 unit apart.
+/// # Example
+/// ```
+/// use renders::{noise::Perlin, vec_math::Vec3};
+/// let perlin = Perlin::new(42);
+/// let value = perlin.noise(Vec3::new(0.3, 1.7, -2.2));
+/// assert!((-1.0..=1.0).contains(&value));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    permutation: [u8; Self::SIZE],
+    gradients: [Vec3; Self::SIZE],
+}
+
+impl Perlin {
+    const SIZE: usize = 256;
+
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        let mut random = SplitMix64(seed);
+
+        let mut gradients = [Vec3::default(); Self::SIZE];
+        for gradient in &mut gradients {
+            // Uniformly distributed direction on the unit sphere.
+            let z = 2.0f64.mul_add(-random.next_f64(), 1.0);
+            let r = z.mul_add(-z, 1.0).max(0.0).sqrt();
+            let phi = 2.0 * std::f64::consts::PI * random.next_f64();
+            *gradient = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+        }
+
+        let mut permutation = [0u8; Self::SIZE];
+        for (i, entry) in permutation.iter_mut().enumerate() {
+            *entry = u8::try_from(i).unwrap_or(u8::MAX);
+        }
+        // Fisher-Yates shuffle.
+        for i in (1..Self::SIZE).rev() {
+            #[allow(clippy::cast_possible_truncation)]
+            let j = (random.next() % (i as u64 + 1)) as usize;
+            permutation.swap(i, j);
+        }
+
+        Self { permutation, gradients }
+    }
+
+    /// Returns the noise at `point`, in the range [-1, 1].
+    #[must_use]
+    pub fn noise(&self, point: Vec3) -> f64 {
+        let floor = Vec3::new(point.x().floor(), point.y().floor(), point.z().floor());
+        let fraction = point - floor;
+        let fade = |t: f64| t * t * t * t.mul_add(t.mul_add(6.0, -15.0), 10.0);
+        let (u, v, w) = (fade(fraction.x()), fade(fraction.y()), fade(fraction.z()));
+
+        #[allow(clippy::cast_possible_truncation)]
+        let cell = [floor.x() as i64, floor.y() as i64, floor.z() as i64];
+        let corner = |dx: i64, dy: i64, dz: i64| {
+            #[allow(clippy::cast_precision_loss)]
+            let offset = fraction - Vec3::new(dx as f64, dy as f64, dz as f64);
+            dot(self.gradients[self.hash(cell[0] + dx, cell[1] + dy, cell[2] + dz)], offset)
+        };
+
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+        let value = lerp(lerp(x00, x10, v), lerp(x01, x11, v), w);
+
+        // Gradient noise of unit gradients stays within ±√3/2, scale it to fill [-1, 1].
+        (value * 2.0 / 3.0f64.sqrt()).clamp(-1.0, 1.0)
+    }
+
+    /// Fractal Brownian motion, the sum of `octaves` layers of noise that each double in frequency and halve in strength.
+    /// Returns a value in the range [-2, 2].
+    #[must_use]
+    pub fn fbm(&self, point: Vec3, octaves: u32) -> f64 {
+        self.octaves(point, octaves, |noise| noise)
+    }
+
+    /// Like [`Perlin::fbm`] but summing the absolute value of each layer, giving billowy features with sharp creases.
+    /// Returns a value in the range [0, 2].
+    #[must_use]
+    pub fn turbulence(&self, point: Vec3, octaves: u32) -> f64 {
+        self.octaves(point, octaves, f64::abs)
+    }
+
+    fn octaves<F: Fn(f64) -> f64>(&self, point: Vec3, octaves: u32, layer: F) -> f64 {
+        let mut sum = 0.0;
+        let mut point = point;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            sum += weight * layer(self.noise(point));
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+        sum
+    }
+
+    fn hash(&self, x: i64, y: i64, z: i64) -> usize {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let index = |coordinate: i64| (coordinate & 255) as usize;
+        let hash = self.permutation[index(x)] as usize;
+        let hash = self.permutation[(hash + index(y)) & 255] as usize;
+        self.permutation[(hash + index(z)) & 255] as usize
+    }
+}
+
+/// Cellular noise as described by Steven Worley: distances to randomly scattered feature points, one per unit cube.
+/// # Example
+/// ```
+/// use renders::{noise::Worley, vec_math::Vec3};
+/// let worley = Worley::new(7);
+/// let (nearest, second_nearest) = worley.distances(Vec3::new(0.3, 1.7, -2.2));
+/// assert!(nearest <= second_nearest);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Returns the distance to the nearest and second nearest feature points.
+    #[must_use]
+    pub fn distances(&self, point: Vec3) -> (f64, f64) {
+        #[allow(clippy::cast_possible_truncation)]
+        let cell = [point.x().floor() as i64, point.y().floor() as i64, point.z().floor() as i64];
+        let mut nearest = f64::INFINITY;
+        let mut second_nearest = f64::INFINITY;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let distance = (self.feature_point(cell[0] + dx, cell[1] + dy, cell[2] + dz) - point).length();
+                    if distance < nearest {
+                        second_nearest = nearest;
+                        nearest = distance;
+                    } else if distance < second_nearest {
+                        second_nearest = distance;
+                    }
+                }
+            }
+        }
+        (nearest, second_nearest)
+    }
+
+    /// The feature point of a cell, somewhere inside of the cell.
+    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_precision_loss)]
+    fn feature_point(self, x: i64, y: i64, z: i64) -> Vec3 {
+        let mut random = SplitMix64(
+            self.seed
+                ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+                ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9),
+        );
+        Vec3::new(x as f64 + random.next_f64(), y as f64 + random.next_f64(), z as f64 + random.next_f64())
+    }
+}
+
+/// Small deterministic random number generator, so noise does not change between versions of the `rand` crate.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed number in [0, 1).
+    #[allow(clippy::cast_precision_loss)]
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn mix(low: Color, high: Color, t: f64) -> Color {
+    let (low, high): (Vec3, Vec3) = (low.into(), high.into());
+    let t = t.clamp(0.0, 1.0);
+    ((1.0 - t) * low + t * high).into()
+}
+
+/// Perlin noise as a texture, blending between two colors.
+/// # Example
+/// ```
+/// use renders::{brdfs, noise::NoiseTexture};
+/// let material = brdfs::make_lambertian_diffuse_brdf(NoiseTexture::new(1, 4.0));
+/// ```
+pub struct NoiseTexture {
+    perlin: Perlin,
+    scale: f64,
+    octaves: u32,
+    low: Color,
+    high: Color,
+}
+
+impl NoiseTexture {
+    /// Creates a black and white noise texture with features `1 / scale` apart.
+    #[must_use]
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self { perlin: Perlin::new(seed), scale, octaves: 1, low: Color::new(0.0, 0.0, 0.0), high: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    /// Adds layers of finer detail, see [`Perlin::fbm`].
+    #[must_use]
+    pub const fn set_octaves(self, octaves: u32) -> Self {
+        Self { octaves, ..self }
+    }
+
+    /// Sets the colors the lowest and highest values of the noise map to.
+    #[must_use]
+    pub const fn set_colors(self, low: Color, high: Color) -> Self {
+        Self { low, high, ..self }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Color {
+        let noise = self.perlin.fbm(self.scale * point, self.octaves.max(1));
+        mix(self.low, self.high, 0.5 * (1.0 + noise))
+    }
+}
+
+/// Turbulence as a texture, blending between two colors.
+pub struct TurbulenceTexture {
+    perlin: Perlin,
+    scale: f64,
+    octaves: u32,
+    low: Color,
+    high: Color,
+}
+
+impl TurbulenceTexture {
+    /// Creates a black and white turbulence texture with 7 octaves and the largest features `1 / scale` apart.
+    #[must_use]
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self { perlin: Perlin::new(seed), scale, octaves: 7, low: Color::new(0.0, 0.0, 0.0), high: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    #[must_use]
+    pub const fn set_octaves(self, octaves: u32) -> Self {
+        Self { octaves, ..self }
+    }
+
+    /// Sets the colors the lowest and highest values of the turbulence map to.
+    #[must_use]
+    pub const fn set_colors(self, low: Color, high: Color) -> Self {
+        Self { low, high, ..self }
+    }
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Color {
+        mix(self.low, self.high, self.perlin.turbulence(self.scale * point, self.octaves))
+    }
+}
+
+/// Veined marble, stripes along the z axis disturbed by turbulence.
+/// # Example
+/// ```
+/// use renders::{colors::Color, noise::MarbleTexture};
+/// let marble = MarbleTexture::new(3, 4.0).set_colors(Color::new(0.2, 0.2, 0.25), Color::new(0.95, 0.93, 0.9));
+/// ```
+pub struct MarbleTexture {
+    perlin: Perlin,
+    scale: f64,
+    turbulence: f64,
+    vein: Color,
+    stone: Color,
+}
+
+impl MarbleTexture {
+    /// Creates black and white marble with stripes about `π / scale` apart.
+    #[must_use]
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self { perlin: Perlin::new(seed), scale, turbulence: 10.0, vein: Color::new(0.0, 0.0, 0.0), stone: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    /// Sets how strongly turbulence bends the stripes.
+    #[must_use]
+    pub const fn set_turbulence(self, turbulence: f64) -> Self {
+        Self { turbulence, ..self }
+    }
+
+    #[must_use]
+    pub const fn set_colors(self, vein: Color, stone: Color) -> Self {
+        Self { vein, stone, ..self }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Color {
+        let phase = self.turbulence.mul_add(self.perlin.turbulence(point, 7), self.scale * point.z());
+        mix(self.vein, self.stone, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// Wood grain, rings around the y axis made irregular by noise.
+/// # Example
+/// ```
+/// use renders::{colors::Color, noise::WoodTexture};
+/// let oak = WoodTexture::new(5, 12.0).set_colors(Color::new(0.35, 0.2, 0.08), Color::new(0.7, 0.5, 0.3));
+/// ```
+pub struct WoodTexture {
+    perlin: Perlin,
+    rings: f64,
+    distortion: f64,
+    dark: Color,
+    light: Color,
+}
+
+impl WoodTexture {
+    /// Creates wood with `rings` rings per unit of distance from the y axis.
+    #[must_use]
+    pub fn new(seed: u64, rings: f64) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            rings,
+            distortion: 0.1,
+            dark: Color::new(0.3, 0.17, 0.07),
+            light: Color::new(0.65, 0.45, 0.25),
+        }
+    }
+
+    /// Sets how far the noise moves the rings, in units of distance.
+    #[must_use]
+    pub const fn set_distortion(self, distortion: f64) -> Self {
+        Self { distortion, ..self }
+    }
+
+    #[must_use]
+    pub const fn set_colors(self, dark: Color, light: Color) -> Self {
+        Self { dark, light, ..self }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Color {
+        // Stretch the noise along the grain, so the rings wobble slowly along the trunk.
+        let grain = Vec3::new(4.0 * point.x(), 0.5 * point.y(), 4.0 * point.z());
+        let radius = self.distortion.mul_add(self.perlin.fbm(grain, 3), point.x().hypot(point.z()));
+        let ring = (radius * self.rings).rem_euclid(1.0);
+        mix(self.light, self.dark, ring * ring * 2.0f64.mul_add(-ring, 3.0))
+    }
+}
+
+/// What a [`CellularTexture`] shows.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CellularPattern {
+    /// Distance to the nearest feature point, giving round spots.
+    #[default]
+    Spots,
+    /// Difference between the distances to the two nearest feature points, which is zero on the borders between cells.
+    Cells,
+}
+
+/// Worley noise as a texture, for stones, scales and cells.
+/// # Example
+/// ```
+/// use renders::{noise::{CellularPattern, CellularTexture}};
+/// let cobbles = CellularTexture::new(9, 3.0).set_pattern(CellularPattern::Cells);
+/// ```
+pub struct CellularTexture {
+    worley: Worley,
+    scale: f64,
+    pattern: CellularPattern,
+    low: Color,
+    high: Color,
+}
+
+impl CellularTexture {
+    /// Creates a black and white texture with cells `1 / scale` apart.
+    #[must_use]
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self {
+            worley: Worley::new(seed),
+            scale,
+            pattern: CellularPattern::Spots,
+            low: Color::new(0.0, 0.0, 0.0),
+            high: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    #[must_use]
+    pub const fn set_pattern(self, pattern: CellularPattern) -> Self {
+        Self { pattern, ..self }
+    }
+
+    #[must_use]
+    pub const fn set_colors(self, low: Color, high: Color) -> Self {
+        Self { low, high, ..self }
+    }
+}
+
+impl Texture for CellularTexture {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Color {
+        let (nearest, second_nearest) = self.worley.distances(self.scale * point);
+        let value = match self.pattern {
+            CellularPattern::Spots => nearest,
+            CellularPattern::Cells => second_nearest - nearest,
+        };
+        mix(self.low, self.high, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_is_deterministic_and_bounded() {
+        let a = Perlin::new(3);
+        let b = Perlin::new(3);
+        let c = Perlin::new(4);
+        let mut differs = false;
+        for i in 0..1000 {
+            let point = Vec3::new(f64::from(i) * 0.137, f64::from(i) * -0.071, f64::from(i) * 0.029);
+            let value = a.noise(point);
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - b.noise(point)).abs() < f64::EPSILON);
+            differs |= (value - c.noise(point)).abs() > 1e-6;
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn perlin_is_zero_on_lattice_points() {
+        let perlin = Perlin::new(11);
+        for point in [Vec3::new(0.0, 0.0, 0.0), Vec3::new(3.0, -2.0, 7.0), Vec3::new(-5.0, 1.0, -1.0)] {
+            assert!(perlin.noise(point).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn worley_distances_are_ordered_and_continuous() {
+        let worley = Worley::new(5);
+        let mut previous = worley.distances(Vec3::new(0.0, 0.5, 0.5));
+        for i in 1..=400 {
+            let (nearest, second_nearest) = worley.distances(Vec3::new(f64::from(i) * 0.01, 0.5, 0.5));
+            assert!(nearest <= second_nearest);
+            // The nearest distance can change no faster than the point moves.
+            assert!((nearest - previous.0).abs() <= 0.01 + 1e-9);
+            previous = (nearest, second_nearest);
+        }
+    }
+}
@@ -23,6 +23,18 @@ impl PixelBuffer {
         }
     }
 
+    /// Returns the width of the buffer in pixels.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the buffer in pixels.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
     /// Set pixel at coordinate x, y. Both x and y are zero indexed
     /// # Panics
     /// Panics if x or y fail a bounds check
@@ -51,7 +63,7 @@ impl PixelBuffer {
 
     /// Iterates over just the pixel locations, from left to right and top to bottom.
     #[must_use]
-    pub fn iter_locations(&self) -> PixelLocationIterator {
+    pub const fn iter_locations(&self) -> PixelLocationIterator {
         PixelLocationIterator::new(self.width, self.height)
     }
     
@@ -79,6 +91,24 @@ impl Display for PixelBuffer {
     }
 }
 
+impl<'a> IntoIterator for &'a PixelBuffer {
+    type Item = (Color, usize, usize);
+    type IntoIter = PixelIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut PixelBuffer {
+    type Item = (&'a mut Color, usize, usize);
+    type IntoIter = PixelIteratorMut<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
 pub struct PixelLocationIterator {
     iter: core::ops::Range<usize>,
     width: usize,
@@ -86,7 +116,7 @@ pub struct PixelLocationIterator {
 
 impl PixelLocationIterator {
     #[must_use]
-    fn new(width: usize, height: usize) -> Self {
+    const fn new(width: usize, height: usize) -> Self {
         Self { iter: 0..(width * height), width }
     }
 }
@@ -114,7 +144,7 @@ impl<'a> PixelIterator<'a> {
     }
 }
 
-impl<'a> Iterator for PixelIterator<'a> {
+impl Iterator for PixelIterator<'_> {
     type Item = (Color, usize, usize);
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -151,6 +181,7 @@ impl<'a> Iterator for PixelIteratorMut<'a>{
 
 
 #[cfg(test)]
+#[allow(clippy::cast_precision_loss, clippy::explicit_iter_loop, clippy::semicolon_if_nothing_returned)]
 mod test {
     use super::*;
 
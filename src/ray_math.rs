@@ -1,4 +1,4 @@
-use crate::vec_math::Vec3;
+use crate::{interval::Interval, vec_math::Vec3};
 
 /// Struct for representing mathmatical rays, consisting of an origin and a direction.
 /// # Example
@@ -17,18 +17,76 @@ use crate::vec_math::Vec3;
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    differentials: Option<RayDifferentials>,
+    wavelength: Option<f64>,
+}
+
+/// Rays through the neighbouring pixels to the right and below, offset from the main ray.
+///
+/// Where they hit gives the size of a pixel on a surface, which textures use to filter away detail that is too small to see.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RayDifferentials {
+    pub x_origin: Vec3,
+    pub x_direction: Vec3,
+    pub y_origin: Vec3,
+    pub y_direction: Vec3,
 }
 
 impl Ray {
-    /// Creates a new ray with origin `origin` and direction `direction.normalized()`.
+    /// Creates a new ray with origin `origin` and direction `direction.normalized()`, without differentials or wavelength.
     #[must_use]
     pub const fn new(origin: Vec3, direction: Vec3) -> Self {
         Self {
             origin,
             direction,
+            differentials: None,
+            wavelength: None,
         }
     }
 
+    /// Attaches the offset rays of neighbouring pixels to the ray.
+    /// # Example
+    /// ```
+    /// use renders::{ray_math::{Ray, RayDifferentials}, vec_math::Vec3};
+    /// let origin = Vec3::new(0.0, 0.0, 0.0);
+    /// let ray = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0)).set_differentials(RayDifferentials {
+    ///     x_origin: origin,
+    ///     x_direction: Vec3::new(0.01, 0.0, -1.0),
+    ///     y_origin: origin,
+    ///     y_direction: Vec3::new(0.0, -0.01, -1.0),
+    /// });
+    /// assert!(ray.differentials().is_some());
+    /// ```
+    #[must_use]
+    pub const fn set_differentials(self, differentials: RayDifferentials) -> Self {
+        Self { differentials: Some(differentials), ..self }
+    }
+
+    /// Returns the offset rays of neighbouring pixels, if the ray has them.
+    #[must_use]
+    pub const fn differentials(self) -> Option<RayDifferentials> {
+        self.differentials
+    }
+
+    /// Makes the ray carry light of a single wavelength in nanometers, as in spectral rendering.
+    /// # Example
+    /// ```
+    /// use renders::{ray_math::Ray, vec_math::Vec3};
+    /// let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    /// assert_eq!(ray.wavelength(), None);
+    /// assert_eq!(ray.set_wavelength(550.0).wavelength(), Some(550.0));
+    /// ```
+    #[must_use]
+    pub const fn set_wavelength(self, wavelength: f64) -> Self {
+        Self { wavelength: Some(wavelength), ..self }
+    }
+
+    /// Returns the wavelength of the light the ray carries, if it carries a single one.
+    #[must_use]
+    pub const fn wavelength(self) -> Option<f64> {
+        self.wavelength
+    }
+
     /// Returns the point t distance along the ray.
     /// ```
     /// use renders::{ray_math::*, vec_math::*};
@@ -56,6 +114,31 @@ impl Ray {
     }
 }
 
+/// Finds the segment of the ray, limited to `ray_t`, that lies inside of the axis aligned box from `min` to `max`.
+/// Returns the ray parameters where the ray enters and exits the box. Flat boxes are crossed in a single point.
+pub(crate) fn box_crossings(min: Vec3, max: Vec3, ray: Ray, ray_t: Interval) -> Option<(f64, f64)> {
+    let mut entry = ray_t.min();
+    let mut exit = ray_t.max();
+
+    for (origin, direction, min, max) in [
+        (ray.origin().x(), ray.direction().x(), min.x(), max.x()),
+        (ray.origin().y(), ray.direction().y(), min.y(), max.y()),
+        (ray.origin().z(), ray.direction().z(), min.z(), max.z()),
+    ] {
+        let inverse = 1.0 / direction;
+        let t0 = (min - origin) * inverse;
+        let t1 = (max - origin) * inverse;
+        let (near, far) = if t0 < t1 {(t0, t1)} else {(t1, t0)};
+        entry = entry.max(near);
+        exit = exit.min(far);
+        if exit < entry {
+            return None;
+        }
+    }
+
+    Some((entry, exit))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,4 +154,17 @@ mod tests {
         assert_eq!(origin, ray.origin());
         assert_eq!(direction, ray.direction());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rays_cross_boxes() {
+        let ray = Ray::new(Vec3::new(0.0, 0.5, -3.0), Vec3::new(0.0, 0.0, 1.0));
+        let (min, max) = (Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(box_crossings(min, max, ray, Interval::new(0.0, f64::INFINITY)), Some((2.0, 4.0)));
+        assert_eq!(box_crossings(min, max, ray, Interval::new(0.0, 3.0)), Some((2.0, 3.0)));
+        assert_eq!(box_crossings(min, max, ray, Interval::new(5.0, f64::INFINITY)), None);
+
+        let down = Ray::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let flat = Vec3::new(1.0, 0.0, 1.0);
+        assert_eq!(box_crossings(-flat, flat, down, Interval::new(0.0, f64::INFINITY)), Some((2.0, 2.0)));
+    }
+}
@@ -0,0 +1,189 @@
+//! Warps from uniform random numbers to points and directions with a known density.
+//!
+//! Every function takes its random numbers as an explicit `u` with components in [0, 1), so callers choose where they
+//! come from, such as independent random numbers or the strata of [`stratify`]. Directions are unit vectors around
+//! the z axis, densities of directions are per solid angle and densities of points per unit of area.
+//! # Example
+//! ```
+//! use renders::sampling;
+//! let direction = sampling::cosine_hemisphere((rand::random(), rand::random()));
+//! let pdf = sampling::cosine_hemisphere_pdf(direction.z());
+//! ```
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+use crate::vec_math::Vec3;
+
+/// Moves `u` into cell `index` of a grid of `columns` by `rows` cells over [0, 1)², counted row by row.
+///
+/// Taking one sample in each cell spreads them more evenly than independent samples, which lowers the noise.
+/// # Panics
+/// Panics if `index` is not less than the number of cells.
+/// # Example
+/// ```
+/// use renders::sampling;
+/// assert_eq!(sampling::stratify(5, 4, 2, (0.5, 0.5)), (0.375, 0.75));
+/// ```
+#[must_use]
+pub fn stratify(index: u32, columns: u32, rows: u32, u: (f64, f64)) -> (f64, f64) {
+    assert!(index < columns * rows);
+    (
+        (f64::from(index % columns) + u.0) / f64::from(columns),
+        (f64::from(index / columns) + u.1) / f64::from(rows),
+    )
+}
+
+/// Samples a direction uniformly over the whole sphere.
+#[must_use]
+pub fn uniform_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 2.0f64.mul_add(-u.0, 1.0);
+    around_z(z, 2.0 * PI * u.1)
+}
+
+/// Density of [`uniform_sphere`].
+#[must_use]
+pub const fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+/// Samples a direction uniformly over the hemisphere above the xy plane.
+#[must_use]
+pub fn uniform_hemisphere(u: (f64, f64)) -> Vec3 {
+    around_z(u.0, 2.0 * PI * u.1)
+}
+
+/// Density of [`uniform_hemisphere`].
+#[must_use]
+pub const fn uniform_hemisphere_pdf() -> f64 {
+    1.0 / (2.0 * PI)
+}
+
+/// Samples a direction above the xy plane proportional to the cosine of its angle with the z axis, by projecting a
+/// point on the unit disk up onto the hemisphere.
+#[must_use]
+pub fn cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let (x, y) = concentric_disk(u);
+    Vec3::new(x, y, x.mul_add(-x, y.mul_add(-y, 1.0)).max(0.0).sqrt())
+}
+
+/// Density of [`cosine_hemisphere`] for a direction with cosine `cos_theta`, zero below the xy plane.
+#[must_use]
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.0) / PI
+}
+
+/// Samples a point uniformly on the unit disk, with the concentric mapping of Shirley and Chiu that keeps strata
+/// of `u` close together.
+#[must_use]
+pub fn concentric_disk(u: (f64, f64)) -> (f64, f64) {
+    let (x, y) = (2.0f64.mul_add(u.0, -1.0), 2.0f64.mul_add(u.1, -1.0));
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (radius, angle) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_4.mul_add(-(x / y), FRAC_PI_2))
+    };
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+/// Density of [`concentric_disk`].
+#[must_use]
+pub const fn concentric_disk_pdf() -> f64 {
+    1.0 / PI
+}
+
+/// Samples a direction uniformly inside the cone around the z axis whose angle has cosine `cos_theta_max`,
+/// such as the directions towards a spherical light.
+#[must_use]
+pub fn uniform_cone(u: (f64, f64), cos_theta_max: f64) -> Vec3 {
+    let z = (cos_theta_max - 1.0).mul_add(u.0, 1.0);
+    around_z(z, 2.0 * PI * u.1)
+}
+
+/// Density of [`uniform_cone`].
+#[must_use]
+pub fn uniform_cone_pdf(cos_theta_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+/// Samples a point uniformly on a triangle and returns its barycentric coordinates, which weigh the corners.
+#[must_use]
+pub fn uniform_triangle(u: (f64, f64)) -> (f64, f64, f64) {
+    let (b0, b1) = if u.0 < u.1 {
+        let b0 = u.0 / 2.0;
+        (b0, u.1 - b0)
+    } else {
+        let b1 = u.1 / 2.0;
+        (u.0 - b1, b1)
+    };
+    (b0, b1, 1.0 - b0 - b1)
+}
+
+/// Density of [`uniform_triangle`] on a triangle of area `area`.
+#[must_use]
+pub fn uniform_triangle_pdf(area: f64) -> f64 {
+    1.0 / area
+}
+
+/// Returns the unit vector with z component `z` at angle `phi` around the z axis.
+fn around_z(z: f64, phi: f64) -> Vec3 {
+    let radius = z.mul_add(-z, 1.0).max(0.0).sqrt();
+    Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDE: u32 = 64;
+
+    /// Averages `f` over one sample in the middle of each stratum, which makes the estimates deterministic.
+    fn stratified_average<F: Fn((f64, f64)) -> f64>(f: F) -> f64 {
+        (0..SIDE * SIDE).map(|index| f(stratify(index, SIDE, SIDE, (0.5, 0.5)))).sum::<f64>() / f64::from(SIDE * SIDE)
+    }
+
+    /// Checks that the integral of z² over the upper hemisphere comes out as 2π / 3, and by symmetry that of x as 0.
+    fn assert_integrates<S: Fn((f64, f64)) -> Vec3, P: Fn(Vec3) -> f64>(sample: S, pdf: P) {
+        let z_squared = stratified_average(|u| {
+            let direction = sample(u);
+            if direction.z() > 0.0 {direction.z().powi(2) / pdf(direction)} else {0.0}
+        });
+        let x = stratified_average(|u| sample(u).x() / pdf(sample(u)));
+        assert!((z_squared - 2.0 * PI / 3.0).abs() < 1e-2, "{z_squared}");
+        assert!(x.abs() < 1e-2, "{x}");
+        assert!((stratified_average(|u| sample(u).length()) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn directions_integrate_with_their_pdfs() {
+        assert_integrates(uniform_sphere, |_| uniform_sphere_pdf());
+        assert_integrates(uniform_hemisphere, |_| uniform_hemisphere_pdf());
+        assert_integrates(cosine_hemisphere, |direction| cosine_hemisphere_pdf(direction.z()));
+
+        let cos_theta_max: f64 = 0.8;
+        let z_squared = stratified_average(|u| uniform_cone(u, cos_theta_max).z().powi(2) / uniform_cone_pdf(cos_theta_max));
+        assert!((z_squared - 2.0 * PI * (1.0 - cos_theta_max.powi(3)) / 3.0).abs() < 1e-3);
+        assert!((0..SIDE * SIDE).all(|index| uniform_cone(stratify(index, SIDE, SIDE, (0.5, 0.5)), cos_theta_max).z() >= cos_theta_max));
+    }
+
+    #[test]
+    fn points_are_spread_uniformly() {
+        // Over the unit disk the average squared radius is 1/2, and over a triangle each corner weighs 1/3.
+        let radius_squared = stratified_average(|u| {
+            let (x, y) = concentric_disk(u);
+            x.mul_add(x, y * y)
+        });
+        assert!((radius_squared - 0.5).abs() < 1e-3);
+        assert!(stratified_average(|u| concentric_disk(u).0).abs() < 1e-9);
+
+        for corner in 0..3 {
+            let weight = stratified_average(|u| {
+                let (b0, b1, b2) = uniform_triangle(u);
+                assert!(b0 >= 0.0 && b1 >= 0.0 && b2 >= -1e-12);
+                [b0, b1, b2][corner]
+            });
+            assert!((weight - 1.0 / 3.0).abs() < 1e-3);
+        }
+    }
+}
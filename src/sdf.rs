@@ -0,0 +1,427 @@
+use std::sync::Arc;
+use crate::{HitRecord, Hittable, brdfs::BRDF, calculate_face_normal, first_opaque_hit, interval::Interval, ray_math::Ray, vec_math::{Vec3, cross, dot, lerp, orthonormal_basis}};
+
+/// Type of a signed distance function: the distance from a point to the surface, negative inside of it.
+pub type Sdf = Arc<dyn Fn(Vec3) -> f64 + Send + Sync>;
+
+/// A surface defined by a signed distance function, rendered by sphere tracing.
+///
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, sdf::{self, SdfObject}, vec_math::Vec3};
+/// let blob = sdf::smooth_union(
+///     sdf::make_sphere_sdf(0.5),
+///     sdf::translate(sdf::make_sphere_sdf(0.3), Vec3::new(0.5, 0.0, 0.0)),
+///     0.2,
+/// );
+/// let object = SdfObject::new(blob, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)))
+///     .set_max_steps(512);
+/// ```
+/// ## Default values:
+/// - `max_steps`: 256,
+/// - `epsilon`: 0.0001,
+/// - `max_distance`: 100.0,
+/// - `step_scale`: 1.0
+pub struct SdfObject {
+    distance: Sdf,
+    surface_shader: BRDF,
+    max_steps: u32,
+    epsilon: f64,
+    max_distance: f64,
+    step_scale: f64,
+}
+
+impl SdfObject {
+    /// Creates a new object from its signed distance function, with the default marching settings.
+    #[must_use]
+    pub fn new(distance: Sdf, surface_shader: BRDF) -> Self {
+        Self {
+            distance,
+            surface_shader,
+            max_steps: 256,
+            epsilon: 0.0001,
+            max_distance: 100.0,
+            step_scale: 1.0,
+        }
+    }
+
+    /// Sets the maximum amount of marching steps taken before a ray is considered to have missed.
+    #[must_use]
+    pub fn set_max_steps(self, max_steps: u32) -> Self {
+        Self { max_steps, ..self }
+    }
+
+    /// Sets how close to the surface a ray has to get for it to count as a hit.
+    #[must_use]
+    pub fn set_epsilon(self, epsilon: f64) -> Self {
+        Self { epsilon, ..self }
+    }
+
+    /// Sets the maximum distance a ray is marched, the march ends sooner when the ray interval is shorter.
+    #[must_use]
+    pub fn set_max_distance(self, max_distance: f64) -> Self {
+        Self { max_distance, ..self }
+    }
+
+    /// Scales every marching step. Values below 1.0 are needed for distance functions that overestimate
+    /// the distance, like most displacements and smooth combinations.
+    #[must_use]
+    pub fn set_step_scale(self, step_scale: f64) -> Self {
+        Self { step_scale, ..self }
+    }
+
+    /// Calculates the outward surface normal at a point as the normalized gradient of the distance function.
+    fn normal_at(&self, point: Vec3) -> Vec3 {
+        let h = self.epsilon;
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        let gradient = Vec3::new(
+            (self.distance)(point + dx) - (self.distance)(point - dx),
+            (self.distance)(point + dy) - (self.distance)(point - dy),
+            (self.distance)(point + dz) - (self.distance)(point - dz),
+        );
+        if gradient.near_zero() {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            gradient.normalized()
+        }
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.intersect(ray, ray_t), rand::random::<f64>)
+    }
+}
+
+impl SdfObject {
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let direction_length = ray.direction().length();
+        let direction = ray.direction() / direction_length;
+
+        let mut travelled = ray_t.min().max(0.0) * direction_length;
+        let end = (ray_t.max() * direction_length).min(self.max_distance);
+
+        // Rays that start on or inside the surface march towards the first sign change instead of the first zero.
+        let start_distance = (self.distance)(ray.origin() + travelled * direction);
+        let side = if start_distance.abs() < self.epsilon {
+            if dot(self.normal_at(ray.origin() + travelled * direction), direction) >= 0.0 {1.0} else {-1.0}
+        } else {
+            start_distance.signum()
+        };
+        // A ray leaving the surface first has to get away from it before it can hit it again.
+        let mut left_surface = start_distance.abs() >= self.epsilon;
+
+        for _ in 0..self.max_steps {
+            if travelled > end {
+                return None;
+            }
+
+            let point = ray.origin() + travelled * direction;
+            let distance = side * (self.distance)(point);
+
+            if distance < self.epsilon {
+                if left_surface {
+                    let t = travelled / direction_length;
+                    if !ray_t.surrounds(t) {
+                        return None;
+                    }
+                    let outward_normal = self.normal_at(point);
+                    let (front_face, normal) = calculate_face_normal(ray, outward_normal);
+                    return Some(HitRecord {
+                        t,
+                        point: ray.at(t),
+                        geometric_normal: normal,
+                        tangent: orthonormal_basis(outward_normal).0,
+                        // Distance fields have no parameterization, use textures that depend on the position instead.
+                        u: 0.0,
+                        v: 0.0,
+                        dpdu: Vec3::default(),
+                        dpdv: Vec3::default(),
+                        brdf: self.surface_shader.clone(),
+                        normal, front_face
+                    });
+                }
+            } else {
+                left_surface = true;
+            }
+
+            travelled += (distance.abs() * self.step_scale).max(self.epsilon);
+        }
+
+        None
+    }
+}
+
+const fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().abs(), v.y().abs(), v.z().abs())
+}
+
+const fn max(v: Vec3, value: f64) -> Vec3 {
+    Vec3::new(v.x().max(value), v.y().max(value), v.z().max(value))
+}
+
+const fn max_component(v: Vec3) -> f64 {
+    v.x().max(v.y()).max(v.z())
+}
+
+/// Sphere centered on the origin.
+#[must_use]
+pub fn make_sphere_sdf(radius: f64) -> Sdf {
+    Arc::new(move |p: Vec3| p.length() - radius)
+}
+
+/// Axis aligned box centered on the origin, `half_extents` is the distance from the center to each face.
+#[must_use]
+pub fn make_box_sdf(half_extents: Vec3) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let q = abs(p) - half_extents;
+        max(q, 0.0).length() + max_component(q).min(0.0)
+    })
+}
+
+/// Torus around the y axis centered on the origin.
+#[must_use]
+pub fn make_torus_sdf(major_radius: f64, minor_radius: f64) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let ring = p.x().hypot(p.z()) - major_radius;
+        ring.hypot(p.y()) - minor_radius
+    })
+}
+
+/// Capsule: all points within `radius` of the line segment from `a` to `b`.
+#[must_use]
+pub fn make_capsule_sdf(a: Vec3, b: Vec3, radius: f64) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let pa = p - a;
+        let ba = b - a;
+        let h = (dot(pa, ba) / ba.square_length()).clamp(0.0, 1.0);
+        (pa - h * ba).length() - radius
+    })
+}
+
+/// Capped cylinder around the y axis centered on the origin.
+#[must_use]
+pub fn make_cylinder_sdf(radius: f64, half_height: f64) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let dx = p.x().hypot(p.z()) - radius;
+        let dy = p.y().abs() - half_height;
+        dx.max(dy).min(0.0) + dx.max(0.0).hypot(dy.max(0.0))
+    })
+}
+
+/// Infinite plane with unit `normal` at `offset` from the origin along that normal.
+#[must_use]
+pub fn make_plane_sdf(normal: Vec3, offset: f64) -> Sdf {
+    let normal = normal.normalized();
+    Arc::new(move |p: Vec3| dot(p, normal) - offset)
+}
+
+/// Distance estimate of the mandelbulb fractal of the given power, the classic one uses a power of 8.
+/// The estimate is not exact, so it should be rendered with a step scale below 1.
+#[must_use]
+pub fn make_mandelbulb_sdf(power: f64, iterations: u32) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut r = 0.0;
+        for _ in 0..iterations {
+            r = z.length();
+            if r > 2.0 {
+                break;
+            }
+            let theta = (z.z() / r).acos() * power;
+            let phi = z.y().atan2(z.x()) * power;
+            dr = (power * r.powf(power - 1.0)).mul_add(dr, 1.0);
+            let zr = r.powf(power);
+            z = zr * Vec3::new(theta.sin() * phi.cos(), phi.sin() * theta.sin(), theta.cos()) + p;
+        }
+        0.5 * r.ln() * r / dr
+    })
+}
+
+/// Union of two shapes: all points inside either one.
+#[must_use]
+pub fn union(a: Sdf, b: Sdf) -> Sdf {
+    Arc::new(move |p: Vec3| a(p).min(b(p)))
+}
+
+/// Intersection of two shapes: all points inside both.
+#[must_use]
+pub fn intersection(a: Sdf, b: Sdf) -> Sdf {
+    Arc::new(move |p: Vec3| a(p).max(b(p)))
+}
+
+/// Subtracts shape `b` from shape `a`.
+#[must_use]
+pub fn subtraction(a: Sdf, b: Sdf) -> Sdf {
+    Arc::new(move |p: Vec3| a(p).max(-b(p)))
+}
+
+/// Union that blends the two shapes together over a distance of `k`.
+#[must_use]
+pub fn smooth_union(a: Sdf, b: Sdf, k: f64) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let (da, db) = (a(p), b(p));
+        let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+        (k * h).mul_add(-(1.0 - h), lerp(db, da, h))
+    })
+}
+
+/// Intersection that blends the two shapes together over a distance of `k`.
+#[must_use]
+pub fn smooth_intersection(a: Sdf, b: Sdf, k: f64) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let (da, db) = (a(p), b(p));
+        let h = (0.5 - 0.5 * (db - da) / k).clamp(0.0, 1.0);
+        (k * h).mul_add(1.0 - h, lerp(db, da, h))
+    })
+}
+
+/// Subtraction of `b` from `a` that blends over a distance of `k`.
+#[must_use]
+pub fn smooth_subtraction(a: Sdf, b: Sdf, k: f64) -> Sdf {
+    Arc::new(move |p: Vec3| {
+        let (da, db) = (a(p), b(p));
+        let h = (0.5 - 0.5 * (da + db) / k).clamp(0.0, 1.0);
+        (k * h).mul_add(1.0 - h, lerp(da, -db, h))
+    })
+}
+
+/// Moves a shape by `offset`.
+#[must_use]
+pub fn translate(sdf: Sdf, offset: Vec3) -> Sdf {
+    Arc::new(move |p: Vec3| sdf(p - offset))
+}
+
+/// Uniformly scales a shape around the origin.
+#[must_use]
+pub fn scale(sdf: Sdf, factor: f64) -> Sdf {
+    Arc::new(move |p: Vec3| sdf(p / factor) * factor)
+}
+
+/// Rotates a shape around `axis` by `angle` radians.
+#[must_use]
+pub fn rotate(sdf: Sdf, axis: Vec3, angle: f64) -> Sdf {
+    let axis = axis.normalized();
+    // Rotating the sample point the opposite way rotates the shape.
+    let (sin, cos) = (-angle).sin_cos();
+    Arc::new(move |p: Vec3| {
+        let rotated = p * cos + cross(axis, p) * sin + axis * dot(axis, p) * (1.0 - cos);
+        sdf(rotated)
+    })
+}
+
+/// Rounds off a shape by growing it by `radius`.
+#[must_use]
+pub fn round(sdf: Sdf, radius: f64) -> Sdf {
+    Arc::new(move |p: Vec3| sdf(p) - radius)
+}
+
+/// Hollows out a shape into a shell of the given thickness.
+#[must_use]
+pub fn onion(sdf: Sdf, thickness: f64) -> Sdf {
+    Arc::new(move |p: Vec3| sdf(p).abs() - thickness)
+}
+
+/// Adds a displacement to the distance of a shape, for example a noise or sine pattern.
+/// Displacements make the distance inexact, so a step scale below 1 may be needed when rendering.
+#[must_use]
+pub fn displace<F>(sdf: Sdf, displacement: F) -> Sdf
+where
+    F: Fn(Vec3) -> f64 + Send + Sync + 'static,
+{
+    Arc::new(move |p: Vec3| sdf(p) + displacement(p))
+}
+
+/// Repeats a shape infinitely on a grid with the given spacing per axis.
+#[must_use]
+pub fn repeat(sdf: Sdf, period: Vec3) -> Sdf {
+    let wrap = |x: f64, period: f64| if period > 0.0 { period.mul_add(-(x / period).round(), x) } else { x };
+    Arc::new(move |p: Vec3| {
+        sdf(Vec3::new(
+            wrap(p.x(), period.x()),
+            wrap(p.y(), period.y()),
+            wrap(p.z(), period.z()),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{brdfs, colors::Color};
+
+    #[test]
+    fn primitive_distances() {
+        let sphere = make_sphere_sdf(1.0);
+        assert!((sphere(Vec3::new(3.0, 0.0, 0.0)) - 2.0).abs() < 1e-12);
+        assert!((sphere(Vec3::new(0.0, 0.0, 0.0)) + 1.0).abs() < 1e-12);
+
+        let cube = make_box_sdf(Vec3::new(1.0, 1.0, 1.0));
+        assert!((cube(Vec3::new(2.0, 0.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert!((cube(Vec3::new(2.0, 2.0, 1.0)) - 2.0_f64.sqrt()).abs() < 1e-12);
+
+        let torus = make_torus_sdf(2.0, 0.5);
+        assert!(torus(Vec3::new(2.0, 0.0, 0.0)) < 0.0);
+        assert!(torus(Vec3::new(0.0, 0.0, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn combinators() {
+        let a = make_sphere_sdf(1.0);
+        let b = translate(make_sphere_sdf(1.0), Vec3::new(1.5, 0.0, 0.0));
+        let point = Vec3::new(2.0, 0.0, 0.0);
+
+        assert!(union(a.clone(), b.clone())(point) < 0.0);
+        assert!(intersection(a.clone(), b.clone())(point) > 0.0);
+        assert!(subtraction(b.clone(), a.clone())(point) < 0.0);
+        assert!(smooth_union(a.clone(), b.clone(), 0.5)(point) <= union(a, b)(point));
+
+        let scaled = scale(make_sphere_sdf(1.0), 2.0);
+        assert!((scaled(Vec3::new(3.0, 0.0, 0.0)) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sphere_tracing_hits_surface() {
+        let object = SdfObject::new(make_sphere_sdf(1.0), brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit = object.hit(ray, Interval::new(0.00001, f64::INFINITY)).expect("Ray is aimed at the sphere.");
+
+        assert!((hit.t - 2.0).abs() < 1e-3);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-3);
+
+        // The surface is 4 units along the ray, past the maximum distance.
+        let near_sighted = SdfObject::new(make_sphere_sdf(1.0), brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)))
+            .set_max_distance(3.0);
+        assert!(near_sighted.hit(ray, Interval::new(0.00001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn sphere_tracing_from_inside_and_misses() {
+        let object = SdfObject::new(make_sphere_sdf(1.0), brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = object.hit(inside, Interval::new(0.00001, f64::INFINITY)).expect("Ray starts inside the sphere.");
+        assert!((hit.t - 1.0).abs() < 1e-3);
+        assert!(!hit.front_face);
+
+        let miss = Ray::new(Vec3::new(0.0, 2.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(object.hit(miss, Interval::new(0.00001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn cutout_surfaces_are_skipped_on_both_sides() {
+        let material = brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let hidden = SdfObject::new(make_sphere_sdf(1.0), brdfs::make_cutout_brdf(material.clone(), 0.0));
+        assert!(hidden.hit(ray, Interval::new(0.00001, f64::INFINITY)).is_none());
+
+        let solid = SdfObject::new(make_sphere_sdf(1.0), brdfs::make_cutout_brdf(material, 1.0));
+        let hit = solid.hit(ray, Interval::new(0.00001, f64::INFINITY)).expect("Ray is aimed at the sphere.");
+        assert!((hit.t - 4.0).abs() < 1e-3);
+    }
+}
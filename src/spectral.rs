@@ -0,0 +1,273 @@
+//! Conversions from light spectra to colors, for effects that depend on the wavelength of light.
+//!
+//! Wavelengths are in nanometers and colors are in linear sRGB.
+
+use std::sync::LazyLock;
+use crate::vec_math::{Vec3, lerp};
+
+/// Shortest wavelength of visible light that is taken into account.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+/// Longest wavelength of visible light that is taken into account.
+pub const MAX_WAVELENGTH: f64 = 780.0;
+
+/// Wavelength of the Fraunhofer d line, at which indices of refraction are usually quoted.
+pub const D_LINE_WAVELENGTH: f64 = 587.6;
+
+/// Number of wavelengths a spectrum is sampled at when converting it to a color.
+const SPECTRUM_SAMPLES: u32 = 40;
+
+/// Average color of the wavelengths in the visible range, which is what a constant spectrum looks like.
+static WHITE: LazyLock<Vec3> = LazyLock::new(|| {
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / f64::from(SPECTRUM_SAMPLES);
+    let mut white = Vec3::default();
+    for i in 0..SPECTRUM_SAMPLES {
+        white += xyz_to_linear_srgb(cie_xyz((f64::from(i) + 0.5).mul_add(step, MIN_WAVELENGTH)));
+    }
+    white / f64::from(SPECTRUM_SAMPLES)
+});
+
+/// The CIE 1931 color matching functions at `wavelength`, using the multi lobe fit of Wyman, Sloan and Shirley.
+/// # Example
+/// ```
+/// use renders::spectral;
+/// let green = spectral::cie_xyz(555.0);
+/// assert!(green.y() > green.x() && green.y() > green.z());
+/// ```
+#[must_use]
+pub fn cie_xyz(wavelength: f64) -> Vec3 {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let t = (wavelength - mean) / if wavelength < mean {below} else {above};
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        0.065f64.mul_add(-lobe(501.1, 20.4, 26.2), 1.056f64.mul_add(lobe(599.8, 37.9, 31.0), 0.362 * lobe(442.0, 16.0, 26.7))),
+        0.821f64.mul_add(lobe(568.8, 46.9, 40.5), 0.286 * lobe(530.9, 16.3, 31.1)),
+        1.217f64.mul_add(lobe(437.0, 11.8, 36.0), 0.681 * lobe(459.0, 26.0, 13.8)),
+    )
+}
+
+/// Converts CIE XYZ to linear sRGB, the result may lie outside of [0, 1] for colors sRGB can not show.
+#[must_use]
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        (-0.4986f64).mul_add(xyz.z(), 3.2406f64.mul_add(xyz.x(), -1.5372 * xyz.y())),
+        0.0415f64.mul_add(xyz.z(), (-0.9689f64).mul_add(xyz.x(), 1.8758 * xyz.y())),
+        1.0570f64.mul_add(xyz.z(), 0.0557f64.mul_add(xyz.x(), -0.2040 * xyz.y())),
+    )
+}
+
+/// Converts a spectrum of reflectances in [0, 1] to the color of a white surface with that reflectance.
+///
+/// The visible range is sampled at evenly spaced wavelengths, and the result is scaled so a constant reflectance
+/// gives a gray of the same value.
+/// # Example
+/// ```
+/// use renders::spectral;
+/// let gray = spectral::reflectance_to_rgb(|_| 0.5);
+/// assert!((gray.x() - 0.5).abs() < 1e-9 && (gray.z() - 0.5).abs() < 1e-9);
+/// let red = spectral::reflectance_to_rgb(|wavelength| if wavelength > 600.0 {1.0} else {0.0});
+/// assert!(red.x() > red.y() && red.x() > red.z());
+/// ```
+#[must_use]
+pub fn reflectance_to_rgb<F: Fn(f64) -> f64>(reflectance: F) -> Vec3 {
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / f64::from(SPECTRUM_SAMPLES);
+    let mut color = Vec3::default();
+    for i in 0..SPECTRUM_SAMPLES {
+        let wavelength = (f64::from(i) + 0.5).mul_add(step, MIN_WAVELENGTH);
+        color += reflectance(wavelength) * xyz_to_linear_srgb(cie_xyz(wavelength));
+    }
+    relative_to_white(color / f64::from(SPECTRUM_SAMPLES))
+}
+
+/// The color of light of a single wavelength, scaled so the average over the visible range is white.
+///
+/// Averaging `radiance * wavelength_to_rgb(wavelength)` over uniformly chosen wavelengths converts a spectrum of
+/// radiance to a color, the components may be negative or above 1 for a single wavelength.
+/// # Example
+/// ```
+/// use renders::spectral;
+/// let red = spectral::wavelength_to_rgb(650.0);
+/// assert!(red.x() > 1.0 && red.x() > red.y() && red.x() > red.z());
+/// ```
+#[must_use]
+pub fn wavelength_to_rgb(wavelength: f64) -> Vec3 {
+    relative_to_white(xyz_to_linear_srgb(cie_xyz(wavelength)))
+}
+
+/// Picks a wavelength uniformly from the visible range.
+#[must_use]
+pub fn sample_wavelength() -> f64 {
+    rand::random_range(MIN_WAVELENGTH..MAX_WAVELENGTH)
+}
+
+/// The value at `wavelength` of a smooth spectrum with roughly the color `rgb`, with the method of Smits.
+///
+/// The spectrum is the gray of the smallest component, plus the spectrum of cyan, magenta or yellow for the second
+/// one and of red, green or blue for the rest. The spectrum is linear in the color: white gives a constant spectrum,
+/// colors in [0, 1] give spectra within 2% of [0, 1] and brighter colors, such as light sources and importance sampling
+/// weights, give spectra that are as much brighter. Only negative values are clipped.
+/// # Example
+/// ```
+/// use renders::{spectral, vec_math::Vec3};
+/// let white = Vec3::new(1.0, 1.0, 1.0);
+/// assert!((spectral::rgb_to_spectrum(white, 450.0) - 1.0).abs() < 1e-12);
+/// assert!((spectral::rgb_to_spectrum(white, 650.0) - 1.0).abs() < 1e-12);
+/// let red = Vec3::new(1.0, 0.0, 0.0);
+/// assert!(spectral::rgb_to_spectrum(red, 650.0) > spectral::rgb_to_spectrum(red, 450.0));
+/// assert!((spectral::rgb_to_spectrum(4.0 * white, 550.0) - 4.0).abs() < 1e-12);
+/// ```
+#[must_use]
+pub fn rgb_to_spectrum(rgb: Vec3, wavelength: f64) -> f64 {
+    let at = |spectrum: &[f64; 10]| smits_basis(spectrum, wavelength);
+    let (red, green, blue) = (rgb.x(), rgb.y(), rgb.z());
+    let value = if red <= green && red <= blue {
+        let rest = if green <= blue {
+            (blue - green) * at(&SMITS_BLUE)
+        } else {
+            (green - blue) * at(&SMITS_GREEN)
+        };
+        (green.min(blue) - red).mul_add(at(&SMITS_CYAN), red + rest)
+    } else if green <= blue {
+        let rest = if red <= blue {
+            (blue - red) * at(&SMITS_BLUE)
+        } else {
+            (red - blue) * at(&SMITS_RED)
+        };
+        (red.min(blue) - green).mul_add(at(&SMITS_MAGENTA), green + rest)
+    } else {
+        let rest = if red <= green {
+            (green - red) * at(&SMITS_GREEN)
+        } else {
+            (red - green) * at(&SMITS_RED)
+        };
+        (red.min(green) - blue).mul_add(at(&SMITS_YELLOW), blue + rest)
+    };
+    value.max(0.0)
+}
+
+/// Index of refraction that changes with the wavelength, which splits white light into its colors.
+/// # Example
+/// ```
+/// use renders::spectral::{self, Dispersion};
+/// let flint = Dispersion::from_abbe(1.62, 36.0);
+/// assert!((flint.ior(spectral::D_LINE_WAVELENGTH) - 1.62).abs() < 1e-9);
+/// assert!(Dispersion::DIAMOND.ior(450.0) > Dispersion::DIAMOND.ior(650.0));
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`, with the wavelength λ in micrometers.
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with the wavelength λ in micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Borosilicate crown glass, the common optical glass of lenses and prisms.
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+    /// Diamond, whose high index of refraction and strong dispersion give it its fire.
+    pub const DIAMOND: Self = Self::Sellmeier { b: [0.3306, 4.3356, 0.0], c: [0.030_625, 0.011_236, 0.0] };
+
+    /// A Cauchy dispersion with index of refraction `ior` at the d line and the Abbe number `abbe`, lower Abbe
+    /// numbers spread the colors further.
+    #[must_use]
+    pub fn from_abbe(ior: f64, abbe: f64) -> Self {
+        let inverse_square = |wavelength: f64| 1.0 / (wavelength * 1e-3).powi(2);
+        let b = (ior - 1.0) / (abbe * (inverse_square(486.1) - inverse_square(656.3)));
+        Self::Cauchy { a: b.mul_add(-inverse_square(D_LINE_WAVELENGTH), ior), b }
+    }
+
+    /// The index of refraction at `wavelength` in nanometers.
+    #[must_use]
+    pub fn ior(self, wavelength: f64) -> f64 {
+        let square = (wavelength * 1e-3).powi(2);
+        match self {
+            Self::Cauchy { a, b } => a + b / square,
+            Self::Sellmeier { b, c } => {
+                (1.0 + b.iter().zip(c).map(|(b, c)| b * square / (square - c)).sum::<f64>()).sqrt()
+            }
+        }
+    }
+}
+
+// Spectra of the basis colors from "An RGB to Spectrum Conversion for Reflectances" by Smits, in 10 bins of equal
+// width from 380 to 720 nm. The white spectrum of the paper is within 1e-3 of 1 and is taken as constant.
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Interpolates linearly between the bin centers of a spectrum of Smits, the ends are extended as constants.
+fn smits_basis(spectrum: &[f64; 10], wavelength: f64) -> f64 {
+    let position = ((wavelength - 380.0) / 34.0 - 0.5).clamp(0.0, 9.0);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let index = (position as usize).min(8);
+    #[allow(clippy::cast_precision_loss)]
+    lerp(spectrum[index], spectrum[index + 1], position - index as f64)
+}
+
+fn relative_to_white(color: Vec3) -> Vec3 {
+    Vec3::new(color.x() / WHITE.x(), color.y() / WHITE.y(), color.z() / WHITE.z())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glasses_match_their_catalog_values() {
+        assert!((Dispersion::BK7.ior(D_LINE_WAVELENGTH) - 1.5168).abs() < 1e-4);
+        assert!((Dispersion::DIAMOND.ior(D_LINE_WAVELENGTH) - 2.4175).abs() < 1e-3);
+
+        let flint = Dispersion::from_abbe(1.62, 36.0);
+        let abbe = (flint.ior(D_LINE_WAVELENGTH) - 1.0) / (flint.ior(486.1) - flint.ior(656.3));
+        assert!((abbe - 36.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn single_wavelengths_average_to_white() {
+        let samples = 4000;
+        let mut white = Vec3::default();
+        for i in 0..samples {
+            let wavelength = MIN_WAVELENGTH + (MAX_WAVELENGTH - MIN_WAVELENGTH) * (f64::from(i) + 0.5) / f64::from(samples);
+            white += wavelength_to_rgb(wavelength) / f64::from(samples);
+        }
+        assert!((white - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-2, "{white:?}");
+    }
+
+    #[test]
+    fn bright_colors_give_bright_spectra() {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let orange = Vec3::new(1.0, 0.5, 0.1);
+        for step in 0..=40 {
+            let wavelength = (MAX_WAVELENGTH - MIN_WAVELENGTH).mul_add(f64::from(step) / 40.0, MIN_WAVELENGTH);
+            assert!((rgb_to_spectrum(4.0 * white, wavelength) - 4.0).abs() < 1e-12, "{wavelength}");
+            let (bright, dim) = (rgb_to_spectrum(8.0 * orange, wavelength), rgb_to_spectrum(orange, wavelength));
+            assert!(8.0f64.mul_add(-dim, bright).abs() < 1e-12, "{wavelength}");
+        }
+    }
+
+    #[test]
+    fn upsampled_colors_keep_their_hue() {
+        let colors = [
+            Vec3::new(0.8, 0.1, 0.1),
+            Vec3::new(0.1, 0.8, 0.1),
+            Vec3::new(0.1, 0.1, 0.8),
+            Vec3::new(0.3, 0.3, 0.3),
+            Vec3::new(0.9, 0.8, 0.1),
+            Vec3::new(0.2, 0.6, 0.9),
+            Vec3::new(0.7, 0.2, 0.6),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        for rgb in colors {
+            let round_trip = reflectance_to_rgb(|wavelength| rgb_to_spectrum(rgb, wavelength));
+            assert!((round_trip - rgb).length() < 0.05, "{rgb:?} became {round_trip:?}");
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use std::sync::Arc;
+use crate::{colors::Color, image::Image, vec_math::Vec3};
+
+/// A color that varies over a surface, looked up by the texture coordinates and position of a hit.
+pub trait Texture: Send + Sync {
+    /// Returns the color at texture coordinates `u`, `v` and position `point`.
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color;
+
+    /// Returns a single number at texture coordinates `u`, `v` and position `point`,
+    /// for textures that control parameters such as roughness. Defaults to the average of the color channels.
+    fn scalar_value(&self, u: f64, v: f64, point: Vec3) -> f64 {
+        let color: Vec3 = self.value(u, v, point).into();
+        (color.x() + color.y() + color.z()) / 3.0
+    }
+
+    /// Returns the color averaged over the footprint of a pixel around texture coordinates `u`, `v` and position `point`,
+    /// so detail smaller than a pixel does not alias. Defaults to the unfiltered color.
+    fn filtered_value(&self, u: f64, v: f64, point: Vec3, _footprint: Footprint) -> Color {
+        self.value(u, v, point)
+    }
+}
+
+/// How much the texture coordinates change from one pixel on the screen to the next, found with ray differentials.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Footprint {
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+}
+
+impl Footprint {
+    /// Returns the footprint for texture coordinates multiplied by `u` and `v`.
+    #[must_use]
+    pub const fn scaled(self, u: f64, v: f64) -> Self {
+        Self { dudx: self.dudx * u, dvdx: self.dvdx * v, dudy: self.dudy * u, dvdy: self.dvdy * v }
+    }
+
+    /// Returns the length of the longest axis of the footprint.
+    #[must_use]
+    pub fn width(self) -> f64 {
+        self.dudx.hypot(self.dvdx).max(self.dudy.hypot(self.dvdy))
+    }
+}
+
+/// A solid color is a texture that is the same everywhere.
+impl Texture for Color {
+    fn value(&self, _u: f64, _v: f64, _point: Vec3) -> Color {
+        *self
+    }
+}
+
+/// A number is a texture that is the same everywhere, its color is gray.
+impl Texture for f64 {
+    fn value(&self, _u: f64, _v: f64, _point: Vec3) -> Color {
+        Color::new(*self, *self, *self)
+    }
+
+    fn scalar_value(&self, _u: f64, _v: f64, _point: Vec3) -> f64 {
+        *self
+    }
+}
+
+/// Allows sharing a texture between materials.
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        (**self).value(u, v, point)
+    }
+
+    fn scalar_value(&self, u: f64, v: f64, point: Vec3) -> f64 {
+        (**self).scalar_value(u, v, point)
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, point: Vec3, footprint: Footprint) -> Color {
+        (**self).filtered_value(u, v, point, footprint)
+    }
+}
+
+/// A checkerboard in texture space with `frequency` squares along each texture coordinate.
+/// # Example
+/// ```
+/// use renders::{colors::Color, textures::UvChecker};
+/// let checker = UvChecker::new(Color::new(0.9, 0.9, 0.9), Color::new(0.1, 0.1, 0.1), 8.0);
+/// ```
+pub struct UvChecker<A: Texture, B: Texture> {
+    even: A,
+    odd: B,
+    frequency: f64,
+}
+
+impl<A: Texture, B: Texture> UvChecker<A, B> {
+    #[must_use]
+    pub const fn new(even: A, odd: B, frequency: f64) -> Self {
+        Self { even, odd, frequency }
+    }
+}
+
+impl<A: Texture, B: Texture> Texture for UvChecker<A, B> {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.filtered_value(u, v, point, Footprint::default())
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, point: Vec3, footprint: Footprint) -> Color {
+        let sum = (u * self.frequency).floor() + (v * self.frequency).floor();
+        if sum.rem_euclid(2.0) < 1.0 {
+            self.even.filtered_value(u, v, point, footprint)
+        } else {
+            self.odd.filtered_value(u, v, point, footprint)
+        }
+    }
+}
+
+/// A checkerboard of cubes with sides of length `scale` filling space, so it does not depend on texture coordinates.
+/// # Example
+/// ```
+/// use renders::{colors::Color, textures::SolidChecker};
+/// let checker = SolidChecker::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9), 0.32);
+/// ```
+pub struct SolidChecker<A: Texture, B: Texture> {
+    even: A,
+    odd: B,
+    scale: f64,
+}
+
+impl<A: Texture, B: Texture> SolidChecker<A, B> {
+    /// # Panics
+    /// Panics if `scale` is not positive.
+    #[must_use]
+    pub fn new(even: A, odd: B, scale: f64) -> Self {
+        assert!(scale > 0.0);
+        Self { even, odd, scale }
+    }
+}
+
+impl<A: Texture, B: Texture> Texture for SolidChecker<A, B> {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.filtered_value(u, v, point, Footprint::default())
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, point: Vec3, footprint: Footprint) -> Color {
+        let cell = point / self.scale;
+        let sum = cell.x().floor() + cell.y().floor() + cell.z().floor();
+        if sum.rem_euclid(2.0) < 1.0 {
+            self.even.filtered_value(u, v, point, footprint)
+        } else {
+            self.odd.filtered_value(u, v, point, footprint)
+        }
+    }
+}
+
+/// How texture coordinates outside of [0, 1] are mapped onto an image.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum WrapMode {
+    /// Tiles the image.
+    #[default]
+    Repeat,
+    /// Tiles the image, flipping every other tile so the edges line up.
+    Mirror,
+    /// Extends the pixels on the edges of the image.
+    Clamp,
+}
+
+/// How pixels are combined when looking up a color between pixel centers.
+///
+/// Only the mipmapped filters look at the footprint of a pixel, the others alias when the texture is seen from far away.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Filter {
+    /// Uses the closest pixel, giving a blocky look.
+    Nearest,
+    /// Interpolates between the four closest pixels.
+    Bilinear,
+    /// Interpolates bilinearly between the two mipmap levels matching the longest axis of the footprint,
+    /// which blurs textures seen at grazing angles.
+    #[default]
+    Trilinear,
+    /// Averages the pixels inside the elliptical footprint with a gaussian (EWA filtering),
+    /// keeping detail along the short axis of the footprint at grazing angles.
+    Anisotropic,
+}
+
+/// Longest allowed ratio between the axes of the footprint for anisotropic filtering, limiting the pixels visited.
+const MAX_ANISOTROPY: f64 = 8.0;
+
+/// A texture that maps an image onto the texture coordinates, with u going right and v going up.
+///
+/// A mipmap of the image is built up front, halving the resolution at each level down to a single pixel,
+/// so far away textures can be looked up at a matching resolution.
+/// # Example
+/// ```
+/// use renders::{pixelbuffer::PixelBuffer, textures::{Filter, ImageTexture, WrapMode}};
+/// // Rendered pixel buffers and loaded images can both be used.
+/// let texture = ImageTexture::new(PixelBuffer::new(64, 64))
+///     .set_wrap_mode(WrapMode::Mirror)
+///     .set_filter(Filter::Nearest);
+/// ```
+pub struct ImageTexture {
+    levels: Vec<Image>,
+    wrap_mode: WrapMode,
+    filter: Filter,
+}
+
+impl ImageTexture {
+    /// Creates a repeating, trilinearly filtered texture from an image.
+    /// # Panics
+    /// Panics if the image has no pixels.
+    #[must_use]
+    pub fn new<I: Into<Image>>(image: I) -> Self {
+        let image = image.into();
+        assert!(image.width() > 0 && image.height() > 0);
+
+        let mut levels = vec![image];
+        while let Some(level) = levels.last().and_then(downsample) {
+            levels.push(level);
+        }
+        Self { levels, wrap_mode: WrapMode::default(), filter: Filter::default() }
+    }
+
+    #[must_use]
+    pub fn set_wrap_mode(self, wrap_mode: WrapMode) -> Self {
+        Self { wrap_mode, ..self }
+    }
+
+    #[must_use]
+    pub fn set_filter(self, filter: Filter) -> Self {
+        Self { filter, ..self }
+    }
+
+    /// Returns a texture of the alpha channel in gray, with the same wrap mode and filter, for use as an opacity mask.
+    /// # Example
+    /// ```
+    /// use renders::{brdfs, colors::Color, image::Image, textures::ImageTexture};
+    /// let mut leaf = Image::new(2, 1);
+    /// leaf.set_alpha(1, 0, 0.0);
+    /// let mask = ImageTexture::new(leaf).alpha_channel();
+    /// let material = brdfs::make_cutout_brdf(brdfs::make_lambertian_diffuse_brdf(Color::new(0.2, 0.5, 0.1)), mask);
+    /// ```
+    #[must_use]
+    pub fn alpha_channel(&self) -> Self {
+        let levels = self.levels.iter().map(|level| {
+            let mut alpha = Image::new(level.width(), level.height());
+            for y in 0..level.height() {
+                for x in 0..level.width() {
+                    let value = level.get_alpha(x, y);
+                    alpha.set_pixel(x, y, Vec3::new(value, value, value));
+                }
+            }
+            alpha
+        }).collect();
+        Self { levels, wrap_mode: self.wrap_mode, filter: self.filter }
+    }
+
+    /// Returns the pixel of a mipmap level at integer coordinates that may lie outside of the image, applying the wrap mode.
+    fn texel(&self, level: usize, x: i64, y: i64) -> Vec3 {
+        let image = &self.levels[level];
+        let x = wrap(x, image.width(), self.wrap_mode);
+        let y = wrap(y, image.height(), self.wrap_mode);
+        image.get_pixel(x, y)
+    }
+
+    /// Returns the continuous pixel coordinates of texture coordinates on a mipmap level.
+    /// Images are stored top to bottom, while v goes up.
+    #[allow(clippy::cast_precision_loss)]
+    fn to_pixels(&self, level: usize, u: f64, v: f64) -> (f64, f64) {
+        let image = &self.levels[level];
+        (u * image.width() as f64, (1.0 - v) * image.height() as f64)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn nearest(&self, u: f64, v: f64) -> Vec3 {
+        let (x, y) = self.to_pixels(0, u, v);
+        self.texel(0, x.floor() as i64, y.floor() as i64)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> Vec3 {
+        let (x, y) = self.to_pixels(level, u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = (1.0 - tx) * self.texel(level, x0, y0) + tx * self.texel(level, x0 + 1, y0);
+        let bottom = (1.0 - tx) * self.texel(level, x0, y0 + 1) + tx * self.texel(level, x0 + 1, y0 + 1);
+        (1.0 - ty) * top + ty * bottom
+    }
+
+    /// Returns the continuous mipmap level at which a footprint of `width` in texture coordinates covers about one pixel.
+    #[allow(clippy::cast_precision_loss)]
+    fn level_for(&self, width: f64) -> f64 {
+        let resolution = self.levels[0].width().max(self.levels[0].height()) as f64;
+        (width * resolution).max(1e-12).log2().clamp(0.0, (self.levels.len() - 1) as f64)
+    }
+
+    /// Blends a lookup between the two mipmap levels around a continuous level.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    fn between_levels<F: Fn(usize) -> Vec3>(&self, level: f64, lookup: F) -> Vec3 {
+        let lower = level.floor() as usize;
+        let t = level - level.floor();
+        if lower + 1 >= self.levels.len() || t == 0.0 {
+            lookup(lower)
+        } else {
+            (1.0 - t) * lookup(lower) + t * lookup(lower + 1)
+        }
+    }
+
+    fn anisotropic(&self, u: f64, v: f64, footprint: Footprint) -> Vec3 {
+        // The axes of the footprint, with v flipped to follow the rows of the image.
+        let mut major = (footprint.dudx, -footprint.dvdx);
+        let mut minor = (footprint.dudy, -footprint.dvdy);
+        if minor.0.hypot(minor.1) > major.0.hypot(major.1) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let major_length = major.0.hypot(major.1);
+        let mut minor_length = minor.0.hypot(minor.1);
+        if minor_length == 0.0 {
+            return self.bilinear(0, u, v);
+        }
+
+        // Very thin ellipses would cover too many pixels, so widen them and pick a blurrier level instead.
+        if minor_length * MAX_ANISOTROPY < major_length {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            minor = (minor.0 * scale, minor.1 * scale);
+            minor_length *= scale;
+        }
+
+        self.between_levels(self.level_for(minor_length), |level| self.ewa(level, u, v, major, minor))
+    }
+
+    /// Averages the pixels of a mipmap level inside the ellipse with axes `major` and `minor` around `u`, `v`,
+    /// weighted by a gaussian that falls off towards the edge of the ellipse.
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::many_single_char_names)]
+    fn ewa(&self, level: usize, u: f64, v: f64, major: (f64, f64), minor: (f64, f64)) -> Vec3 {
+        let image = &self.levels[level];
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let (x, y) = self.to_pixels(level, u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let major = (major.0 * width, major.1 * height);
+        let minor = (minor.0 * width, minor.1 * height);
+
+        // Implicit ellipse a x^2 + b x y + c y^2 < 1, grown by a pixel so it always covers some pixel centers.
+        let a = major.1.mul_add(major.1, minor.1 * minor.1) + 1.0;
+        let b = -2.0 * major.0.mul_add(major.1, minor.0 * minor.1);
+        let c = major.0.mul_add(major.0, minor.0 * minor.0) + 1.0;
+        let scale = 1.0 / a.mul_add(c, -0.25 * b * b);
+        let (a, b, c) = (a * scale, b * scale, c * scale);
+
+        let determinant = (4.0 * a).mul_add(c, -(b * b));
+        let half_width = 2.0 * (determinant * c).sqrt() / determinant;
+        let half_height = 2.0 * (a * determinant).sqrt() / determinant;
+
+        let mut sum = Vec3::default();
+        let mut total_weight = 0.0;
+        for pixel_y in (y - half_height).ceil() as i64..=(y + half_height).floor() as i64 {
+            for pixel_x in (x - half_width).ceil() as i64..=(x + half_width).floor() as i64 {
+                let (dx, dy) = (pixel_x as f64 - x, pixel_y as f64 - y);
+                let radius_squared = a.mul_add(dx * dx, b.mul_add(dx * dy, c * dy * dy));
+                if radius_squared < 1.0 {
+                    let weight = (-2.0 * radius_squared).exp() - (-2.0f64).exp();
+                    sum += weight * self.texel(level, pixel_x, pixel_y);
+                    total_weight += weight;
+                }
+            }
+        }
+
+        if total_weight > 0.0 {sum / total_weight} else {self.bilinear(level, u, v)}
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.filtered_value(u, v, point, Footprint::default())
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, _point: Vec3, footprint: Footprint) -> Color {
+        match self.filter {
+            Filter::Nearest => self.nearest(u, v),
+            Filter::Bilinear => self.bilinear(0, u, v),
+            Filter::Trilinear => self.between_levels(self.level_for(footprint.width()), |level| self.bilinear(level, u, v)),
+            Filter::Anisotropic => self.anisotropic(u, v, footprint),
+        }.into()
+    }
+}
+
+/// Halves the resolution of an image by averaging blocks of two by two pixels, or returns `None` for a single pixel.
+fn downsample(image: &Image) -> Option<Image> {
+    if image.width() == 1 && image.height() == 1 {
+        return None;
+    }
+    let mut smaller = Image::new(image.width().div_ceil(2), image.height().div_ceil(2));
+    for y in 0..smaller.height() {
+        for x in 0..smaller.width() {
+            // Odd sizes repeat the last row or column.
+            let (left, right) = (2 * x, (2 * x + 1).min(image.width() - 1));
+            let (top, bottom) = (2 * y, (2 * y + 1).min(image.height() - 1));
+            let color = image.get_pixel(left, top) + image.get_pixel(right, top) + image.get_pixel(left, bottom) + image.get_pixel(right, bottom);
+            let alpha = image.get_alpha(left, top) + image.get_alpha(right, top) + image.get_alpha(left, bottom) + image.get_alpha(right, bottom);
+            smaller.set_pixel(x, y, 0.25 * color);
+            smaller.set_alpha(x, y, 0.25 * alpha);
+        }
+    }
+    Some(smaller)
+}
+
+/// Maps a pixel coordinate onto the range [0, size) according to the wrap mode.
+#[allow(clippy::cast_possible_wrap)]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_possible_truncation)]
+const fn wrap(coordinate: i64, size: usize, wrap_mode: WrapMode) -> usize {
+    let size = size as i64;
+    let wrapped = match wrap_mode {
+        WrapMode::Repeat => coordinate.rem_euclid(size),
+        WrapMode::Mirror => {
+            let period = coordinate.rem_euclid(2 * size);
+            if period < size {period} else {2 * size - 1 - period}
+        }
+        WrapMode::Clamp => if coordinate < 0 {0} else if coordinate >= size {size - 1} else {coordinate},
+    };
+    wrapped as usize
+}
+
+/// Scales and offsets the texture coordinates and position before looking up another texture.
+/// # Example
+/// ```
+/// use renders::{colors::Color, textures::{Transformed, UvChecker}, vec_math::Vec3};
+/// let checker = UvChecker::new(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 2.0);
+/// let tiled = Transformed::new(checker).set_scale(4.0, 2.0).set_offset(0.5, 0.0);
+/// ```
+pub struct Transformed<T: Texture> {
+    texture: T,
+    scale: (f64, f64),
+    offset: (f64, f64),
+    point_scale: f64,
+    point_offset: Vec3,
+}
+
+impl<T: Texture> Transformed<T> {
+    #[must_use]
+    pub const fn new(texture: T) -> Self {
+        Self { texture, scale: (1.0, 1.0), offset: (0.0, 0.0), point_scale: 1.0, point_offset: Vec3::new(0.0, 0.0, 0.0) }
+    }
+
+    /// Multiplies the texture coordinates, so the texture repeats `u` times along u and `v` times along v.
+    #[must_use]
+    pub fn set_scale(self, u: f64, v: f64) -> Self {
+        Self { scale: (u, v), ..self }
+    }
+
+    /// Moves the texture coordinates, applied after scaling.
+    #[must_use]
+    pub fn set_offset(self, u: f64, v: f64) -> Self {
+        Self { offset: (u, v), ..self }
+    }
+
+    /// Multiplies the position, for textures that depend on the position of a hit.
+    #[must_use]
+    pub fn set_point_scale(self, point_scale: f64) -> Self {
+        Self { point_scale, ..self }
+    }
+
+    /// Moves the position, applied after scaling.
+    #[must_use]
+    pub fn set_point_offset(self, point_offset: Vec3) -> Self {
+        Self { point_offset, ..self }
+    }
+}
+
+impl<T: Texture> Texture for Transformed<T> {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.filtered_value(u, v, point, Footprint::default())
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, point: Vec3, footprint: Footprint) -> Color {
+        self.texture.filtered_value(
+            u.mul_add(self.scale.0, self.offset.0),
+            v.mul_add(self.scale.1, self.offset.1),
+            point * self.point_scale + self.point_offset,
+            footprint.scaled(self.scale.0, self.scale.1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixelbuffer::PixelBuffer;
+
+    fn make_gradient_image() -> PixelBuffer {
+        let mut image = PixelBuffer::new(2, 2);
+        image.set_pixel(0, 0, Color::new(0.0, 0.0, 0.0));
+        image.set_pixel(1, 0, Color::new(1.0, 0.0, 0.0));
+        image.set_pixel(0, 1, Color::new(0.0, 1.0, 0.0));
+        image.set_pixel(1, 1, Color::new(1.0, 1.0, 0.0));
+        image
+    }
+
+    #[test]
+    fn checkers_alternate() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+
+        let uv = UvChecker::new(white, black, 2.0);
+        assert_eq!(uv.value(0.25, 0.25, origin), white);
+        assert_eq!(uv.value(0.75, 0.25, origin), black);
+        assert_eq!(uv.value(0.75, 0.75, origin), white);
+
+        let solid = SolidChecker::new(white, black, 1.0);
+        assert_eq!(solid.value(0.0, 0.0, Vec3::new(0.5, 0.5, 0.5)), white);
+        assert_eq!(solid.value(0.0, 0.0, Vec3::new(-0.5, 0.5, 0.5)), black);
+        assert_eq!(solid.value(0.0, 0.0, Vec3::new(-0.5, -0.5, 0.5)), white);
+    }
+
+    #[test]
+    fn image_lookup_and_filtering() {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let nearest = ImageTexture::new(make_gradient_image()).set_filter(Filter::Nearest);
+        // v goes up, so the bottom left of the texture is the first pixel of the bottom row.
+        assert_eq!(nearest.value(0.25, 0.25, origin), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(nearest.value(0.75, 0.75, origin), Color::new(1.0, 0.0, 0.0));
+
+        let bilinear = ImageTexture::new(make_gradient_image()).set_wrap_mode(WrapMode::Clamp);
+        let center: Vec3 = bilinear.value(0.5, 0.5, origin).into();
+        assert!((center - Vec3::new(0.5, 0.5, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn mipmaps_filter_by_footprint() {
+        // Vertical stripes one pixel wide, so the color only changes along u.
+        let mut stripes = Image::new(8, 8);
+        for y in 0..8 {
+            for x in (0..8).step_by(2) {
+                stripes.set_pixel(x, y, Vec3::new(1.0, 1.0, 1.0));
+            }
+        }
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let (u, v) = (0.5 / 8.0, 0.5);
+        // Long along v and a pixel wide along u.
+        let footprint = Footprint { dudx: 0.0, dvdx: 0.5, dudy: 0.5 / 8.0, dvdy: 0.0 };
+        let brightness = |texture: &ImageTexture, footprint: Footprint| Vec3::from(texture.filtered_value(u, v, origin, footprint)).x();
+
+        let trilinear = ImageTexture::new(stripes.clone());
+        assert!((brightness(&trilinear, Footprint::default()) - 1.0).abs() < 1e-9);
+        assert!((brightness(&trilinear, footprint) - 0.5).abs() < 0.05);
+        assert!((brightness(&trilinear, footprint.scaled(4.0, 4.0)) - 0.5).abs() < 1e-9);
+
+        // Anisotropic filtering only blurs along the long axis, so the stripe stays brighter than its surroundings.
+        let anisotropic = ImageTexture::new(stripes).set_filter(Filter::Anisotropic);
+        assert!(brightness(&anisotropic, footprint) > 0.6);
+    }
+
+    #[test]
+    fn footprint_grows_with_distance() {
+        use crate::{Hittable, Sphere, brdfs, interval::Interval, ray_math::{Ray, RayDifferentials}};
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+        let footprint_from = |distance: f64| {
+            let origin = Vec3::new(0.0, 0.0, distance);
+            let direction = Vec3::new(0.0, 0.0, -1.0);
+            let ray = Ray::new(origin, direction).set_differentials(RayDifferentials {
+                x_origin: origin,
+                x_direction: Vec3::new(0.001, 0.0, -1.0),
+                y_origin: origin,
+                y_direction: Vec3::new(0.0, -0.001, -1.0),
+            });
+            let hit = sphere.hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+            hit.footprint(ray)
+        };
+
+        let near = footprint_from(2.0);
+        let far = footprint_from(11.0);
+        assert!((far.width() / near.width() - 10.0).abs() < 0.01);
+        // Moving right on the screen moves along u around the sphere.
+        assert!((near.dudx - 0.001 / (2.0 * std::f64::consts::PI)).abs() < 1e-6);
+        assert!(near.dvdx.abs() < 1e-9);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        assert_eq!(wrap(-1, 4, WrapMode::Repeat), 3);
+        assert_eq!(wrap(5, 4, WrapMode::Repeat), 1);
+        assert_eq!(wrap(-1, 4, WrapMode::Mirror), 0);
+        assert_eq!(wrap(5, 4, WrapMode::Mirror), 2);
+        assert_eq!(wrap(-3, 4, WrapMode::Clamp), 0);
+        assert_eq!(wrap(9, 4, WrapMode::Clamp), 3);
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops;
 
-use crate::interval::Interval;
+use crate::{interval::Interval, sampling};
 
 /// Struct for representing 3d Math vectors.
 #[derive(PartialEq, Debug, Clone, Copy, Default)]
@@ -62,16 +62,10 @@ impl Vec3 {
         }
     }
 
-    /// Returns a random vector that lies on the unit sphere.
+    /// Returns a random vector that lies on the unit sphere, with all directions equally likely.
     #[must_use]
     pub fn random_unit_vector() -> Self {
-        loop {
-            let p = Self::random();
-            let square_length = p.square_length();
-            if 1e-160 < square_length && square_length <= 1.0 {
-                return p / square_length.sqrt();
-            }
-        }
+        sampling::uniform_sphere((rand::random(), rand::random()))
     }
 
     /// Returns a random vector that lies on the unit hemisphere that surrounds the normal vector.
@@ -254,6 +248,89 @@ pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
     }
 }
 
+/// Interpolates linearly from `a` at `t = 0` to `b` at `t = 1`.
+#[must_use]
+pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    (b - a).mul_add(t, a)
+}
+
+/// Returns two unit vectors that together with unit vector `n` form a right handed orthonormal basis.
+#[must_use]
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let basis = Onb::from_normal(n);
+    (basis.tangent(), basis.bitangent())
+}
+
+/// A right handed orthonormal basis, for working in a local frame such as the shading frame of a surface,
+/// where the tangent is the x axis, the bitangent the y axis and the normal the z axis.
+/// # Example
+/// ```
+/// use renders::vec_math::{Onb, Vec3};
+/// let basis = Onb::from_normal_and_tangent(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+/// assert_eq!(basis.to_local(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, 1.0));
+/// assert_eq!(basis.to_world(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(1.0, 0.0, 0.0));
+/// ```
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Onb {
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis around unit vector `normal`, with the branchless method of Duff et al. that stays accurate
+    /// for every direction of the normal.
+    #[must_use]
+    pub fn from_normal(normal: Vec3) -> Self {
+        let sign = 1.0f64.copysign(normal.z);
+        let a = -1.0 / (sign + normal.z);
+        let b = normal.x * normal.y * a;
+        Self {
+            tangent: Vec3::new((sign * normal.x * normal.x).mul_add(a, 1.0), sign * b, -sign * normal.x),
+            bitangent: Vec3::new(b, (normal.y * normal.y).mul_add(a, sign), -normal.y),
+            normal,
+        }
+    }
+
+    /// Builds a basis around unit vector `normal` with the tangent along `tangent` made perpendicular to the normal,
+    /// or any tangent if `tangent` is parallel to the normal.
+    #[must_use]
+    pub fn from_normal_and_tangent(normal: Vec3, tangent: Vec3) -> Self {
+        let tangent = tangent - dot(tangent, normal) * normal;
+        if tangent.near_zero() {
+            return Self::from_normal(normal);
+        }
+        let tangent = tangent.normalized();
+        Self { tangent, bitangent: cross(normal, tangent), normal }
+    }
+
+    #[must_use]
+    pub const fn tangent(&self) -> Vec3 {
+        self.tangent
+    }
+
+    #[must_use]
+    pub const fn bitangent(&self) -> Vec3 {
+        self.bitangent
+    }
+
+    #[must_use]
+    pub const fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Expresses a world space vector in the basis.
+    #[must_use]
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(dot(v, self.tangent), dot(v, self.bitangent), dot(v, self.normal))
+    }
+
+    /// Turns a vector expressed in the basis back into world space.
+    #[must_use]
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+}
 
 #[cfg(test)]
 #[allow(clippy::float_cmp)]
@@ -320,6 +397,39 @@ mod tests {
         assert_eq!(dot(a, a), 1.0);
     }
 
+    #[test]
+    fn random_unit_vectors_are_unit_length() {
+        // These hold for any random numbers, how evenly the directions are spread is tested on the samplers they use.
+        let normal = Vec3::new(0.0, 0.6, 0.8);
+        for _ in 0..1000 {
+            assert!((Vec3::random_unit_vector().length() - 1.0).abs() < 1e-9);
+            let on_hemisphere = Vec3::random_on_hemisphere(normal);
+            assert!((on_hemisphere.length() - 1.0).abs() < 1e-9);
+            assert!(dot(on_hemisphere, normal) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn bases_are_orthonormal_and_right_handed() {
+        let normals = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 1e-9).normalized(),
+            Vec3::new(0.3, -0.5, -0.8).normalized(),
+        ];
+        let v = Vec3::new(0.2, -0.7, 0.4);
+        for normal in normals {
+            for basis in [Onb::from_normal(normal), Onb::from_normal_and_tangent(normal, Vec3::new(1.0, 1.0, 0.0))] {
+                assert!((basis.tangent().length() - 1.0).abs() < 1e-12 && (basis.bitangent().length() - 1.0).abs() < 1e-12);
+                assert!(dot(basis.tangent(), basis.normal()).abs() < 1e-12 && dot(basis.bitangent(), basis.normal()).abs() < 1e-12);
+                assert!((cross(basis.tangent(), basis.bitangent()) - normal).length() < 1e-12);
+                assert!((basis.to_world(basis.to_local(v)) - v).length() < 1e-12);
+                assert!((basis.to_local(normal) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+            }
+        }
+    }
+
     #[test]
     fn cross_test() {
         let a = Vec3::new(2.0, 3.0, 4.0);
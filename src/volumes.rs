@@ -0,0 +1,736 @@
+use std::sync::Arc;
+
+use crate::{HitRecord, Hittable, Sphere, brdfs::{BRDF, Material, MaterialFlags, Reflection, sample_henyey_greenstein}, colors::Color, first_opaque_hit, interval::Interval, microfacet::fresnel_dielectric, ray_math::{Ray, box_crossings}, textures::Texture, vec_math::{Vec3, dot, reflect, refract, unit_vector}, voxel_grid::VoxelGrid};
+
+/// A volume of constant density inside a closed boundary shape, such as smoke or a cloud.
+///
+/// Rays travelling through the volume have a chance to scatter proportional to the density,
+/// the direction they scatter in is chosen by the phase function.
+///
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, vec_math::Vec3, volumes::ConstantMedium, Sphere};
+/// let boundary = Sphere::new(
+///     Vec3::new(0.0, 0.0, -1.0),
+///     0.5,
+///     brdfs::make_lambertian_diffuse_brdf(Color::new(0.0, 0.0, 0.0)),
+/// );
+/// let smoke = ConstantMedium::new(
+///     boundary,
+///     2.0,
+///     brdfs::make_henyey_greenstein_phase_function(Color::new(0.9, 0.9, 0.9), 0.3),
+/// );
+/// ```
+pub struct ConstantMedium<T: Hittable> {
+    boundary: T,
+    negative_inverse_density: f64,
+    phase_function: BRDF,
+}
+
+impl<T: Hittable> ConstantMedium<T> {
+    /// Creates a new volume filling the inside of `boundary`, the boundary has to be a closed shape.
+    /// # Panics
+    /// panics if density is not larger than 0.
+    #[must_use]
+    pub fn new(boundary: T, density: f64, phase_function: BRDF) -> Self {
+        assert!(density > 0.0);
+        Self {
+            boundary,
+            negative_inverse_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl<T: Hittable> Hittable for ConstantMedium<T> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.scatter(ray, ray_t, rand::random()), rand::random::<f64>)
+    }
+}
+
+impl<T: Hittable> ConstantMedium<T> {
+    /// Finds where the ray scatters inside the boundary, using the uniform random number `u` in [0, 1).
+    fn scatter(&self, ray: Ray, ray_t: Interval, u: f64) -> Option<HitRecord> {
+        let (entry, exit) = boundary_crossings(&self.boundary, ray, ray_t)?;
+
+        let ray_length = ray.direction().length();
+        let distance_inside_boundary = (exit - entry) * ray_length;
+        let hit_distance = self.negative_inverse_density * (1.0 - u).ln();
+
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = entry + hit_distance / ray_length;
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            // The normal, texture coordinates and facing are meaningless inside a volume and ignored by phase functions.
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(1.0, 0.0, 0.0),
+            tangent: Vec3::new(0.0, 1.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
+            front_face: true,
+            brdf: self.phase_function.clone(),
+        })
+    }
+}
+
+/// Global atmospheric fog: a constant medium filling a very large sphere around the scene.
+/// Rays that leave the sphere reach the sky, so the sphere should surround the whole scene.
+///
+/// ## Default values:
+/// - `center`: (0.0, 0.0, 0.0),
+/// - `radius`: 1000.0
+pub struct Fog {
+    medium: ConstantMedium<Sphere>,
+}
+
+impl Fog {
+    /// Creates fog of the given density that scatters light according to `phase_function`.
+    /// # Panics
+    /// panics if density is not larger than 0.
+    #[must_use]
+    pub fn new(density: f64, phase_function: BRDF) -> Self {
+        let boundary = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1000.0, phase_function.clone());
+        Self { medium: ConstantMedium::new(boundary, density, phase_function) }
+    }
+
+    /// Sets the region filled by the fog.
+    /// # Panics
+    /// panics if radius is set to be smaller than 0.
+    #[must_use]
+    pub fn set_extent(self, center: Vec3, radius: f64) -> Self {
+        let boundary = Sphere::new(center, radius, self.medium.phase_function.clone());
+        Self {
+            medium: ConstantMedium { boundary, ..self.medium }
+        }
+    }
+}
+
+impl Hittable for Fog {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        self.medium.hit(ray, ray_t)
+    }
+}
+
+/// A volume with a density and temperature that vary through space, loaded from a voxel grid.
+/// The grid is stretched to fill the axis aligned box from `min` to `max`.
+///
+/// Scattering is found with delta tracking and transmittance is estimated with ratio tracking,
+/// both are unbiased for any density in the grid.
+/// Voxels with a temperature glow with the color of a black body of that temperature,
+/// the phase function is wrapped in a material that emits this light at every point where a ray interacts with the volume.
+///
+/// # Example
+/// ```no_run
+/// use renders::{brdfs, colors::Color, vec_math::Vec3, volumes::HeterogeneousMedium, voxel_grid::VoxelGrid};
+/// let grid = VoxelGrid::load("smoke.rvol").expect("Smoke file should be readable.");
+/// let smoke = HeterogeneousMedium::new(
+///     grid,
+///     Vec3::new(-1.0, 0.0, -2.0),
+///     Vec3::new(1.0, 2.0, 0.0),
+///     brdfs::make_isotropic_phase_function(Color::new(0.8, 0.8, 0.8)),
+/// )
+/// .set_density_scale(5.0)
+/// .set_emission_scale(0.5);
+/// ```
+/// ## Default values:
+/// - `density_scale`: 1.0,
+/// - `emission_scale`: 1.0
+pub struct HeterogeneousMedium {
+    grid: Arc<VoxelGrid>,
+    min: Vec3,
+    max: Vec3,
+    density_scale: f64,
+    majorant: f64,
+    emission_scale: f64,
+    phase_function: BRDF,
+    material: BRDF,
+}
+
+impl HeterogeneousMedium {
+    /// Creates a new volume from a voxel grid filling the box from `min` to `max`.
+    /// # Panics
+    /// Panics if `max` is not larger than `min` along every axis.
+    #[must_use]
+    pub fn new(grid: VoxelGrid, min: Vec3, max: Vec3, phase_function: BRDF) -> Self {
+        assert!(min.x() < max.x() && min.y() < max.y() && min.z() < max.z());
+        let majorant = grid.max_density();
+        Self {
+            grid: Arc::new(grid),
+            min,
+            max,
+            density_scale: 1.0,
+            majorant,
+            emission_scale: 1.0,
+            material: phase_function.clone(),
+            phase_function,
+        }
+        .with_material()
+    }
+
+    /// Sets the factor all densities in the grid are multiplied with.
+    /// # Panics
+    /// Panics if the scale is negative.
+    #[must_use]
+    pub fn set_density_scale(self, density_scale: f64) -> Self {
+        assert!(density_scale >= 0.0);
+        Self {
+            majorant: self.grid.max_density() * density_scale,
+            density_scale,
+            ..self
+        }
+    }
+
+    /// Sets the brightness of the emission, it is the brightness of a voxel at 6500 Kelvin.
+    /// Brightness scales with the fourth power of the temperature.
+    #[must_use]
+    pub fn set_emission_scale(self, emission_scale: f64) -> Self {
+        Self { emission_scale, ..self }.with_material()
+    }
+
+    /// Builds the material of the hits from the phase function, glowing if the grid has temperatures.
+    fn with_material(self) -> Self {
+        if !self.grid.has_temperature() {
+            return Self { material: self.phase_function.clone(), ..self };
+        }
+        let material = Arc::new(Blackbody {
+            grid: self.grid.clone(),
+            min: self.min,
+            max: self.max,
+            emission_scale: self.emission_scale,
+            phase_function: self.phase_function.clone(),
+        });
+        Self { material, ..self }
+    }
+
+    /// Estimates the fraction of light that passes through the volume along the ray within `ray_t`, using ratio tracking.
+    #[must_use]
+    pub fn transmittance(&self, ray: Ray, ray_t: Interval) -> f64 {
+        self.ratio_track(ray, ray_t, rand::random::<f64>)
+    }
+
+    fn density(&self, point: Vec3) -> f64 {
+        self.grid.density_at(local_position(self.min, self.max, point)) * self.density_scale
+    }
+
+    /// Estimates the transmittance with ratio tracking, drawing uniform random numbers in [0, 1) from `random`.
+    fn ratio_track<R: FnMut() -> f64>(&self, ray: Ray, ray_t: Interval, mut random: R) -> f64 {
+        let Some((entry, exit)) = box_crossings(self.min, self.max, ray, ray_t) else {
+            return 1.0;
+        };
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+
+        // Ratio tracking: instead of stopping at a collision, weigh by the chance of passing through it.
+        let step_scale = 1.0 / (self.majorant * ray.direction().length());
+        let mut transmittance = 1.0;
+        let mut t = entry;
+        loop {
+            t -= (1.0 - random()).ln() * step_scale;
+            if t >= exit {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density(ray.at(t)) / self.majorant;
+        }
+    }
+
+    /// Finds where the ray scatters with delta tracking, drawing uniform random numbers in [0, 1) from `random`.
+    fn track<R: FnMut() -> f64>(&self, ray: Ray, ray_t: Interval, mut random: R) -> Option<HitRecord> {
+        let (entry, exit) = box_crossings(self.min, self.max, ray, ray_t)?;
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        // Delta tracking: sample collisions against the majorant and accept them with probability density / majorant.
+        let step_scale = 1.0 / (self.majorant * ray.direction().length());
+        let mut t = entry;
+        loop {
+            t -= (1.0 - random()).ln() * step_scale;
+            if t >= exit {
+                return None;
+            }
+
+            let point = ray.at(t);
+            if random() * self.majorant < self.density(point) {
+                return Some(HitRecord {
+                    t,
+                    point,
+                    normal: Vec3::new(1.0, 0.0, 0.0),
+                    geometric_normal: Vec3::new(1.0, 0.0, 0.0),
+                    tangent: Vec3::new(0.0, 1.0, 0.0),
+                    u: 0.0,
+                    v: 0.0,
+                    dpdu: Vec3::default(),
+                    dpdv: Vec3::default(),
+                    front_face: true,
+                    brdf: self.material.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.track(ray, ray_t, rand::random::<f64>), rand::random::<f64>)
+    }
+}
+
+/// The phase function of a heterogeneous medium, glowing with the black body color of the temperature in the grid.
+struct Blackbody {
+    grid: Arc<VoxelGrid>,
+    min: Vec3,
+    max: Vec3,
+    emission_scale: f64,
+    phase_function: BRDF,
+}
+
+impl Material for Blackbody {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        self.phase_function.sample(incoming, hit)
+    }
+
+    fn eval(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> Vec3 {
+        self.phase_function.eval(incoming, hit, direction)
+    }
+
+    fn pdf(&self, incoming: Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        self.phase_function.pdf(incoming, hit, direction)
+    }
+
+    fn emitted(&self, _incoming: Ray, hit: &HitRecord) -> Vec3 {
+        let temperature = self.grid.temperature_at(local_position(self.min, self.max, hit.point));
+        if temperature <= 0.0 {
+            return Vec3::default();
+        }
+        Vec3::from(Color::from_temperature(temperature)) * (self.emission_scale * (temperature / 6500.0).powi(4))
+    }
+
+    fn flags(&self) -> MaterialFlags {
+        self.phase_function.flags()
+    }
+}
+
+/// Position of `point` relative to the box from `min` to `max`, in [0, 1] along each axis inside the box.
+fn local_position(min: Vec3, max: Vec3, point: Vec3) -> Vec3 {
+    let relative = point - min;
+    let size = max - min;
+    Vec3::new(relative.x() / size.x(), relative.y() / size.y(), relative.z() / size.z())
+}
+
+/// Scattering events followed by a random walk under a subsurface scattering surface before Russian roulette may end
+/// it, which keeps the walk unbiased while ending walks whose light has mostly been absorbed.
+const ROULETTE_SCATTER_EVENTS: usize = 256;
+
+/// A translucent object such as skin, wax, marble or milk, where light enters the surface, scatters around inside and
+/// leaves again some distance away from where it entered.
+///
+/// Light crossing the smooth boundary follows a random walk through a medium filling the closed boundary shape, until
+/// it finds its way out again. The `albedo` is the color of the object seen from far away: it is converted to the
+/// chance of surviving each scattering event, so a thick object ends up with roughly that color. The distance between
+/// scattering events is the same for all channels, the channels with a higher albedo survive more scattering events and
+/// so spread further under the surface, which gives the glow of for example a hand lit from behind.
+/// Other objects inside the boundary are ignored by the walk.
+///
+/// # Example
+/// ```
+/// use renders::{brdfs, colors::Color, vec_math::Vec3, volumes::Subsurface, Sphere};
+/// let boundary = Sphere::new(
+///     Vec3::new(0.0, 0.0, -1.0),
+///     0.5,
+///     brdfs::make_lambertian_diffuse_brdf(Color::new(0.0, 0.0, 0.0)),
+/// );
+/// let wax = Subsurface::new(boundary, Color::new(0.9, 0.7, 0.5), 0.05)
+///     .set_ior(1.45)
+///     .set_anisotropy(0.3);
+/// ```
+/// ## Default values:
+/// - `ior`: 1.4,
+/// - `anisotropy`: 0.0
+pub struct Subsurface<H: Hittable, T: Texture> {
+    walk: RandomWalk<H, T>,
+    material: BRDF,
+}
+
+impl<H: Hittable + Send + Sync + 'static, T: Texture + 'static> Subsurface<H, T> {
+    /// Creates a translucent object filling the inside of `boundary`, the boundary has to be a closed shape.
+    /// `mean_free_path` is the average distance light travels under the surface between scattering events.
+    /// # Panics
+    /// panics if the mean free path is not larger than 0.
+    #[must_use]
+    pub fn new(boundary: H, albedo: T, mean_free_path: f64) -> Self {
+        assert!(mean_free_path > 0.0);
+        Self::from_walk(RandomWalk {
+            boundary: Arc::new(boundary),
+            albedo: Arc::new(albedo),
+            mean_free_path,
+            ior: 1.4,
+            anisotropy: 0.0,
+        })
+    }
+
+    /// Sets the index of refraction of the boundary, which decides how much light is reflected off the surface.
+    #[must_use]
+    pub fn set_ior(self, ior: f64) -> Self {
+        Self::from_walk(RandomWalk { ior, ..self.walk })
+    }
+
+    /// Sets the asymmetry of the Henyey-Greenstein phase function used inside, in the range (-1, 1).
+    /// Skin and most other organic materials scatter strongly forwards.
+    /// # Panics
+    /// panics if the anisotropy is not in the range (-1, 1).
+    #[must_use]
+    pub fn set_anisotropy(self, anisotropy: f64) -> Self {
+        assert!(anisotropy > -1.0 && anisotropy < 1.0);
+        Self::from_walk(RandomWalk { anisotropy, ..self.walk })
+    }
+
+    fn from_walk(walk: RandomWalk<H, T>) -> Self {
+        let material = Arc::new(RandomWalk {
+            boundary: walk.boundary.clone(),
+            albedo: walk.albedo.clone(),
+            ..walk
+        });
+        Self { walk, material }
+    }
+}
+
+impl<H: Hittable, T: Texture> Hittable for Subsurface<H, T> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        first_opaque_hit(ray, ray_t, |ray_t| self.intersect(ray, ray_t), rand::random::<f64>)
+    }
+}
+
+impl<H: Hittable, T: Texture> Subsurface<H, T> {
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut hit = self.walk.boundary.hit(ray, ray_t)?;
+        hit.brdf = self.material.clone();
+        Some(hit)
+    }
+}
+
+struct RandomWalk<H: Hittable, T: Texture> {
+    boundary: Arc<H>,
+    albedo: Arc<T>,
+    mean_free_path: f64,
+    ior: f64,
+    anisotropy: f64,
+}
+
+impl<H: Hittable, T: Texture> RandomWalk<H, T> {
+    /// Decides whether light crosses the smooth boundary at a hit, with the chance given by the Fresnel equations.
+    /// Returns the refracted direction, or `None` if the light is reflected.
+    fn cross_boundary(&self, direction: Vec3, hit: &HitRecord, u: f64) -> Option<Vec3> {
+        let cos_theta = dot(-direction, hit.normal).min(1.0);
+        let (cos_outside, refraction_constant) = if hit.front_face {(cos_theta, 1.0 / self.ior)} else {(-cos_theta, self.ior)};
+        if fresnel_dielectric(cos_outside, self.ior) > u {
+            return None;
+        }
+        Some(unit_vector(refract(direction, hit.normal, refraction_constant)))
+    }
+
+    /// Follows light that entered the boundary along `ray` until it leaves again, `albedo` is the chance per channel of
+    /// surviving a scattering event.
+    fn walk<R: FnMut() -> f64>(&self, mut ray: Ray, albedo: Vec3, random: &mut R) -> Option<Reflection> {
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        for events in 0.. {
+            let exit = self.boundary.hit(ray, Interval::new(0.0, f64::INFINITY))?;
+            let distance = -self.mean_free_path * (1.0 - random()).ln();
+
+            if distance < exit.t {
+                throughput = throughput * albedo;
+                let survival = throughput.x().max(throughput.y()).max(throughput.z());
+                if events >= ROULETTE_SCATTER_EVENTS && survival < 1.0 {
+                    if random() >= survival {
+                        return None;
+                    }
+                    throughput = throughput / survival;
+                }
+                ray = Ray::new(ray.at(distance), sample_henyey_greenstein(ray.direction(), self.anisotropy, (random(), random())));
+            } else if let Some(refracted) = self.cross_boundary(ray.direction(), &exit, random()) {
+                return Some(Reflection { reflected: exit.spawn_ray(refracted), attenuation: throughput });
+            } else {
+                ray = exit.spawn_ray(reflect(ray.direction(), exit.normal));
+            }
+        }
+        unreachable!("the walk only ends by leaving the boundary or by Russian roulette")
+    }
+
+    /// Samples the light leaving the surface, drawing uniform random numbers in [0, 1) from `random`.
+    fn sample_with<R: FnMut() -> f64>(&self, incoming: Ray, hit: &HitRecord, random: &mut R) -> Option<Reflection> {
+        let direction = unit_vector(incoming.direction());
+        let Some(refracted) = self.cross_boundary(direction, hit, random()) else {
+            let bend = |direction: Vec3| reflect(direction, hit.normal);
+            return Some(
+                Reflection { reflected: hit.spawn_specular_ray(incoming, bend(direction), bend), attenuation: Vec3::new(1.0, 1.0, 1.0) }
+            );
+        };
+        if !hit.front_face {
+            return Some(Reflection { reflected: hit.spawn_ray(refracted), attenuation: Vec3::new(1.0, 1.0, 1.0) });
+        }
+
+        let albedo: Vec3 = self.albedo.value(hit.u, hit.v, hit.point).into();
+        let albedo = Vec3::new(
+            single_scattering_albedo(albedo.x()),
+            single_scattering_albedo(albedo.y()),
+            single_scattering_albedo(albedo.z()),
+        );
+        self.walk(hit.spawn_ray(refracted), albedo, random)
+    }
+}
+
+impl<H: Hittable + Send + Sync, T: Texture> Material for RandomWalk<H, T> {
+    fn sample(&self, incoming: Ray, hit: &HitRecord) -> Option<Reflection> {
+        self.sample_with(incoming, hit, &mut rand::random::<f64>)
+    }
+
+    fn eval(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn pdf(&self, _incoming: Ray, _hit: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// Where the light leaves the surface can only be found by walking, so it can not be evaluated for a given
+    /// direction and is treated like a specular surface by light sampling.
+    fn flags(&self) -> MaterialFlags {
+        MaterialFlags::SPECULAR | MaterialFlags::TRANSMISSIVE
+    }
+}
+
+/// Chance of surviving a single scattering event that makes a thick medium reflect `albedo` of the light in total,
+/// using the inverse of van de Hulst's fit for isotropic scattering.
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let albedo = albedo.clamp(0.0, 1.0);
+    let root = 4.20863f64.mul_add(albedo, 4.09712) - 17.7126f64.mul_add(albedo * albedo, 41.6808f64.mul_add(albedo, 9.59217)).sqrt();
+    root.mul_add(-root, 1.0)
+}
+
+/// Finds the segment of the ray, limited to `ray_t`, that lies inside of a closed boundary.
+/// Returns the ray parameters where the ray enters and exits the boundary.
+pub(crate) fn boundary_crossings<T: Hittable>(boundary: &T, ray: Ray, ray_t: Interval) -> Option<(f64, f64)> {
+    let entry = boundary.hit(ray, Interval::universe())?;
+    let exit = boundary.hit(ray, Interval::new(entry.t + 0.0001, f64::INFINITY))?;
+
+    let entry = entry.t.max(ray_t.min()).max(0.0);
+    let exit = exit.t.min(ray_t.max());
+
+    if entry >= exit {
+        return None;
+    }
+    Some((entry, exit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brdfs;
+    use crate::sampling;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn scattering_stays_inside_boundary() {
+        let medium = ConstantMedium::new(
+            Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5))),
+            0.5,
+            brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let mut scattered = 0;
+        for index in 0..1000 {
+            let u = (f64::from(index) + 0.5) / 1000.0;
+            if let Some(hit) = medium.scatter(ray, Interval::new(0.00001, f64::INFINITY), u) {
+                assert!(hit.t >= 4.0 && hit.t <= 6.0);
+                assert!(hit.point.length() <= 1.0 + 1e-9);
+                scattered += 1;
+            }
+        }
+        // Two units of density 0.5 let 1/e of the light through.
+        assert_eq!(scattered, 632);
+    }
+
+    #[test]
+    fn missing_boundary_never_scatters() {
+        let medium = ConstantMedium::new(
+            Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5))),
+            1000.0,
+            brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 2.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        for index in 0..100 {
+            let u = (f64::from(index) + 0.5) / 100.0;
+            assert!(medium.scatter(ray, Interval::new(0.00001, f64::INFINITY), u).is_none());
+        }
+    }
+
+    #[test]
+    fn dense_fog_scatters_from_inside() {
+        let fog = Fog::new(1000.0, brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        for index in 0..100 {
+            let u = (f64::from(index) + 0.5) / 100.0;
+            let hit = fog.medium.scatter(ray, Interval::new(0.00001, f64::INFINITY), u).expect("Fog this dense should always scatter.");
+            assert!(hit.t < 1.0);
+        }
+    }
+
+    #[test]
+    fn delta_tracking_matches_beer_lambert() {
+        let grid = VoxelGrid::new(2, 2, 2, vec![1.0; 8]);
+        let medium = HeterogeneousMedium::new(
+            grid,
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)),
+        )
+        .set_density_scale(0.5);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // Rays pass through the two units of density 0.5 with a chance of 1/e, the estimate has a standard deviation
+        // of sqrt(p (1 - p) / n).
+        let mut rng = StdRng::seed_from_u64(28);
+        let samples = 20_000;
+        let passed = (0..samples)
+            .filter(|_| medium.track(ray, Interval::new(0.00001, f64::INFINITY), || rng.random()).is_none())
+            .count();
+        let fraction = f64::from(u32::try_from(passed).expect("at most the number of samples")) / f64::from(samples);
+        let expected = (-1.0f64).exp();
+        let deviation = (expected * (1.0 - expected) / f64::from(samples)).sqrt();
+        assert!((fraction - expected).abs() < 4.0 * deviation, "{fraction}");
+    }
+
+    #[test]
+    fn ratio_tracking_matches_beer_lambert() {
+        // The ray runs between the two voxels, through density 0.5 below a majorant of 1, so every collision weighs.
+        let grid = VoxelGrid::new(2, 1, 1, vec![1.0, 0.0]);
+        let medium = HeterogeneousMedium::new(
+            grid,
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let mut rng = StdRng::seed_from_u64(28);
+        let samples = 20_000;
+        let estimates: Vec<f64> = (0..samples)
+            .map(|_| medium.ratio_track(ray, Interval::new(0.00001, f64::INFINITY), || rng.random()))
+            .collect();
+        let mean = estimates.iter().sum::<f64>() / f64::from(samples);
+        let variance = estimates.iter().map(|estimate| (estimate - mean).powi(2)).sum::<f64>() / f64::from(samples - 1);
+        let deviation = (variance / f64::from(samples)).sqrt();
+        assert!((mean - (-1.0f64).exp()).abs() < 4.0 * deviation, "{mean} {deviation}");
+    }
+
+    #[test]
+    fn empty_grid_is_transparent() {
+        let medium = HeterogeneousMedium::new(
+            VoxelGrid::new(1, 1, 1, vec![0.0]),
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(medium.hit(ray, Interval::new(0.00001, f64::INFINITY)).is_none());
+        assert!((medium.transmittance(ray, Interval::new(0.00001, f64::INFINITY)) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn hot_voxels_glow_brighter_than_white() {
+        let grid = VoxelGrid::new(1, 1, 1, vec![1000.0]).set_temperature(vec![6500.0]);
+        let fire = HeterogeneousMedium::new(
+            grid,
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            brdfs::make_isotropic_phase_function(Color::new(1.0, 1.0, 1.0)),
+        )
+        .set_emission_scale(8.0);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let mut rng = StdRng::seed_from_u64(28);
+        let hit = fire.track(ray, Interval::new(0.00001, f64::INFINITY), || rng.random()).expect("dense volume");
+        let expected = 8.0 * Vec3::from(Color::from_temperature(6500.0));
+        assert!((hit.brdf.emitted(ray, &hit) - expected).length() < 1e-9);
+        assert!(hit.brdf.emitted(ray, &hit).x() > 1.0);
+    }
+
+    #[test]
+    fn subsurface_light_leaves_through_the_boundary() {
+        let boundary = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+        let wax = Subsurface::new(boundary, Color::new(0.9, 0.6, 0.3), 0.2).set_anisotropy(0.5);
+        let ray = Ray::new(Vec3::new(0.2, 0.1, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = wax.hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+
+        let mut rng = StdRng::seed_from_u64(41);
+        for _ in 0..200 {
+            let Some(reflection) = wax.walk.sample_with(ray, &hit, &mut || rng.random()) else {
+                continue;
+            };
+            let origin = reflection.reflected.origin();
+            assert!((origin.length() - 1.0).abs() < 1e-6);
+            assert!(dot(reflection.reflected.direction(), origin) > 0.0);
+        }
+    }
+
+    #[test]
+    fn thick_subsurface_reflects_its_albedo() {
+        let boundary = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+        let marble = Subsurface::new(boundary, Color::new(0.5, 0.5, 0.5), 0.002).set_ior(1.0);
+
+        // Parallel rays spread evenly over the square around the disk of the sphere light it like a diffuse surface
+        // would be lit, so the light reflected per ray averages to the albedo times the area of the disk.
+        let mut rng = StdRng::seed_from_u64(41);
+        let side = 64;
+        let (mut sum, mut square_sum) = (0.0, 0.0);
+        for index in 0..side * side {
+            let (x, y) = sampling::stratify(index, side, side, (rng.random(), rng.random()));
+            let ray = Ray::new(Vec3::new(2.0f64.mul_add(x, -1.0), 2.0f64.mul_add(y, -1.0), 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let reflected = marble
+                .hit(ray, Interval::new(0.0, f64::INFINITY))
+                .and_then(|hit| marble.walk.sample_with(ray, &hit, &mut || rng.random()))
+                .map_or(0.0, |reflection| reflection.attenuation.x() / (std::f64::consts::PI / 4.0));
+            sum += reflected;
+            square_sum += reflected * reflected;
+        }
+
+        // The estimate is compared within four standard errors, treating the stratified samples as independent.
+        let samples = f64::from(side * side);
+        let mean = sum / samples;
+        let variance = mean.mul_add(-mean, square_sum / samples);
+        let deviation = (variance / samples).sqrt();
+        assert!((mean - 0.5).abs() < 4.0 * deviation, "{mean} {deviation}");
+    }
+
+    #[test]
+    fn long_walks_keep_their_energy() {
+        // Without absorption every walk leaves the boundary eventually, so all light that enters comes back out.
+        let boundary = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, brdfs::make_lambertian_diffuse_brdf(Color::new(0.5, 0.5, 0.5)));
+        let milk = Subsurface::new(boundary, Color::new(1.0, 1.0, 1.0), 0.005).set_ior(1.0);
+
+        let mut rng = StdRng::seed_from_u64(41);
+        let side = 32;
+        let mut sum = 0.0;
+        for index in 0..side * side {
+            let (x, y) = sampling::stratify(index, side, side, (rng.random(), rng.random()));
+            let ray = Ray::new(Vec3::new(x - 0.5, y - 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let hit = milk.hit(ray, Interval::new(0.0, f64::INFINITY)).expect("ray aimed at the sphere");
+            sum += milk.walk.sample_with(ray, &hit, &mut || rng.random()).map_or(0.0, |reflection| reflection.attenuation.x());
+        }
+
+        // Walks as long as the limit on scattering events happen for about a tenth of the rays, ending them there
+        // would lose that light. Russian roulette does not end walks without absorption.
+        let mean = sum / f64::from(side * side);
+        assert!(mean > 0.99, "{mean}");
+    }
+}
@@ -0,0 +1,271 @@
+//! Dense voxel grids for heterogeneous volumes, and the raw file format they are stored in.
+//!
+//! # File format
+//! A voxel grid file starts with a plain text header of three lines, each terminated by a `\n`:
+//! ```text
+//! RVOL
+//! <size x> <size y> <size z>
+//! <channels>
+//! ```
+//! `<channels>` is 1 for a grid containing only density or 2 for a grid containing density and temperature.
+//!
+//! The header is directly followed by the voxel values as little endian 32 bit floats.
+//! Each channel is stored as a separate block, first all densities and then all temperatures.
+//! Within a block the voxels are ordered with x changing fastest, then y and then z.
+//! Temperatures are in Kelvin. Densities and temperatures may not be negative or NaN.
+
+use std::{fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+use crate::{invalid_data, vec_math::{Vec3, lerp}};
+
+const MAGIC: &str = "RVOL";
+
+/// A dense grid of voxels with a density channel and an optional temperature channel.
+/// The grid is sampled with coordinates in [0, 1] along each axis, voxel values lie at the voxel centers.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VoxelGrid {
+    size_x: usize,
+    size_y: usize,
+    size_z: usize,
+    density: Vec<f32>,
+    temperature: Option<Vec<f32>>,
+}
+
+impl VoxelGrid {
+    /// Creates a voxel grid from densities ordered x fastest, then y and then z.
+    /// # Panics
+    /// Panics if any of the sizes is zero, if the amount of densities does not match the size of the grid
+    /// or if a density is negative or NaN.
+    #[must_use]
+    pub fn new(size_x: usize, size_y: usize, size_z: usize, density: Vec<f32>) -> Self {
+        assert!(size_x > 0 && size_y > 0 && size_z > 0);
+        assert_eq!(density.len(), size_x * size_y * size_z);
+        assert!(density.iter().all(|density| *density >= 0.0));
+        Self { size_x, size_y, size_z, density, temperature: None }
+    }
+
+    /// Adds a temperature channel in Kelvin to the grid, ordered the same as the densities.
+    /// # Panics
+    /// Panics if the amount of temperatures does not match the size of the grid or if a temperature is negative or NaN.
+    #[must_use]
+    pub fn set_temperature(self, temperature: Vec<f32>) -> Self {
+        assert_eq!(temperature.len(), self.density.len());
+        assert!(temperature.iter().all(|temperature| *temperature >= 0.0));
+        Self { temperature: Some(temperature), ..self }
+    }
+
+    /// Loads a voxel grid from a file.
+    /// # Errors
+    /// Returns an error if the file can not be read or is not a valid voxel grid file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the voxel grid to a file.
+    /// # Errors
+    /// Returns an error if the file can not be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a voxel grid in the raw voxel format.
+    /// # Errors
+    /// Returns an error if reading fails or the data is not a valid voxel grid.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if read_header_line(&mut reader)? != MAGIC {
+            return Err(invalid_data("not a voxel grid file"));
+        }
+
+        let sizes = read_header_line(&mut reader)?
+            .split_whitespace()
+            .map(str::parse::<usize>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid_data("invalid grid size"))?;
+        let [size_x, size_y, size_z] = sizes[..] else {
+            return Err(invalid_data("grid size should have three dimensions"));
+        };
+        if size_x == 0 || size_y == 0 || size_z == 0 {
+            return Err(invalid_data("grid size should not be zero"));
+        }
+
+        let channels = read_header_line(&mut reader)?
+            .parse::<usize>()
+            .map_err(|_| invalid_data("invalid channel count"))?;
+        if !(1..=2).contains(&channels) {
+            return Err(invalid_data("voxel grids have either 1 or 2 channels"));
+        }
+
+        let voxel_count = size_x
+            .checked_mul(size_y)
+            .and_then(|count| count.checked_mul(size_z))
+            .ok_or_else(|| invalid_data("grid size too large"))?;
+
+        let density = read_channel(&mut reader, voxel_count)?;
+        let temperature = if channels == 2 {
+            Some(read_channel(&mut reader, voxel_count)?)
+        } else {
+            None
+        };
+
+        Ok(Self { size_x, size_y, size_z, density, temperature })
+    }
+
+    /// Writes the voxel grid in the raw voxel format.
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let channels = if self.temperature.is_some() {2} else {1};
+        write!(writer, "{MAGIC}\n{} {} {}\n{channels}\n", self.size_x, self.size_y, self.size_z)?;
+
+        for value in self.density.iter().chain(self.temperature.iter().flatten()) {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the size of the grid in voxels along x, y and z.
+    #[must_use]
+    pub const fn size(&self) -> (usize, usize, usize) {
+        (self.size_x, self.size_y, self.size_z)
+    }
+
+    /// Returns true if the grid contains a temperature channel.
+    #[must_use]
+    pub const fn has_temperature(&self) -> bool {
+        self.temperature.is_some()
+    }
+
+    /// Returns the largest density in the grid.
+    #[must_use]
+    pub fn max_density(&self) -> f64 {
+        self.density.iter().copied().fold(0.0, f32::max).into()
+    }
+
+    /// Returns the trilinearly interpolated density at `position`, with each coordinate in the range [0, 1].
+    #[must_use]
+    pub fn density_at(&self, position: Vec3) -> f64 {
+        self.interpolate(&self.density, position)
+    }
+
+    /// Returns the trilinearly interpolated temperature at `position`, or 0 if the grid has no temperature channel.
+    #[must_use]
+    pub fn temperature_at(&self, position: Vec3) -> f64 {
+        self.temperature.as_ref().map_or(0.0, |temperature| self.interpolate(temperature, position))
+    }
+
+    fn interpolate(&self, channel: &[f32], position: Vec3) -> f64 {
+        let (x0, x1, tx) = cell_coordinates(position.x(), self.size_x);
+        let (y0, y1, ty) = cell_coordinates(position.y(), self.size_y);
+        let (z0, z1, tz) = cell_coordinates(position.z(), self.size_z);
+
+        let at = |x: usize, y: usize, z: usize| f64::from(channel[(z * self.size_y + y) * self.size_x + x]);
+
+        let c00 = lerp(at(x0, y0, z0), at(x1, y0, z0), tx);
+        let c10 = lerp(at(x0, y1, z0), at(x1, y1, z0), tx);
+        let c01 = lerp(at(x0, y0, z1), at(x1, y0, z1), tx);
+        let c11 = lerp(at(x0, y1, z1), at(x1, y1, z1), tx);
+
+        lerp(lerp(c00, c10, ty), lerp(c01, c11, ty), tz)
+    }
+}
+
+/// Returns the two voxel indices surrounding a coordinate in [0, 1] and the interpolation weight between them.
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn cell_coordinates(coordinate: f64, size: usize) -> (usize, usize, f64) {
+    let continuous = coordinate.mul_add(size as f64, -0.5).clamp(0.0, (size - 1) as f64);
+    let lower = continuous.floor() as usize;
+    let upper = (lower + 1).min(size - 1);
+    (lower, upper, continuous - continuous.floor())
+}
+
+fn read_header_line<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() > 256 {
+            return Err(invalid_data("header line too long"));
+        }
+        line.push(byte[0]);
+    }
+    String::from_utf8(line)
+        .map(|line| line.trim().to_owned())
+        .map_err(|_| invalid_data("header is not valid text"))
+}
+
+fn read_channel<R: Read>(reader: &mut R, voxel_count: usize) -> io::Result<Vec<f32>> {
+    let length = voxel_count
+        .checked_mul(4)
+        .and_then(|length| u64::try_from(length).ok())
+        .ok_or_else(|| invalid_data("grid size too large"))?;
+
+    // The buffer only grows as data arrives, so a header claiming a huge grid can not exhaust the memory.
+    let mut bytes = Vec::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    if bytes.len() != voxel_count * 4 {
+        return Err(invalid_data("voxel data ends before the end of the grid"));
+    }
+
+    let values: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    if !values.iter().all(|value| *value >= 0.0) {
+        return Err(invalid_data("voxel values should not be negative or NaN"));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_round_trip() {
+        let grid = VoxelGrid::new(2, 1, 2, vec![0.0, 1.0, 2.0, 3.0])
+            .set_temperature(vec![1000.0, 1500.0, 2000.0, 2500.0]);
+
+        let mut bytes = Vec::new();
+        grid.write(&mut bytes).expect("Writing to a vector should succeed.");
+        assert!(bytes.starts_with(b"RVOL\n2 1 2\n2\n"));
+
+        let read = VoxelGrid::read(bytes.as_slice()).expect("The written grid should be valid.");
+        assert_eq!(read, grid);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(VoxelGrid::read(b"VOXL\n1 1 1\n1\n\0\0\0\0".as_slice()).is_err());
+        assert!(VoxelGrid::read(b"RVOL\n1 1\n1\n\0\0\0\0".as_slice()).is_err());
+        assert!(VoxelGrid::read(b"RVOL\n1 1 1\n3\n\0\0\0\0".as_slice()).is_err());
+        assert!(VoxelGrid::read(b"RVOL\n2 1 1\n1\n\0\0\0\0".as_slice()).is_err());
+
+        let invalid_kind = |bytes: &[u8]| VoxelGrid::read(bytes).map_err(|error| error.kind());
+        assert_eq!(invalid_kind(b"RVOL\n100000 100000 100000\n1\n"), Err(io::ErrorKind::InvalidData));
+        assert_eq!(invalid_kind(b"RVOL\n4611686018427387904 1 1\n1\n"), Err(io::ErrorKind::InvalidData));
+        for value in [-1.0f32, f32::NAN] {
+            let mut bytes = b"RVOL\n1 1 1\n1\n".to_vec();
+            bytes.extend_from_slice(&value.to_le_bytes());
+            assert_eq!(invalid_kind(&bytes), Err(io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn trilinear_interpolation() {
+        let grid = VoxelGrid::new(2, 1, 1, vec![0.0, 1.0]);
+
+        assert_eq!(grid.density_at(Vec3::new(0.0, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density_at(Vec3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density_at(Vec3::new(0.5, 0.5, 0.5)), 0.5);
+        assert_eq!(grid.density_at(Vec3::new(1.0, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.max_density(), 1.0);
+        assert_eq!(grid.temperature_at(Vec3::new(0.5, 0.5, 0.5)), 0.0);
+    }
+}